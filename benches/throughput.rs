@@ -0,0 +1,90 @@
+//! Throughput of the CRC and the PNG parser, the two loops every command runs over whole
+//! files. Run it with `cargo bench`.
+//!
+//! The crate has no dependencies, so this is a plain `harness = false` program rather
+//! than a criterion bench. Each result is kept under `target/bench/`, and the next run
+//! prints the change against it and flags a slowdown of more than `REGRESSION` as a
+//! regression.
+
+use pngme::crc::crc32;
+use pngme::png::{Png, PngBuilder};
+use std::fs;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// How many times each benchmark is timed. The fastest run is reported, as the one the
+/// rest of the system disturbed least.
+const SAMPLES: usize = 30;
+
+/// The slowdown against the previous run, as a fraction, above which a result is flagged.
+const REGRESSION: f64 = 0.10;
+
+fn main() {
+    let megabyte: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+    bench("crc32", megabyte.len(), || {
+        black_box(crc32(black_box(&megabyte)));
+    });
+
+    // A medium image: an `IHDR`, 256 `IDAT` chunks of 8 KiB and `IEND`.
+    let mut builder = PngBuilder::new(1024, 1024);
+    for i in 0..256 {
+        builder = builder.idat(vec![i as u8; 8 * 1024]);
+    }
+    let bytes = builder.build().as_bytes();
+    bench("png_try_from", bytes.len(), || {
+        black_box(Png::try_from(black_box(&bytes[..])).unwrap());
+    });
+}
+
+/// Times `run`, which processes `bytes` bytes, and prints its throughput next to the
+/// previous run's.
+fn bench(name: &str, bytes: usize, mut run: impl FnMut()) {
+    run();
+    let fastest = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    let throughput = bytes as f64 / (1024.0 * 1024.0) / fastest.as_secs_f64();
+
+    let baseline = baseline_dir().join(name);
+    let previous = fs::read_to_string(&baseline)
+        .ok()
+        .and_then(|text| text.trim().parse::<f64>().ok());
+    match previous {
+        Some(previous) => {
+            let change = throughput / previous - 1.0;
+            let flag = if change < -REGRESSION {
+                "  REGRESSED"
+            } else {
+                ""
+            };
+            println!(
+                "{:<14} {:>9.1} MiB/s  {:+.1}% against the last run{}",
+                name,
+                throughput,
+                change * 100.0,
+                flag
+            );
+        }
+        None => println!("{:<14} {:>9.1} MiB/s", name, throughput),
+    }
+
+    if let Err(e) = fs::create_dir_all(baseline_dir())
+        .and_then(|()| fs::write(&baseline, format!("{}\n", throughput)))
+    {
+        eprintln!("Could not save the {} result: {}", name, e);
+    }
+}
+
+/// Where results are kept between runs: `bench/` in the target directory.
+fn baseline_dir() -> PathBuf {
+    let target = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"));
+    target.join("bench")
+}
@@ -0,0 +1,1985 @@
+use crate::chunk_type::{ChunkType, PRESETS};
+use crate::rng::Rng;
+use crate::Result;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: pngme <COMMAND>
+
+Commands:
+  encode <FILE> <CHUNK_TYPE> <MESSAGE> (--in-place | --output <PATH>)
+                                                 Hide a message in a new chunk
+  decode <FILE> [CHUNK_TYPE]                     Print the message stored in a chunk
+  remove <FILE> <CHUNK_TYPE> (--in-place | --output <PATH>)
+                                                 Remove the first chunk of a type
+  rename-chunk <FILE> <OLD_TYPE> <NEW_TYPE> [--all] (--in-place | --output <PATH>)
+                                                 Change the type of the first such chunk
+  obfuscate <FILE> [--seed <N>] (--in-place | --output <PATH>)
+                                                 Give custom chunks random new types
+  print <FILE>... [--pretty] [--expand-idat]     List every chunk in each file
+  info <FILE>                                    Describe the image header
+  verify <FILE>                                  Check the signature, structure and CRCs
+  extract-icc <FILE> --output <PATH>             Save the embedded iCCP color profile
+  manifest <DIR> [--output <FILE>] [--recursive] Write a JSON manifest of every PNG
+  fix-lengths <FILE> (--in-place | --output <PATH>)
+                                                 Repair chunk lengths using the CRCs
+  fix-iend <FILE> (--in-place | --output <PATH>) Move IEND back to the end of the file
+  fix-crcs <FILE> (--in-place | --output <PATH>) Recompute the chunk CRCs that are wrong
+  normalize <FILE> (--in-place | --output <PATH>)
+                                                 Reorder chunks into the canonical order
+  scan <FILE>... [--jobs <N>]                    Look for hidden data in many files
+  has <FILE> <CHUNK_TYPE> [--verbose]            Exit 0 if the file has such a chunk
+  capacity <FILE> [--threshold <BYTES>]          Estimate how much data can be hidden
+  gen-type [--seed <N>]                          Print a random private chunk type
+  batch encode <CHUNK_TYPE> <MESSAGE> <FILE>... (--in-place | --output-dir <DIR>)
+                                                 Encode the same message into many files
+  batch remove <CHUNK_TYPE> <FILE>... (--in-place | --output-dir <DIR>)
+                                                 Remove a chunk from many files
+
+Commands that change files only write when told where to: --in-place overwrites <FILE>,
+and --output <PATH> (or encode's [OUTPUT] argument) writes the result to a new file.
+Batch takes --in-place, or --output-dir <DIR> or --rename-output <TEMPLATE> to write new
+files. With none of them they fail without writing anything.
+
+Print, decode, info, verify, has, capacity and scan also accept an http:// URL in place
+of <FILE> when pngme is built with the http feature; the commands that write files
+refuse URLs.
+
+Encode options:
+  --preset <NAME>        Use a named chunk type in place of <CHUNK_TYPE>
+  --type-from <LABEL>    Derive the same private chunk type from a label every time
+  --random-type          Pick a random private, safe-to-copy ancillary chunk type, like
+                         gen-type does, and print it so the message can be decoded later
+  --seed <N>             With --random-type, make the random choice repeatable
+  --lsb                  Hide the message in the low bits of the pixels rather than in a
+                         chunk, so it survives chunk stripping; takes no <CHUNK_TYPE>
+                         and needs a non-interlaced, non-indexed image with 8 or 16 bits
+                         per sample
+  --keyword <KEYWORD>    The keyword a zTXt message is stored under, compressed
+                         (default \"Comment\")
+  --from-png <PNG>       Store a whole image in the chunk in place of <MESSAGE>
+  --message-file <PATH>  Store the bytes of a file, unchanged, in place of <MESSAGE>
+  --pipe <COMMAND>       Run the chunk data through a command before storing it
+  --split-on <DELIMITER>
+                         Store each field of the message between delimiters in a chunk
+                         of its own, empty fields included
+  --max-size <BYTES>     Fail without writing if the data to store, after compression
+                         and --pipe, is larger than BYTES
+  --ascii-only           Refuse messages with characters other than printable ASCII
+  --append-only          Refuse to change the critical chunks (IHDR, PLTE, IDAT, IEND
+                         and others)
+  --append               Add the message to the end of the first chunk of the type,
+                         creating the chunk if there is none; not for zTXt or critical
+                         chunks
+Only one of --preset, --type-from, --random-type and --lsb may be given, and with any of
+them encode takes --output instead of [OUTPUT].
+
+Decode options:
+  --preset <NAME>, --type-from <LABEL>
+                         Name the chunk type the same way encode does
+  --count                Print how many chunks of the type there are, not their data
+  --all                  Print every chunk of the type in order, not only the first
+  --as-png               Check that the chunk holds a whole PNG image
+  --to-file <PATH>       Write the chunk data to a file instead of printing it
+  --as-rust, --as-c      Print the data as a byte array literal
+  --output-format <FORMAT>
+                         Print text (the default), raw, rust or c; raw writes the
+                         chunk data exactly as stored
+  --limit <N>            Output only the first N characters of a message, or N bytes
+                         of raw data
+  --no-newline, -n       Leave out the newline after the message
+  --quiet-not-found      Print nothing and exit 0 when the file has no chunk of the
+                         type, so scripts can tell absent chunks from real errors
+  --pipe <COMMAND>       Run the data through a command before writing it out; not
+                         with --as-rust, --as-c or --output-format
+  --lsb                  Read a message hidden by encode --lsb; the output options
+                         above apply to it, except --count and --all
+Without a chunk type, decode searches the ancillary chunks for text and prints the
+likely message; the output options above then need --lsb. Pipe commands, for encode and
+decode, are split on spaces and run directly, without a shell.
+
+Rename-chunk and obfuscate options:
+  --all       Rename every chunk of the old type, not only the first
+  --seed <N>  Make obfuscate's choice of new types repeatable
+Obfuscate prints each old custom chunk type next to its new one, so rename-chunk --all
+can put them back.
+
+Print options:
+  --pretty, --indent  Group chunks by category, summarising IDAT chunks on one line
+  --expand-idat       With --pretty, list each IDAT chunk on its own line
+  --table             Draw the chunk list as a bordered table with each chunk's
+                      property flags; plain columns when not writing to a terminal
+  --no-summary        Leave out the footer counting critical and ancillary chunks,
+                      the file size and any custom chunk types
+  --entropy           List each chunk's data entropy in bits per byte; values near
+                      8 in ancillary chunks suggest compressed or encrypted data
+  --allow-duplicate-signature
+                      Also list PNGs appended after the first image's IEND
+  --detect            Show Software and Comment text and the editors they name
+  --offset <BYTES>    Parse the PNG starting at this byte offset within the file
+  --scan              List the byte offsets of every PNG signature in the file
+  --strict, --error-on-warning
+                      Fail if parsing finds anything odd, such as trailing data or
+                      non-standard chunks, instead of only warning about it
+  --raw-text          With --detect, show text values without escaping control
+                      characters as \\xNN
+
+Batch options:
+  --jobs <N>   Number of files to process in parallel (default: one per CPU)
+  --quiet, -q  Do not show a progress bar on stderr
+  --in-place   Overwrite each input with its result
+  --rename-output <TEMPLATE>
+               Leave the inputs alone and write each result to a new file named by
+               the template, such as \"{stem}.tagged.{ext}\". {stem}, {ext}, {name}
+               and {dir} are the input's file stem, extension, file name and
+               directory; without {dir} the file goes next to its input
+  --output-dir <DIR>
+               Leave the inputs alone and write each result to a file of the same
+               name in DIR, creating it if needed
+
+Write options, for every command that takes --in-place:
+  --preserve-timestamps  Keep the input file's access and modification times
+  --verify-after-write   Re-read the written file and check its chunks and CRCs
+  --dedupe-header        Refuse to write a file with more than one IHDR, PLTE or IEND
+  --follow-symlinks      When the output is a symbolic link, write to the file it points
+                         to and keep the link (the default)
+  --no-follow-symlinks   When the output is a symbolic link, replace the link itself
+                         with the new file and leave its target untouched
+  --drop-trailing-data   Write even when the input has data after IEND, such as an
+                         appended archive, which the output loses; without it such
+                         files are refused
+
+Exit status:
+  0  Success
+  1  Any other failure, or has found no chunk of the type
+  2  Bad command line
+  3  File not found
+  4  The file is not a valid PNG
+  5  Chunk not found
+  6  A chunk's CRC does not match its contents";
+
+#[derive(Debug)]
+pub enum ArgsError {
+    MissingCommand,
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    UnexpectedArgument(String),
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    MissingOutput(&'static str),
+    MissingBatchOutput,
+    ConflictingFlags(&'static str, &'static str),
+    RequiresFlag(&'static str, &'static str),
+    InvalidValue { flag: &'static str, value: String },
+    UnknownPreset(String),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCommand => write!(f, "Missing command\n\n{}", USAGE),
+            Self::UnknownCommand(cmd) => write!(f, "Unknown command: {}\n\n{}", cmd, USAGE),
+            Self::MissingArgument(name) => write!(f, "Missing argument: <{}>", name),
+            Self::UnexpectedArgument(arg) => write!(f, "Unexpected argument: {}", arg),
+            Self::UnknownFlag(flag) => write!(f, "Unknown flag: {}", flag),
+            Self::MissingValue(flag) => write!(f, "Missing value for {}", flag),
+            Self::MissingOutput(command) => write!(
+                f,
+                "{} needs --in-place to overwrite <FILE> or --output <PATH> to write a new file",
+                command
+            ),
+            Self::MissingBatchOutput => write!(
+                f,
+                "batch needs --in-place to overwrite each <FILE>, or --output-dir <DIR> or \
+                 --rename-output <TEMPLATE> to write new files"
+            ),
+            Self::ConflictingFlags(first, second) => {
+                write!(f, "{} cannot be used with {}", first, second)
+            }
+            Self::RequiresFlag(flag, required) => {
+                write!(f, "{} can only be used with {}", flag, required)
+            }
+            Self::InvalidValue { flag, value } => {
+                write!(f, "Invalid value for {}: {}", flag, value)
+            }
+            Self::UnknownPreset(name) => {
+                let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+                write!(
+                    f,
+                    "Unknown preset: {} (valid presets: {})",
+                    name,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+#[derive(Debug)]
+pub enum PngMeArgs {
+    Encode(EncodeArgs),
+    Decode(DecodeArgs),
+    Remove(RemoveArgs),
+    RenameChunk(RenameChunkArgs),
+    Obfuscate(ObfuscateArgs),
+    Print(PrintArgs),
+    Capacity(CapacityArgs),
+    Has(HasArgs),
+    Batch(BatchArgs),
+    GenType(GenTypeArgs),
+    Info(InfoArgs),
+    Manifest(ManifestArgs),
+    FixLengths(RepairArgs),
+    FixCrcs(RepairArgs),
+    Normalize(RepairArgs),
+    Scan(ScanArgs),
+    FixIend(RepairArgs),
+    Verify(VerifyArgs),
+    ExtractIcc(ExtractIccArgs),
+    SelfTest,
+}
+
+#[derive(Debug)]
+pub struct EncodeArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub message: String,
+    /// Where to write the result; `file_path` is overwritten if this is `None`, which the
+    /// command line only allows with `--in-place`.
+    pub output_file: Option<PathBuf>,
+    /// Whether `chunk_type` was picked at random by `--random-type`, as a private type.
+    pub random_type: bool,
+    /// The label `chunk_type` was derived from with `--type-from`.
+    pub type_from: Option<String>,
+    /// The keyword for text chunks such as `zTXt`.
+    pub keyword: String,
+    /// A PNG file to store whole as the chunk data, in place of `message`.
+    pub from_png: Option<PathBuf>,
+    /// A file whose bytes, exactly as they are, are the message in place of `message`.
+    pub message_file: Option<PathBuf>,
+    /// Reject messages with anything other than printable ASCII.
+    pub ascii_only: bool,
+    /// Refuse to go ahead if anything but ancillary chunks would be added.
+    pub append_only: bool,
+    /// Add the message to the end of the first existing chunk of the type, if there is one.
+    pub append: bool,
+    /// A command to run the chunk data through before it is stored.
+    pub pipe: Option<String>,
+    /// Hide the message in the low bits of the image samples instead of a new chunk.
+    /// `chunk_type` is then `IDAT`, the chunk that gets rewritten.
+    pub lsb: bool,
+    /// Fail without writing if the data to store is larger than this many bytes.
+    pub max_size: Option<usize>,
+    /// Store each field of `message` between these delimiters in a chunk of its own.
+    pub split_on: Option<String>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub struct DecodeArgs {
+    pub file_path: PathBuf,
+    /// Without a chunk type, `decode` searches the ancillary chunks for a message.
+    pub chunk_type: Option<ChunkType>,
+    /// Treat the chunk data as an embedded PNG and warn if it does not parse as one.
+    pub as_png: bool,
+    /// Write the raw chunk data to this file instead of printing it.
+    pub to_file: Option<PathBuf>,
+    /// Only print how many chunks of the type there are.
+    pub count: bool,
+    /// Print the chunk data as a source code byte array instead of text.
+    pub literal: Option<ByteLiteral>,
+    /// Write the chunk data exactly as stored, without decoding it as text or adding a
+    /// newline.
+    pub raw: bool,
+    /// A command to run the chunk data through, printing its raw output.
+    pub pipe: Option<String>,
+    /// Read a message hidden in the image samples by `encode --lsb`.
+    pub lsb: bool,
+    /// Output at most this many characters of text, or bytes of raw data.
+    pub limit: Option<usize>,
+    /// Leave out the newline printed after the message.
+    pub no_newline: bool,
+    /// Print nothing and succeed when the file has no chunk of the type.
+    pub quiet_not_found: bool,
+    /// Print every chunk of the type, in file order, rather than only the first.
+    pub all: bool,
+}
+
+/// The languages `decode` can print chunk data as an array literal for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteLiteral {
+    Rust,
+    C,
+}
+
+#[derive(Debug)]
+pub struct RemoveArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    /// Where to write the result, as for `EncodeArgs::output_file`.
+    pub output_file: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub struct RenameChunkArgs {
+    pub file_path: PathBuf,
+    pub old_type: ChunkType,
+    pub new_type: ChunkType,
+    /// Rename every chunk of `old_type`, not just the first.
+    pub all: bool,
+    /// Where to write the result, as for `EncodeArgs::output_file`.
+    pub output_file: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub struct ObfuscateArgs {
+    pub file_path: PathBuf,
+    /// Makes the new types reproducible; the current time is used otherwise.
+    pub seed: Option<u64>,
+    /// Where to write the result, as for `EncodeArgs::output_file`.
+    pub output_file: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub struct PrintArgs {
+    /// With more than one file, each listing is printed under a header naming the file.
+    pub file_paths: Vec<PathBuf>,
+    /// Group chunks by category instead of printing a flat table.
+    pub pretty: bool,
+    /// List `IDAT` chunks one by one in the grouped view rather than summarising them.
+    pub expand_idat: bool,
+    /// Accept further complete PNGs appended after the first image's `IEND`.
+    pub allow_duplicate_signature: bool,
+    /// Show the `Software` and `Comment` text values and the editors they name.
+    pub detect: bool,
+    /// Start parsing at this byte offset, for a PNG embedded in a larger file.
+    pub offset: Option<usize>,
+    /// Only list the offsets where a PNG signature starts.
+    pub scan: bool,
+    /// Fail on any parse warning instead of only reporting it.
+    pub error_on_warning: bool,
+    /// Show text values as they are, without escaping control characters.
+    pub raw_text: bool,
+    /// Draw the flat listing as a bordered table. `main` turns this off when standard
+    /// output is not a terminal.
+    pub table: bool,
+    /// Leave out the footer counting the chunks and naming any custom chunk types.
+    pub no_summary: bool,
+    /// List the Shannon entropy of each chunk's data.
+    pub entropy: bool,
+}
+
+#[derive(Debug)]
+pub struct InfoArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct ExtractIccArgs {
+    pub file_path: PathBuf,
+    /// Where to write the inflated ICC profile.
+    pub output: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct ManifestArgs {
+    pub dir: PathBuf,
+    /// Where to write the manifest; it is printed if this is not given.
+    pub output: Option<PathBuf>,
+    /// Also include PNGs in subdirectories.
+    pub recursive: bool,
+}
+
+/// The arguments of the commands that repair or rewrite a whole file: `fix-lengths`,
+/// `fix-iend`, `fix-crcs` and `normalize`.
+#[derive(Debug)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    /// Where to write the result, as for `EncodeArgs::output_file`.
+    pub output_file: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub struct ScanArgs {
+    pub files: Vec<PathBuf>,
+    pub jobs: usize,
+}
+
+#[derive(Debug)]
+pub struct BatchArgs {
+    pub operation: BatchOperation,
+    pub files: Vec<PathBuf>,
+    pub jobs: usize,
+    /// Never show the progress bar.
+    pub quiet: bool,
+    /// Write each result to a file named by this template instead of changing the input.
+    pub rename_output: Option<String>,
+    /// Write each result to a file of the same name in this directory instead of changing
+    /// the input.
+    pub output_dir: Option<PathBuf>,
+    pub write: WriteOptions,
+}
+
+#[derive(Debug)]
+pub enum BatchOperation {
+    Encode {
+        chunk_type: ChunkType,
+        message: String,
+    },
+    Remove {
+        chunk_type: ChunkType,
+    },
+}
+
+#[derive(Debug)]
+pub struct HasArgs {
+    pub file_path: PathBuf,
+    pub chunk_type: ChunkType,
+    pub verbose: bool,
+}
+
+#[derive(Debug)]
+pub struct GenTypeArgs {
+    /// Makes the generated type reproducible; the current time is used otherwise.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct CapacityArgs {
+    pub file_path: PathBuf,
+    /// How many bytes may be added before `capacity` warns.
+    pub threshold: u64,
+}
+
+/// Flags shared by every command that rewrites a PNG file.
+#[derive(Debug, Default, Clone)]
+pub struct WriteOptions {
+    pub preserve_timestamps: bool,
+    /// Read the file back after writing it and check it holds exactly what was written.
+    pub verify_after_write: bool,
+    /// Refuse to write a PNG with more than one `IHDR`, `PLTE` or `IEND` chunk.
+    pub dedupe_header: bool,
+    /// When the output is a symbolic link, replace the link with a regular file instead
+    /// of writing through it to its target.
+    pub replace_symlinks: bool,
+    /// Write even though the input had data after `IEND`, which the output cannot keep.
+    pub drop_trailing_data: bool,
+}
+
+impl WriteOptions {
+    /// Applies `flag` if it is a write option, returning whether it was one.
+    fn parse_flag(&mut self, flag: &str) -> bool {
+        match flag {
+            "--preserve-timestamps" => self.preserve_timestamps = true,
+            "--verify-after-write" => self.verify_after_write = true,
+            "--dedupe-header" => self.dedupe_header = true,
+            "--no-follow-symlinks" => self.replace_symlinks = true,
+            "--follow-symlinks" => self.replace_symlinks = false,
+            "--drop-trailing-data" => self.drop_trailing_data = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl PngMeArgs {
+    /// Parses the command line, without the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<PngMeArgs> {
+        let mut args = ArgList::new(args);
+        let command = args.next_arg().ok_or(ArgsError::MissingCommand)?;
+        let parsed = match command.as_str() {
+            "encode" => PngMeArgs::Encode(EncodeArgs::parse(&mut args)?),
+            "decode" => PngMeArgs::Decode(DecodeArgs::parse(&mut args)?),
+            "remove" => PngMeArgs::Remove(RemoveArgs::parse(&mut args)?),
+            "rename-chunk" => PngMeArgs::RenameChunk(RenameChunkArgs::parse(&mut args)?),
+            "obfuscate" => PngMeArgs::Obfuscate(ObfuscateArgs::parse(&mut args)?),
+            "print" => PngMeArgs::Print(PrintArgs::parse(&mut args)?),
+            "capacity" => PngMeArgs::Capacity(CapacityArgs::parse(&mut args)?),
+            "has" => PngMeArgs::Has(HasArgs::parse(&mut args)?),
+            "batch" => PngMeArgs::Batch(BatchArgs::parse(&mut args)?),
+            "gen-type" => PngMeArgs::GenType(GenTypeArgs::parse(&mut args)?),
+            "info" => PngMeArgs::Info(InfoArgs::parse(&mut args)?),
+            "manifest" => PngMeArgs::Manifest(ManifestArgs::parse(&mut args)?),
+            "fix-lengths" => PngMeArgs::FixLengths(RepairArgs::parse(&mut args, "fix-lengths")?),
+            "fix-iend" => PngMeArgs::FixIend(RepairArgs::parse(&mut args, "fix-iend")?),
+            "fix-crcs" => PngMeArgs::FixCrcs(RepairArgs::parse(&mut args, "fix-crcs")?),
+            "normalize" => PngMeArgs::Normalize(RepairArgs::parse(&mut args, "normalize")?),
+            "scan" => PngMeArgs::Scan(ScanArgs::parse(&mut args)?),
+            "verify" => PngMeArgs::Verify(VerifyArgs::parse(&mut args)?),
+            "extract-icc" => PngMeArgs::ExtractIcc(ExtractIccArgs::parse(&mut args)?),
+            // A diagnostic, so it is left out of the usage text.
+            "selftest" => match args.next_arg() {
+                Some(arg) => return Err(Box::new(ArgsError::UnexpectedArgument(arg))),
+                None => PngMeArgs::SelfTest,
+            },
+            _ => return Err(Box::new(ArgsError::UnknownCommand(command))),
+        };
+        Ok(parsed)
+    }
+}
+
+impl EncodeArgs {
+    fn parse(args: &mut ArgList) -> Result<EncodeArgs> {
+        let mut positionals = Positionals::new();
+        let mut write = WriteOptions::default();
+        let mut preset = None;
+        let mut random_type = false;
+        let mut seed = None;
+        let mut type_from = None;
+        let mut keyword = String::from("Comment");
+        let mut from_png = None;
+        let mut message_file = None;
+        let mut ascii_only = false;
+        let mut append_only = false;
+        let mut append = false;
+        let mut pipe = None;
+        let mut lsb = false;
+        let mut max_size = None;
+        let mut split_on = None;
+        let mut in_place = false;
+        let mut output = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--preset" => preset = Some(args.value("--preset")?),
+                "--in-place" => in_place = true,
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                "--lsb" => lsb = true,
+                "--max-size" => max_size = Some(args.parsed_value("--max-size")?),
+                "--split-on" => split_on = Some(args.value("--split-on")?),
+                "--random-type" => random_type = true,
+                "--seed" => seed = Some(args.parsed_value("--seed")?),
+                "--type-from" => type_from = Some(args.value("--type-from")?),
+                "--keyword" => keyword = args.value("--keyword")?,
+                "--from-png" => from_png = Some(PathBuf::from(args.value("--from-png")?)),
+                "--message-file" => {
+                    message_file = Some(PathBuf::from(args.value("--message-file")?))
+                }
+                "--ascii-only" | "--strict-ascii-message" => ascii_only = true,
+                "--append-only" => append_only = true,
+                "--append" | "--append-to-type" => append = true,
+                "--pipe" => pipe = Some(args.value("--pipe")?),
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        // Each of these takes the place of <CHUNK_TYPE>, so at most one may be given.
+        let type_flags: Vec<&'static str> = [
+            ("--lsb", lsb),
+            ("--random-type", random_type),
+            ("--type-from", type_from.is_some()),
+            ("--preset", preset.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(flag, given)| given.then_some(flag))
+        .collect();
+        if let [first, second, ..] = type_flags[..] {
+            return Err(Box::new(ArgsError::ConflictingFlags(first, second)));
+        }
+        if seed.is_some() && !random_type {
+            return Err(Box::new(ArgsError::RequiresFlag("--seed", "--random-type")));
+        }
+
+        let file_path = positionals.required("FILE")?.into();
+        let chunk_type = if lsb {
+            ChunkType::from_str("IDAT")?
+        } else if random_type {
+            let mut rng = seed.map_or_else(Rng::from_time, Rng::from_seed);
+            ChunkType::random_private(&mut rng)
+        } else if let Some(label) = &type_from {
+            ChunkType::from_label(label)
+        } else {
+            chunk_type_arg(&mut positionals, preset)?
+        };
+        if from_png.is_some() && message_file.is_some() {
+            return Err(Box::new(ArgsError::ConflictingFlags(
+                "--message-file",
+                "--from-png",
+            )));
+        }
+        let message = if from_png.is_some() || message_file.is_some() {
+            String::new()
+        } else {
+            positionals.required("MESSAGE")?
+        };
+        if let Some(delimiter) = &split_on {
+            if delimiter.is_empty() {
+                return Err(Box::new(ArgsError::InvalidValue {
+                    flag: "--split-on",
+                    value: delimiter.clone(),
+                }));
+            }
+            for (conflicting, given) in [
+                ("--from-png", from_png.is_some()),
+                ("--message-file", message_file.is_some()),
+                ("--lsb", lsb),
+                ("--append", append),
+            ] {
+                if given {
+                    return Err(Box::new(ArgsError::ConflictingFlags(
+                        "--split-on",
+                        conflicting,
+                    )));
+                }
+            }
+        }
+        // Without <CHUNK_TYPE> a stray chunk type would shift into [OUTPUT], so the output
+        // has to be named with --output.
+        let output = match type_flags[..] {
+            [] => output.or_else(|| positionals.optional().map(PathBuf::from)),
+            _ => output,
+        };
+        let parsed = EncodeArgs {
+            file_path,
+            chunk_type,
+            message,
+            output_file: output_arg("encode", in_place, output)?,
+            random_type,
+            type_from,
+            keyword,
+            from_png,
+            message_file,
+            ascii_only,
+            append_only,
+            append,
+            pipe,
+            lsb,
+            max_size,
+            split_on,
+            write,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl DecodeArgs {
+    fn parse(args: &mut ArgList) -> Result<DecodeArgs> {
+        let mut positionals = Positionals::new();
+        let mut preset = None;
+        let mut type_from = None;
+        let mut as_png = false;
+        let mut to_file = None;
+        let mut count = false;
+        let mut literal = None;
+        let mut raw = false;
+        // The flag that chose how the data is printed, which --pipe cannot be combined with.
+        let mut format_flag = None;
+        let mut pipe = None;
+        let mut lsb = false;
+        let mut limit = None;
+        let mut no_newline = false;
+        let mut quiet_not_found = false;
+        let mut all = false;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--preset" => preset = Some(args.value("--preset")?),
+                "--limit" | "--limit-output" => limit = Some(args.parsed_value("--limit")?),
+                "--no-newline" | "-n" => no_newline = true,
+                "--quiet-not-found" => quiet_not_found = true,
+                "--all" => all = true,
+                "--type-from" => type_from = Some(args.value("--type-from")?),
+                "--as-png" => as_png = true,
+                "--to-file" => to_file = Some(PathBuf::from(args.value("--to-file")?)),
+                "--count" => count = true,
+                "--as-rust" => {
+                    literal = Some(ByteLiteral::Rust);
+                    format_flag = Some("--as-rust");
+                }
+                "--as-c" => {
+                    literal = Some(ByteLiteral::C);
+                    format_flag = Some("--as-c");
+                }
+                "--output-format" => {
+                    (literal, raw) = match args.value("--output-format")?.as_str() {
+                        "text" => (None, false),
+                        "raw" => (None, true),
+                        "rust" => (Some(ByteLiteral::Rust), false),
+                        "c" => (Some(ByteLiteral::C), false),
+                        other => {
+                            return Err(Box::new(ArgsError::InvalidValue {
+                                flag: "--output-format",
+                                value: other.to_string(),
+                            }))
+                        }
+                    };
+                    format_flag = Some("--output-format");
+                }
+                "--lsb" => lsb = true,
+                "--pipe" => pipe = Some(args.value("--pipe")?),
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let file_path = positionals.required("FILE")?.into();
+        let chunk_type = match (preset, type_from) {
+            (Some(_), Some(_)) => {
+                return Err(Box::new(ArgsError::ConflictingFlags(
+                    "--type-from",
+                    "--preset",
+                )))
+            }
+            (None, Some(label)) => Some(ChunkType::from_label(&label)),
+            (Some(preset), None) => Some(chunk_type_arg(&mut positionals, Some(preset))?),
+            (None, None) => positionals
+                .optional()
+                .map(|chunk_type| ChunkType::from_str(&chunk_type))
+                .transpose()?,
+        };
+        // The search prints its findings as they are, so the output options need a chunk
+        // type.
+        let output_options = as_png
+            || to_file.is_some()
+            || count
+            || literal.is_some()
+            || raw
+            || pipe.is_some()
+            || limit.is_some()
+            || no_newline
+            || quiet_not_found
+            || all;
+        if chunk_type.is_none() && !lsb && output_options {
+            return Err(Box::new(ArgsError::MissingArgument("CHUNK_TYPE")));
+        }
+        if let (Some(_), Some(flag)) = (&pipe, format_flag) {
+            return Err(Box::new(ArgsError::ConflictingFlags("--pipe", flag)));
+        }
+        // There is only one LSB message, so there is nothing to count.
+        if lsb && count {
+            return Err(Box::new(ArgsError::ConflictingFlags("--lsb", "--count")));
+        }
+        if all {
+            for (conflicting, given) in [
+                ("--count", count),
+                ("--to-file", to_file.is_some()),
+                ("--lsb", lsb),
+            ] {
+                if given {
+                    return Err(Box::new(ArgsError::ConflictingFlags("--all", conflicting)));
+                }
+            }
+        }
+
+        let parsed = DecodeArgs {
+            file_path,
+            chunk_type,
+            as_png,
+            to_file,
+            count,
+            literal,
+            raw,
+            pipe,
+            lsb,
+            limit,
+            no_newline,
+            quiet_not_found,
+            all,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl RemoveArgs {
+    fn parse(args: &mut ArgList) -> Result<RemoveArgs> {
+        let mut positionals = Positionals::new();
+        let mut write = WriteOptions::default();
+        let mut in_place = false;
+        let mut output = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--in-place" => in_place = true,
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = RemoveArgs {
+            file_path: positionals.required("FILE")?.into(),
+            chunk_type: ChunkType::from_str(&positionals.required("CHUNK_TYPE")?)?,
+            output_file: output_arg("remove", in_place, output)?,
+            write,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl RenameChunkArgs {
+    fn parse(args: &mut ArgList) -> Result<RenameChunkArgs> {
+        let mut positionals = Positionals::new();
+        let mut write = WriteOptions::default();
+        let mut in_place = false;
+        let mut output = None;
+        let mut all = false;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--in-place" => in_place = true,
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                "--all" => all = true,
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = RenameChunkArgs {
+            file_path: positionals.required("FILE")?.into(),
+            old_type: ChunkType::from_str(&positionals.required("OLD_TYPE")?)?,
+            new_type: ChunkType::from_str(&positionals.required("NEW_TYPE")?)?,
+            all,
+            output_file: output_arg("rename-chunk", in_place, output)?,
+            write,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl ObfuscateArgs {
+    fn parse(args: &mut ArgList) -> Result<ObfuscateArgs> {
+        let mut positionals = Positionals::new();
+        let mut write = WriteOptions::default();
+        let mut seed = None;
+        let mut in_place = false;
+        let mut output = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--seed" => seed = Some(args.parsed_value("--seed")?),
+                "--in-place" => in_place = true,
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = ObfuscateArgs {
+            file_path: positionals.required("FILE")?.into(),
+            seed,
+            output_file: output_arg("obfuscate", in_place, output)?,
+            write,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl PrintArgs {
+    fn parse(args: &mut ArgList) -> Result<PrintArgs> {
+        let mut positionals = Positionals::new();
+        let mut pretty = false;
+        let mut expand_idat = false;
+        let mut allow_duplicate_signature = false;
+        let mut detect = false;
+        let mut offset = None;
+        let mut scan = false;
+        let mut error_on_warning = false;
+        let mut raw_text = false;
+        let mut table = false;
+        let mut no_summary = false;
+        let mut entropy = false;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--pretty" | "--indent" => pretty = true,
+                "--expand-idat" => expand_idat = true,
+                "--allow-duplicate-signature" => allow_duplicate_signature = true,
+                "--detect" => detect = true,
+                "--offset" => offset = Some(args.parsed_value("--offset")?),
+                "--scan" => scan = true,
+                "--strict" | "--error-on-warning" => error_on_warning = true,
+                "--raw-text" => raw_text = true,
+                "--table" => table = true,
+                "--no-summary" => no_summary = true,
+                "--entropy" => entropy = true,
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let file_paths: Vec<PathBuf> = positionals.rest().into_iter().map(PathBuf::from).collect();
+        if file_paths.is_empty() {
+            return Err(Box::new(ArgsError::MissingArgument("FILE")));
+        }
+
+        Ok(PrintArgs {
+            file_paths,
+            // Expanding IDAT only means something in the grouped view.
+            pretty: pretty || expand_idat,
+            expand_idat,
+            allow_duplicate_signature,
+            detect,
+            offset,
+            scan,
+            error_on_warning,
+            raw_text,
+            table,
+            no_summary,
+            entropy,
+        })
+    }
+}
+
+impl InfoArgs {
+    fn parse(args: &mut ArgList) -> Result<InfoArgs> {
+        let mut positionals = Positionals::new();
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = InfoArgs {
+            file_path: positionals.required("FILE")?.into(),
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl VerifyArgs {
+    fn parse(args: &mut ArgList) -> Result<VerifyArgs> {
+        let mut positionals = Positionals::new();
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = VerifyArgs {
+            file_path: positionals.required("FILE")?.into(),
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl ExtractIccArgs {
+    fn parse(args: &mut ArgList) -> Result<ExtractIccArgs> {
+        let mut positionals = Positionals::new();
+        let mut output = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = ExtractIccArgs {
+            file_path: positionals.required("FILE")?.into(),
+            output: output.ok_or(ArgsError::MissingArgument("--output"))?,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl ManifestArgs {
+    fn parse(args: &mut ArgList) -> Result<ManifestArgs> {
+        let mut positionals = Positionals::new();
+        let mut output = None;
+        let mut recursive = false;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                "--recursive" | "-r" => recursive = true,
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = ManifestArgs {
+            dir: positionals.required("DIR")?.into(),
+            output,
+            recursive,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl RepairArgs {
+    /// Parses the arguments of `command`, one of the repair commands.
+    fn parse(args: &mut ArgList, command: &'static str) -> Result<RepairArgs> {
+        let mut positionals = Positionals::new();
+        let mut write = WriteOptions::default();
+        let mut in_place = false;
+        let mut output = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--in-place" => in_place = true,
+                "--output" | "-o" => output = Some(PathBuf::from(args.value("--output")?)),
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = RepairArgs {
+            file_path: positionals.required("FILE")?.into(),
+            output_file: output_arg(command, in_place, output)?,
+            write,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl ScanArgs {
+    fn parse(args: &mut ArgList) -> Result<ScanArgs> {
+        let mut positionals = Positionals::new();
+        let mut jobs = crate::batch::default_jobs();
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--jobs" => jobs = args.parsed_value("--jobs")?,
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let files: Vec<PathBuf> = positionals.rest().into_iter().map(PathBuf::from).collect();
+        if files.is_empty() {
+            return Err(Box::new(ArgsError::MissingArgument("FILE")));
+        }
+        Ok(ScanArgs { files, jobs })
+    }
+}
+
+impl HasArgs {
+    fn parse(args: &mut ArgList) -> Result<HasArgs> {
+        let mut positionals = Positionals::new();
+        let mut verbose = false;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--verbose" | "-v" => verbose = true,
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = HasArgs {
+            file_path: positionals.required("FILE")?.into(),
+            chunk_type: ChunkType::from_str(&positionals.required("CHUNK_TYPE")?)?,
+            verbose,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl GenTypeArgs {
+    fn parse(args: &mut ArgList) -> Result<GenTypeArgs> {
+        let mut positionals = Positionals::new();
+        let mut seed = None;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--seed" => seed = Some(args.parsed_value("--seed")?),
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        positionals.finish()?;
+        Ok(GenTypeArgs { seed })
+    }
+}
+
+impl CapacityArgs {
+    /// Warn once more than 10 MiB would be added to the file.
+    pub const DEFAULT_THRESHOLD: u64 = 10 * 1024 * 1024;
+
+    fn parse(args: &mut ArgList) -> Result<CapacityArgs> {
+        let mut positionals = Positionals::new();
+        let mut threshold = Self::DEFAULT_THRESHOLD;
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--threshold" => threshold = args.parsed_value("--threshold")?,
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let parsed = CapacityArgs {
+            file_path: positionals.required("FILE")?.into(),
+            threshold,
+        };
+        positionals.finish()?;
+        Ok(parsed)
+    }
+}
+
+impl BatchArgs {
+    fn parse(args: &mut ArgList) -> Result<BatchArgs> {
+        let operation = args
+            .next_arg()
+            .ok_or(ArgsError::MissingArgument("OPERATION"))?;
+        let mut positionals = Positionals::new();
+        let mut jobs = crate::batch::default_jobs();
+        let mut quiet = false;
+        let mut rename_output = None;
+        let mut output_dir = None;
+        let mut in_place = false;
+        let mut write = WriteOptions::default();
+        while let Some(arg) = args.next_arg() {
+            match arg.as_str() {
+                "--in-place" => in_place = true,
+                "--jobs" => jobs = args.parsed_value("--jobs")?,
+                "--quiet" | "-q" => quiet = true,
+                "--rename-output" => rename_output = Some(args.value("--rename-output")?),
+                "--output-dir" => output_dir = Some(PathBuf::from(args.value("--output-dir")?)),
+                flag if write.parse_flag(flag) => {}
+                flag if is_flag(flag) => return Err(Box::new(ArgsError::UnknownFlag(arg))),
+                _ => positionals.push(arg),
+            }
+        }
+
+        let operation = match operation.as_str() {
+            "encode" => BatchOperation::Encode {
+                chunk_type: ChunkType::from_str(&positionals.required("CHUNK_TYPE")?)?,
+                message: positionals.required("MESSAGE")?,
+            },
+            "remove" => BatchOperation::Remove {
+                chunk_type: ChunkType::from_str(&positionals.required("CHUNK_TYPE")?)?,
+            },
+            _ => return Err(Box::new(ArgsError::UnknownCommand(operation))),
+        };
+        let files: Vec<PathBuf> = positionals.rest().into_iter().map(PathBuf::from).collect();
+        if files.is_empty() {
+            return Err(Box::new(ArgsError::MissingArgument("FILE")));
+        }
+        match (in_place, &rename_output, &output_dir) {
+            (_, Some(_), Some(_)) => {
+                return Err(Box::new(ArgsError::ConflictingFlags(
+                    "--output-dir",
+                    "--rename-output",
+                )))
+            }
+            (true, Some(_), None) => {
+                return Err(Box::new(ArgsError::ConflictingFlags(
+                    "--in-place",
+                    "--rename-output",
+                )))
+            }
+            (true, None, Some(_)) => {
+                return Err(Box::new(ArgsError::ConflictingFlags(
+                    "--in-place",
+                    "--output-dir",
+                )))
+            }
+            (false, None, None) => return Err(Box::new(ArgsError::MissingBatchOutput)),
+            _ => {}
+        }
+
+        Ok(BatchArgs {
+            operation,
+            files,
+            jobs,
+            quiet,
+            rename_output,
+            output_dir,
+            write,
+        })
+    }
+}
+
+/// The chunk type named by `--preset`, or else the next `<CHUNK_TYPE>` positional.
+fn chunk_type_arg(positionals: &mut Positionals, preset: Option<String>) -> Result<ChunkType> {
+    match preset {
+        Some(name) => Ok(ChunkType::from_preset(&name).ok_or(ArgsError::UnknownPreset(name))?),
+        None => ChunkType::from_str(&positionals.required("CHUNK_TYPE")?),
+    }
+}
+
+/// Where a command that edits a file writes its result: `None` to overwrite the input,
+/// which takes `--in-place`, or the `--output` path. There is deliberately no default, so
+/// a file is never overwritten by accident.
+fn output_arg(
+    command: &'static str,
+    in_place: bool,
+    output: Option<PathBuf>,
+) -> Result<Option<PathBuf>> {
+    match (in_place, output) {
+        (true, Some(_)) => Err(Box::new(ArgsError::ConflictingFlags(
+            "--in-place",
+            "--output",
+        ))),
+        (true, None) => Ok(None),
+        (false, Some(output)) => Ok(Some(output)),
+        (false, None) => Err(Box::new(ArgsError::MissingOutput(command))),
+    }
+}
+
+/// Anything starting with `-` other than a lone `-` is treated as a flag.
+fn is_flag(arg: &str) -> bool {
+    arg.starts_with('-') && arg != "-"
+}
+
+/// The remaining command line arguments, consumed front to back.
+struct ArgList {
+    args: std::vec::IntoIter<String>,
+}
+
+impl ArgList {
+    fn new<I: IntoIterator<Item = String>>(args: I) -> ArgList {
+        ArgList {
+            args: args.into_iter().collect::<Vec<_>>().into_iter(),
+        }
+    }
+
+    fn next_arg(&mut self) -> Option<String> {
+        self.args.next()
+    }
+
+    /// The value following a flag such as `--output <PATH>`.
+    fn value(&mut self, flag: &'static str) -> Result<String> {
+        Ok(self.args.next().ok_or(ArgsError::MissingValue(flag))?)
+    }
+
+    /// The value following a flag, parsed into `T`.
+    fn parsed_value<T: FromStr>(&mut self, flag: &'static str) -> Result<T> {
+        let value = self.value(flag)?;
+        value
+            .parse()
+            .map_err(|_| Box::new(ArgsError::InvalidValue { flag, value }).into())
+    }
+}
+
+/// Positional arguments collected while scanning for flags, taken in order.
+struct Positionals {
+    values: std::collections::VecDeque<String>,
+}
+
+impl Positionals {
+    fn new() -> Positionals {
+        Positionals {
+            values: std::collections::VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, value: String) {
+        self.values.push_back(value);
+    }
+
+    fn required(&mut self, name: &'static str) -> Result<String> {
+        Ok(self
+            .values
+            .pop_front()
+            .ok_or(ArgsError::MissingArgument(name))?)
+    }
+
+    fn optional(&mut self) -> Option<String> {
+        self.values.pop_front()
+    }
+
+    /// Takes every positional argument not consumed yet.
+    fn rest(&mut self) -> Vec<String> {
+        self.values.drain(..).collect()
+    }
+
+    /// Errors if any positional argument was left unused.
+    fn finish(&mut self) -> Result<()> {
+        match self.values.pop_front() {
+            Some(extra) => Err(Box::new(ArgsError::UnexpectedArgument(extra))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<PngMeArgs> {
+        PngMeArgs::parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_encode() {
+        match parse("encode image.png ruSt hello out.png").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.file_path, PathBuf::from("image.png"));
+                assert_eq!(args.chunk_type.to_string(), "ruSt");
+                assert_eq!(args.message, "hello");
+                assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+                assert!(!args.write.preserve_timestamps);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_split_on_and_all() {
+        match parse("encode image.png ruSt a|b|c --in-place --split-on |").unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.split_on.as_deref(), Some("|")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("encode image.png ruSt abc --in-place --split-on").is_err());
+        assert!(parse("encode image.png ruSt a|b --in-place --split-on | --append").is_err());
+        assert!(parse("encode image.png a|b --in-place --split-on | --lsb").is_err());
+
+        match parse("decode image.png ruSt --all").unwrap() {
+            PngMeArgs::Decode(args) => assert!(args.all),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("decode image.png ruSt --all --count").is_err());
+        assert!(parse("decode image.png ruSt --all --to-file out.bin").is_err());
+    }
+
+    #[test]
+    fn test_parse_message_file_and_output_format() {
+        match parse("encode image.png ruSt --message-file payload.bin --in-place").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.message_file, Some(PathBuf::from("payload.bin")));
+                assert_eq!(args.message, "");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(
+            parse("encode image.png ruSt --message-file a.bin --from-png b.png --in-place")
+                .is_err()
+        );
+
+        for (format, literal, raw) in [
+            ("text", None, false),
+            ("raw", None, true),
+            ("rust", Some(ByteLiteral::Rust), false),
+            ("c", Some(ByteLiteral::C), false),
+        ] {
+            match parse(&format!("decode image.png ruSt --output-format {}", format)).unwrap() {
+                PngMeArgs::Decode(args) => assert_eq!((args.literal, args.raw), (literal, raw)),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert!(parse("decode image.png ruSt --output-format hex").is_err());
+        assert!(parse("decode image.png --output-format raw").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_size() {
+        match parse("encode image.png ruSt hello --in-place --max-size 1024").unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.max_size, Some(1024)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.max_size, None),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("encode image.png ruSt hello --in-place --max-size big").is_err());
+    }
+
+    #[test]
+    fn test_parse_rename_chunk() {
+        match parse("rename-chunk image.png ruSt teSt --in-place").unwrap() {
+            PngMeArgs::RenameChunk(args) => {
+                assert_eq!(args.old_type.to_string(), "ruSt");
+                assert_eq!(args.new_type.to_string(), "teSt");
+                assert_eq!(args.output_file, None);
+                assert!(!args.all);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("rename-chunk image.png ruSt teSt --all -o out.png").unwrap() {
+            PngMeArgs::RenameChunk(args) => assert!(args.all),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("rename-chunk image.png ruSt teSt").is_err());
+        assert!(parse("rename-chunk image.png ruSt --in-place").is_err());
+        assert!(parse("rename-chunk image.png ruSt te5t --in-place").is_err());
+    }
+
+    #[test]
+    fn test_parse_obfuscate() {
+        match parse("obfuscate image.png --seed 7 -o out.png").unwrap() {
+            PngMeArgs::Obfuscate(args) => {
+                assert_eq!(args.file_path, PathBuf::from("image.png"));
+                assert_eq!(args.seed, Some(7));
+                assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("obfuscate image.png").is_err());
+        assert!(parse("obfuscate image.png --in-place --seed x").is_err());
+    }
+
+    #[test]
+    fn test_parse_output_contract() {
+        match parse("encode image.png ruSt hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.output_file, None),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --output out.png").unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.output_file, Some(PathBuf::from("out.png"))),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("remove image.png ruSt -o out.png").unwrap() {
+            PngMeArgs::Remove(args) => assert_eq!(args.output_file, Some(PathBuf::from("out.png"))),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let err = parse("encode image.png ruSt hello").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArgsError::MissingOutput("encode"))
+        ));
+        assert!(err.to_string().contains("--in-place"));
+        assert!(parse("remove image.png ruSt").is_err());
+        assert!(parse("remove image.png ruSt --in-place --output out.png").is_err());
+        assert!(parse("encode image.png ruSt hello out.png --output other.png").is_err());
+
+        match parse("fix-lengths image.png -o fixed.png").unwrap() {
+            PngMeArgs::FixLengths(args) => {
+                assert_eq!(args.output_file, Some(PathBuf::from("fixed.png")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        for command in ["fix-lengths", "fix-iend", "fix-crcs", "normalize"] {
+            let err = parse(&format!("{} image.png", command)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(ArgsError::MissingOutput(name)) if *name == command
+            ));
+        }
+
+        let err = parse("batch remove ruSt a.png b.png").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArgsError::MissingBatchOutput)
+        ));
+        assert!(parse("batch remove ruSt a.png --in-place --output-dir out").is_err());
+        assert!(parse("batch remove ruSt a.png --in-place --rename-output {name}.out").is_err());
+    }
+
+    #[test]
+    fn test_parse_write_options() {
+        match parse("remove image.png ruSt --in-place --preserve-timestamps").unwrap() {
+            PngMeArgs::Remove(args) => assert!(args.write.preserve_timestamps),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --in-place --verify-after-write").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert!(args.write.verify_after_write && !args.write.preserve_timestamps)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("fix-crcs image.png --in-place --dedupe-header").unwrap() {
+            PngMeArgs::FixCrcs(args) => {
+                assert!(args.write.dedupe_header && !args.write.replace_symlinks)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("fix-iend image.png -o out.png --drop-trailing-data").unwrap() {
+            PngMeArgs::FixIend(args) => assert!(args.write.drop_trailing_data),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("normalize image.png --in-place --no-follow-symlinks").unwrap() {
+            PngMeArgs::Normalize(args) => assert!(args.write.replace_symlinks),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("normalize image.png --in-place --no-follow-symlinks --follow-symlinks")
+            .unwrap()
+        {
+            PngMeArgs::Normalize(args) => assert!(!args.write.replace_symlinks),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("decode image.png ruSt --preserve-timestamps").is_err());
+    }
+
+    #[test]
+    fn test_parse_decode() {
+        match parse("decode image.png ruSt").unwrap() {
+            PngMeArgs::Decode(args) => {
+                assert_eq!(args.file_path, PathBuf::from("image.png"));
+                assert_eq!(args.chunk_type.unwrap().to_string(), "ruSt");
+                assert!(!args.count);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png").unwrap() {
+            PngMeArgs::Decode(args) => assert!(args.chunk_type.is_none()),
+            other => panic!("unexpected {:?}", other),
+        }
+        for flag in [
+            "--output-format raw",
+            "--count",
+            "--limit 2",
+            "-n",
+            "--quiet-not-found",
+            "--all",
+        ] {
+            let err = parse(&format!("decode image.png {}", flag)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ArgsError>(),
+                Some(ArgsError::MissingArgument("CHUNK_TYPE"))
+            ));
+        }
+        assert!(parse("decode image.png --lsb --limit 2 -n").is_ok());
+        match parse("decode image.png ruSt --count").unwrap() {
+            PngMeArgs::Decode(args) => assert!(args.count),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png ruSt --as-c").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.literal, Some(ByteLiteral::C)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_capacity() {
+        match parse("capacity image.png --threshold 2048").unwrap() {
+            PngMeArgs::Capacity(args) => assert_eq!(args.threshold, 2048),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("capacity image.png").unwrap() {
+            PngMeArgs::Capacity(args) => {
+                assert_eq!(args.threshold, CapacityArgs::DEFAULT_THRESHOLD)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("capacity image.png --threshold lots").is_err());
+        assert!(parse("capacity image.png --threshold").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        match parse("batch encode ruSt hello a.png b.png --jobs 3 --in-place").unwrap() {
+            PngMeArgs::Batch(args) => {
+                assert!(matches!(args.operation, BatchOperation::Encode { .. }));
+                assert_eq!(
+                    args.files,
+                    vec![PathBuf::from("a.png"), PathBuf::from("b.png")]
+                );
+                assert_eq!(args.jobs, 3);
+                assert!(!args.quiet);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("batch remove ruSt a.png -q --in-place").unwrap() {
+            PngMeArgs::Batch(args) => assert!(args.quiet && args.rename_output.is_none()),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("batch encode ruSt hello a.png --rename-output {stem}.tagged.{ext}").unwrap() {
+            PngMeArgs::Batch(args) => {
+                assert_eq!(args.rename_output.as_deref(), Some("{stem}.tagged.{ext}"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("batch remove ruSt a.png b.png --output-dir processed").unwrap() {
+            PngMeArgs::Batch(args) => {
+                assert_eq!(args.output_dir, Some(PathBuf::from("processed")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("batch remove ruSt a.png --output-dir out --rename-output {name}").is_err());
+        assert!(parse("batch remove ruSt").is_err());
+        assert!(parse("batch explode ruSt a.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_preset() {
+        match parse("encode image.png --preset secret hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.chunk_type.to_string(), "ruSt");
+                assert_eq!(args.message, "hello");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png --preset note").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.chunk_type.unwrap().to_string(), "noTe"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let err = parse("decode image.png --preset nope").unwrap_err();
+        assert!(err.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_parse_random_type() {
+        let chunk_type = |line| match parse(line).unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert!(args.random_type);
+                assert_eq!(args.message, "hello");
+                args.chunk_type
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        let first = chunk_type("encode image.png hello --random-type --seed 5 --in-place");
+        let second = chunk_type("encode image.png --seed 5 --random-type hello --in-place");
+        assert_eq!(first, second);
+        assert!(first.is_valid());
+        assert!(!first.is_critical());
+        assert!(!first.is_public());
+        assert!(first.is_safe_to_copy());
+    }
+
+    #[test]
+    fn test_parse_type_from() {
+        let expected = ChunkType::from_label("project-x");
+        match parse("encode image.png --type-from project-x hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.chunk_type, expected);
+                assert_eq!(args.type_from.as_deref(), Some("project-x"));
+                assert_eq!(args.message, "hello");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png --type-from project-x").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.chunk_type, Some(expected)),
+            other => panic!("unexpected {:?}", other),
+        }
+        let err = parse("decode image.png --type-from project-x --preset note").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArgsError::ConflictingFlags("--type-from", "--preset"))
+        ));
+        assert!(parse("encode image.png hello --type-from").is_err());
+    }
+
+    #[test]
+    fn test_parse_encode_chunk_type_flags() {
+        let conflict = |line: &str| match parse(line).unwrap_err().downcast_ref() {
+            Some(ArgsError::ConflictingFlags(first, second)) => (*first, *second),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            conflict("encode image.png hello --random-type --preset note --in-place"),
+            ("--random-type", "--preset")
+        );
+        assert_eq!(
+            conflict("encode image.png hello --type-from x --preset note --in-place"),
+            ("--type-from", "--preset")
+        );
+        assert_eq!(
+            conflict("encode image.png hello --lsb --random-type --in-place"),
+            ("--lsb", "--random-type")
+        );
+        assert_eq!(
+            conflict("encode image.png hello --lsb --type-from x --in-place"),
+            ("--lsb", "--type-from")
+        );
+
+        let err = parse("encode image.png ruSt hello --seed 5 --in-place").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ArgsError::RequiresFlag("--seed", "--random-type"))
+        ));
+
+        // A chunk type left in by mistake must not become the output path.
+        for flag in ["--random-type", "--type-from x", "--preset note", "--lsb"] {
+            let line = format!("encode image.png ruSt hello {} --in-place", flag);
+            let err = parse(&line).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(ArgsError::UnexpectedArgument(arg)) if arg == "hello"
+            ));
+            match parse(&format!("encode image.png hello {} -o out.png", flag)).unwrap() {
+                PngMeArgs::Encode(args) => {
+                    assert_eq!(args.message, "hello");
+                    assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_decode_limit() {
+        match parse("decode image.png ruSt --limit 5").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.limit, Some(5)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png ruSt --limit-output 7 --as-png").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.limit, Some(7)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("decode image.png ruSt --limit lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_quiet_not_found() {
+        match parse("decode image.png ruSt --quiet-not-found").unwrap() {
+            PngMeArgs::Decode(args) => assert!(args.quiet_not_found),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png ruSt").unwrap() {
+            PngMeArgs::Decode(args) => assert!(!args.quiet_not_found),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_no_newline() {
+        for command in [
+            "decode image.png ruSt --no-newline",
+            "decode image.png -n ruSt",
+        ] {
+            match parse(command).unwrap() {
+                PngMeArgs::Decode(args) => assert!(args.no_newline),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        match parse("decode image.png ruSt").unwrap() {
+            PngMeArgs::Decode(args) => assert!(!args.no_newline),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_lsb() {
+        match parse("encode image.png --lsb hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert!(args.lsb);
+                assert_eq!(args.chunk_type.to_string(), "IDAT");
+                assert_eq!(args.message, "hello");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png --lsb").unwrap() {
+            PngMeArgs::Decode(args) => assert!(args.lsb && args.chunk_type.is_none()),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png --lsb --to-file out.bin").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.to_file, Some(PathBuf::from("out.bin"))),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("decode image.png --lsb --count").is_err());
+    }
+
+    #[test]
+    fn test_parse_pipe() {
+        let args = [
+            "encode",
+            "image.png",
+            "ruSt",
+            "hello",
+            "--pipe",
+            "tr a-z A-Z",
+            "--in-place",
+        ];
+        match PngMeArgs::parse(args.iter().map(|s| s.to_string())).unwrap() {
+            PngMeArgs::Encode(args) => assert_eq!(args.pipe.as_deref(), Some("tr a-z A-Z")),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode image.png ruSt --pipe gunzip").unwrap() {
+            PngMeArgs::Decode(args) => assert_eq!(args.pipe.as_deref(), Some("gunzip")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("decode image.png --pipe gunzip").is_err());
+        for flag in ["--as-rust", "--as-c", "--output-format raw"] {
+            let err = parse(&format!("decode image.png ruSt --pipe gunzip {}", flag)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(ArgsError::ConflictingFlags("--pipe", _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_ascii_only() {
+        match parse("encode image.png ruSt hello --in-place --ascii-only").unwrap() {
+            PngMeArgs::Encode(args) => assert!(args.ascii_only),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --in-place --append-only").unwrap() {
+            PngMeArgs::Encode(args) => assert!(args.append_only && !args.ascii_only),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --in-place").unwrap() {
+            PngMeArgs::Encode(args) => assert!(!args.ascii_only && !args.append_only),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("encode image.png ruSt hello --in-place --append").unwrap() {
+            PngMeArgs::Encode(args) => assert!(args.append && !args.append_only),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_embedded_png() {
+        match parse("encode carrier.png ruSt --from-png inner.png out.png").unwrap() {
+            PngMeArgs::Encode(args) => {
+                assert_eq!(args.from_png, Some(PathBuf::from("inner.png")));
+                assert_eq!(args.output_file, Some(PathBuf::from("out.png")));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("decode carrier.png ruSt --as-png --to-file inner.png").unwrap() {
+            PngMeArgs::Decode(args) => {
+                assert!(args.as_png);
+                assert_eq!(args.to_file, Some(PathBuf::from("inner.png")));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_print() {
+        match parse("print image.png").unwrap() {
+            PngMeArgs::Print(args) => {
+                assert!(!args.pretty && !args.expand_idat && !args.no_summary && !args.entropy)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --no-summary --entropy").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.no_summary && args.entropy),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print a.png b.png").unwrap() {
+            PngMeArgs::Print(args) => {
+                assert_eq!(
+                    args.file_paths,
+                    [PathBuf::from("a.png"), PathBuf::from("b.png")]
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("print --pretty").is_err());
+        match parse("print --indent image.png").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.pretty && !args.expand_idat),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --expand-idat").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.pretty && args.expand_idat),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --allow-duplicate-signature").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.allow_duplicate_signature && !args.detect),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --detect").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.detect),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print assets.bin --offset 1024").unwrap() {
+            PngMeArgs::Print(args) => assert_eq!((args.offset, args.scan), (Some(1024), false)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --error-on-warning").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.error_on_warning && !args.raw_text),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --detect --raw-text").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.detect && args.raw_text),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print image.png --table").unwrap() {
+            PngMeArgs::Print(args) => assert!(args.table && !args.pretty),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("print assets.bin --scan").unwrap() {
+            PngMeArgs::Print(args) => assert_eq!((args.offset, args.scan), (None, true)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        match parse("manifest images --output manifest.json -r").unwrap() {
+            PngMeArgs::Manifest(args) => {
+                assert_eq!(args.dir, PathBuf::from("images"));
+                assert_eq!(args.output, Some(PathBuf::from("manifest.json")));
+                assert!(args.recursive);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("manifest").is_err());
+    }
+
+    #[test]
+    fn test_parse_gen_type() {
+        match parse("gen-type --seed 12").unwrap() {
+            PngMeArgs::GenType(args) => assert_eq!(args.seed, Some(12)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("gen-type").unwrap() {
+            PngMeArgs::GenType(args) => assert_eq!(args.seed, None),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("gen-type extra").is_err());
+    }
+
+    #[test]
+    fn test_parse_scan() {
+        match parse("scan a.png b.png --jobs 2").unwrap() {
+            PngMeArgs::Scan(args) => {
+                assert_eq!(
+                    args.files,
+                    vec![PathBuf::from("a.png"), PathBuf::from("b.png")]
+                );
+                assert_eq!(args.jobs, 2);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("scan").is_err());
+    }
+
+    #[test]
+    fn test_parse_verify() {
+        match parse("verify image.png").unwrap() {
+            PngMeArgs::Verify(args) => assert_eq!(args.file_path, PathBuf::from("image.png")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("verify").is_err());
+        assert!(parse("verify a.png b.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_extract_icc() {
+        match parse("extract-icc image.png --output profile.icc").unwrap() {
+            PngMeArgs::ExtractIcc(args) => {
+                assert_eq!(args.file_path, PathBuf::from("image.png"));
+                assert_eq!(args.output, PathBuf::from("profile.icc"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("extract-icc image.png").is_err());
+        assert!(parse("extract-icc --output profile.icc").is_err());
+    }
+
+    #[test]
+    fn test_parse_selftest() {
+        assert!(matches!(parse("selftest").unwrap(), PngMeArgs::SelfTest));
+        assert!(parse("selftest --verbose").is_err());
+    }
+
+    #[test]
+    fn test_parse_missing_argument() {
+        assert!(parse("remove image.png").is_err());
+        assert!(parse("decode image.png --count").is_err());
+    }
+
+    #[test]
+    fn test_parse_extra_argument() {
+        assert!(parse("info image.png other.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_command() {
+        assert!(parse("explode image.png").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_parse_bad_chunk_type() {
+        assert!(parse("decode image.png ru5t").is_err());
+    }
+}
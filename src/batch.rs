@@ -0,0 +1,345 @@
+use crate::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+#[derive(Debug)]
+pub enum BatchError {
+    UnknownPlaceholder(String),
+    UnclosedPlaceholder(String),
+    NoFileName(PathBuf),
+    OutputCollision {
+        output: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    OverwritesInput {
+        input: PathBuf,
+        output: PathBuf,
+    },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder {{{}}} in output template (expected stem, ext, name or dir)",
+                name
+            ),
+            Self::UnclosedPlaceholder(template) => {
+                write!(f, "Unclosed placeholder in output template: {}", template)
+            }
+            Self::NoFileName(path) => write!(
+                f,
+                "{} has no file name to keep in the output directory",
+                path.display()
+            ),
+            Self::OutputCollision {
+                output,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} and {} would both be written to {}",
+                first.display(),
+                second.display(),
+                output.display()
+            ),
+            Self::OverwritesInput { input, output } => write!(
+                f,
+                "{} would be written to {}, which is one of the inputs",
+                input.display(),
+                output.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// The outcome of running a batch task on one file.
+#[derive(Debug)]
+pub struct FileResult {
+    pub path: PathBuf,
+    /// The task's success message, or its error rendered as text.
+    pub outcome: std::result::Result<String, String>,
+}
+
+/// Runs `task` on every file using up to `jobs` worker threads.
+///
+/// Results come back in the same order as `files`, whatever order the workers finish in,
+/// and a failure on one file does not stop the others.
+pub fn run<F>(files: &[PathBuf], jobs: usize, task: F) -> Vec<FileResult>
+where
+    F: Fn(&Path) -> Result<String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let workers = jobs.clamp(1, files.len().max(1));
+
+    let mut results: Vec<(usize, FileResult)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = files.get(index) else {
+                            break;
+                        };
+                        let outcome = task(path).map_err(|e| e.to_string());
+                        done.push((
+                            index,
+                            FileResult {
+                                path: path.clone(),
+                                outcome,
+                            },
+                        ));
+                    }
+                    done
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("batch worker panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Where `--rename-output` writes the result for `input`. The template's `{stem}`, `{ext}`,
+/// `{name}` and `{dir}` stand for the input's file stem, extension, file name and
+/// directory, which is `.` for a bare file name. A template that does not use `{dir}`
+/// names a file in the input's directory.
+pub fn output_path(template: &str, input: &Path) -> Result<PathBuf> {
+    let part = |part: Option<&std::ffi::OsStr>| -> String {
+        part.map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let dir = input.parent().unwrap_or(Path::new(""));
+    // Without this, `{dir}/out-{name}` on `cat.png` would name `/out-cat.png`.
+    let dir_name = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| BatchError::UnclosedPlaceholder(template.to_string()))?;
+        let name = &rest[start + 1..start + end];
+        match name {
+            "stem" => rendered.push_str(&part(input.file_stem())),
+            "ext" => rendered.push_str(&part(input.extension())),
+            "name" => rendered.push_str(&part(input.file_name())),
+            "dir" => rendered.push_str(&dir_name.to_string_lossy()),
+            _ => return Err(Box::new(BatchError::UnknownPlaceholder(name.to_string()))),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+
+    if template.contains("{dir}") {
+        Ok(PathBuf::from(rendered))
+    } else {
+        Ok(dir.join(rendered))
+    }
+}
+
+/// The `output_path` of each of `files`, failing if two of them map to the same output.
+pub fn output_paths(template: &str, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    unique_outputs(files, |file| output_path(template, file))
+}
+
+/// Where `--output-dir` writes the result for each of `files`: a file of the same name in
+/// `dir`. Fails if two inputs share a name, such as `a/cat.png` and `b/cat.png`.
+pub fn output_dir_paths(dir: &Path, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    unique_outputs(files, |file| match file.file_name() {
+        Some(name) => Ok(dir.join(name)),
+        None => Err(Box::new(BatchError::NoFileName(file.clone()))),
+    })
+}
+
+/// The `output` of each of `files`, failing if two of them map to the same path or one
+/// maps onto an input, which the workers might be reading at the same time.
+fn unique_outputs<F>(files: &[PathBuf], output: F) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&PathBuf) -> Result<PathBuf>,
+{
+    let inputs: HashMap<PathBuf, &PathBuf> = files.iter().map(|f| (file_key(f), f)).collect();
+    let mut seen: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    let mut outputs = Vec::with_capacity(files.len());
+    for file in files {
+        let output = output(file)?;
+        if inputs.contains_key(&file_key(&output)) {
+            return Err(Box::new(BatchError::OverwritesInput {
+                input: file.clone(),
+                output,
+            }));
+        }
+        if let Some(first) = seen.insert(file_key(&output), file) {
+            return Err(Box::new(BatchError::OutputCollision {
+                output,
+                first: first.clone(),
+                second: file.clone(),
+            }));
+        }
+        outputs.push(output);
+    }
+    Ok(outputs)
+}
+
+/// `path` spelled so that two names for the same file compare equal: its directory is
+/// resolved when it exists, so `a.png`, `./a.png` and `dir/../a.png` all match, and `.`
+/// components are dropped when it does not.
+fn file_key(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect(),
+    }
+}
+
+/// The number of worker threads to use when `--jobs` is not given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let files: Vec<PathBuf> = (0..16).map(|i| PathBuf::from(format!("{}", i))).collect();
+        let results = run(&files, 4, |path| {
+            let n: u64 = path.to_str().unwrap().parse().unwrap();
+            // Make early files finish last.
+            thread::sleep(Duration::from_millis(16 - n));
+            Ok(format!("done {}", n))
+        });
+
+        assert_eq!(results.len(), 16);
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.path, files[i]);
+            assert_eq!(result.outcome, Ok(format!("done {}", i)));
+        }
+    }
+
+    #[test]
+    fn test_errors_do_not_abort_others() {
+        let files: Vec<PathBuf> = ["a", "bad", "c"].iter().map(PathBuf::from).collect();
+        let results = run(&files, 2, |path| {
+            if path == Path::new("bad") {
+                return Err("broken".into());
+            }
+            Ok("ok".to_string())
+        });
+
+        assert_eq!(results[0].outcome, Ok("ok".to_string()));
+        assert_eq!(results[1].outcome, Err("broken".to_string()));
+        assert_eq!(results[2].outcome, Ok("ok".to_string()));
+    }
+
+    #[test]
+    fn test_no_files() {
+        assert!(run(&[], 4, |_| Ok(String::new())).is_empty());
+    }
+
+    #[test]
+    fn test_output_path() {
+        let input = Path::new("images/cat.png");
+        let output = |template| output_path(template, input).unwrap();
+        assert_eq!(
+            output("{stem}.tagged.{ext}"),
+            PathBuf::from("images/cat.tagged.png")
+        );
+        assert_eq!(
+            output("tagged-{name}"),
+            PathBuf::from("images/tagged-cat.png")
+        );
+        assert_eq!(
+            output("{dir}-out/{name}"),
+            PathBuf::from("images-out/cat.png")
+        );
+        assert_eq!(
+            output_path("{stem}.out", Path::new("cat")).unwrap(),
+            PathBuf::from("cat.out")
+        );
+        assert_eq!(
+            output_path("{dir}/out-{name}", Path::new("cat.png")).unwrap(),
+            PathBuf::from("./out-cat.png")
+        );
+
+        assert!(matches!(
+            output_path("{size}.png", input).unwrap_err().downcast_ref(),
+            Some(BatchError::UnknownPlaceholder(name)) if name == "size"
+        ));
+        assert!(output_path("{stem.png", input).is_err());
+    }
+
+    #[test]
+    fn test_output_paths_reject_collisions() {
+        let files = vec![PathBuf::from("a/cat.png"), PathBuf::from("b/cat.png")];
+        assert_eq!(
+            output_paths("{stem}.tagged.{ext}", &files).unwrap(),
+            vec![
+                PathBuf::from("a/cat.tagged.png"),
+                PathBuf::from("b/cat.tagged.png")
+            ]
+        );
+
+        let files = vec![PathBuf::from("a/cat.png"), PathBuf::from("a/cat.apng")];
+        let err = output_paths("{stem}.out", &files).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a/cat.png and a/cat.apng would both be written to a/cat.out"
+        );
+    }
+
+    #[test]
+    fn test_output_paths_reject_overwriting_inputs() {
+        let files = vec![PathBuf::from("a.png"), PathBuf::from("a.tagged.png")];
+        let err = output_paths("{stem}.tagged.{ext}", &files).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(BatchError::OverwritesInput { input, .. }) if input == Path::new("a.png")
+        ));
+        assert!(output_paths("{name}", &[PathBuf::from("a.png")]).is_err());
+    }
+
+    #[test]
+    fn test_output_dir_paths() {
+        let files = vec![PathBuf::from("a/cat.png"), PathBuf::from("b/dog.png")];
+        assert_eq!(
+            output_dir_paths(Path::new("out"), &files).unwrap(),
+            vec![PathBuf::from("out/cat.png"), PathBuf::from("out/dog.png")]
+        );
+
+        let files = vec![PathBuf::from("a/cat.png"), PathBuf::from("b/cat.png")];
+        let err = output_dir_paths(Path::new("out"), &files).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a/cat.png and b/cat.png would both be written to out/cat.png"
+        );
+        assert!(output_dir_paths(Path::new("out"), &[PathBuf::from("..")]).is_err());
+    }
+}
@@ -1,8 +1,86 @@
 use crate::chunk_type::ChunkType;
+use crate::crc::Crc32;
+use crate::ihdr::{Ihdr, IhdrError};
+use crate::phys::{Phys, PhysError};
+use crate::text;
+use crate::{Error, Result};
+use std::convert::TryFrom;
 use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum ChunkDecodingError {
+    TooShort(usize),
+    LengthMismatch {
+        declared: u32,
+        actual: usize,
+    },
+    BadCrc {
+        expected: u32,
+        actual: u32,
+    },
+    TooLong(u32),
+    /// The reader was already at its end: there are no more chunks.
+    Eof,
+    /// The reader ended part way through a chunk.
+    Incomplete,
+    /// The data is not UTF-8; `snippet` shows the bytes around `offset` in hex.
+    NotUtf8 {
+        offset: usize,
+        snippet: String,
+    },
+}
+
+impl fmt::Display for ChunkDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort(len) => {
+                write!(f, "Chunk too short: {} bytes (expected at least 12)", len)
+            }
+            Self::LengthMismatch { declared, actual } => {
+                write!(
+                    f,
+                    "Chunk length mismatch: declared {} bytes of data, found {}",
+                    declared, actual
+                )
+            }
+            Self::BadCrc { expected, actual } => {
+                write!(
+                    f,
+                    "Bad CRC: expected {:#010x}, computed {:#010x}",
+                    expected, actual
+                )
+            }
+            Self::TooLong(len) => write!(
+                f,
+                "Chunk too long: {} bytes of data (at most {})",
+                len,
+                Chunk::MAX_LENGTH
+            ),
+            Self::Eof => write!(f, "No more chunks"),
+            Self::Incomplete => write!(f, "Chunk ended unexpectedly"),
+            Self::NotUtf8 { offset, snippet } => write!(
+                f,
+                "Chunk data is not valid UTF-8: bad byte at offset {} ({})",
+                offset, snippet
+            ),
+        }
+    }
+}
 
+impl std::error::Error for ChunkDecodingError {}
 
-struct Chunk {
+impl ChunkDecodingError {
+    /// Whether `error` is the clean end of input returned by `Chunk::try_from_reader`.
+    pub fn is_eof(error: &Error) -> bool {
+        matches!(error.downcast_ref(), Some(ChunkDecodingError::Eof))
+    }
+}
+
+/// Chunks compare and hash by type and data; the length and CRC follow from those.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
@@ -10,6 +88,110 @@ struct Chunk {
 }
 
 impl Chunk {
+    /// The largest data length the PNG spec allows in a single chunk (2^31 - 1).
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;
+
+    /// Bytes each chunk takes on disk besides its data: length, type and CRC.
+    pub const OVERHEAD: usize = 12;
+
+    /// Creates a new chunk, computing its length and CRC from `chunk_type` and `data`.
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        let crc = Self::compute_crc(&chunk_type, &data);
+        Chunk {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        }
+    }
+
+    /// Creates a new chunk like `Chunk::new`, parsing the chunk type from a string. Fails
+    /// with the `ChunkTypeDecodingError` if `chunk_type` is not four ASCII letters.
+    pub fn try_from_parts(chunk_type: &str, data: Vec<u8>) -> Result<Chunk> {
+        Ok(Chunk::new(ChunkType::from_str(chunk_type)?, data))
+    }
+
+    /// Creates a `tEXt` chunk holding `value` under `keyword`, both stored as Latin-1.
+    /// Fails if the keyword is not 1-79 printable characters or either string has
+    /// characters outside Latin-1.
+    pub fn new_text(keyword: &str, value: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str("tEXt").expect("tEXt is a valid chunk type");
+        Ok(Chunk::new(chunk_type, text::encode_text(keyword, value)?))
+    }
+
+    /// Creates a `zTXt` chunk holding `value` compressed under `keyword`, with the same
+    /// checks as `Chunk::new_text`.
+    pub fn new_ztxt(keyword: &str, value: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str("zTXt").expect("zTXt is a valid chunk type");
+        Ok(Chunk::new(chunk_type, text::encode_ztxt(keyword, value)?))
+    }
+
+    /// Reads exactly one chunk from `reader` and verifies its CRC.
+    ///
+    /// Returns `ChunkDecodingError::Eof` if the reader has no bytes left before the chunk
+    /// starts, and `ChunkDecodingError::Incomplete` if it ends part way through one.
+    pub fn try_from_reader<R: Read>(reader: &mut R) -> Result<Chunk> {
+        let mut length_bytes = [0; 4];
+        let mut filled = 0;
+        while filled < length_bytes.len() {
+            match reader.read(&mut length_bytes[filled..]) {
+                Ok(0) if filled == 0 => return Err(Box::new(ChunkDecodingError::Eof)),
+                Ok(0) => return Err(Box::new(ChunkDecodingError::Incomplete)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+
+        let length = u32::from_be_bytes(length_bytes);
+        if length > Self::MAX_LENGTH {
+            return Err(Box::new(ChunkDecodingError::TooLong(length)));
+        }
+
+        let mut type_bytes = [0; 4];
+        read_exact(reader, &mut type_bytes)?;
+        let chunk_type = ChunkType::try_from(type_bytes)?;
+
+        // Read through `take` so a bogus length fails at the end of input rather than
+        // allocating the whole declared size up front.
+        let mut data = Vec::new();
+        reader.take(length as u64).read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(Box::new(ChunkDecodingError::Incomplete));
+        }
+
+        let mut crc_bytes = [0; 4];
+        read_exact(reader, &mut crc_bytes)?;
+
+        Self::from_parts(length, chunk_type, data, u32::from_be_bytes(crc_bytes))
+    }
+
+    /// Assembles a parsed chunk, checking `crc` against its type and data.
+    fn from_parts(length: u32, chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Result<Chunk> {
+        let actual = Self::compute_crc(&chunk_type, &data);
+        if crc != actual {
+            return Err(Box::new(ChunkDecodingError::BadCrc {
+                expected: crc,
+                actual,
+            }));
+        }
+
+        Ok(Chunk {
+            length,
+            chunk_type,
+            data,
+            crc,
+        })
+    }
+
+    /// The CRC of the given chunk type and data, as stored at the end of a chunk.
+    fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(&chunk_type.bytes());
+        crc.update(data);
+        crc.finalize()
+    }
+
     /// The length of the data portion of this chunk.
     pub fn length(&self) -> u32 {
         self.length
@@ -30,10 +212,44 @@ impl Chunk {
         self.crc
     }
 
+    /// The exact bytes the CRC is computed over: the chunk type followed by the data. The
+    /// length field is not covered. Useful for checking a CRC with another tool.
+    pub fn crc_input(&self) -> Vec<u8> {
+        let mut input = Vec::with_capacity(4 + self.data.len());
+        input.extend_from_slice(&self.chunk_type.bytes());
+        input.extend_from_slice(&self.data);
+        input
+    }
+
     /// Returns the data stored in this chunk as a `String`. This function will return an error
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> Result<String> {
-        Ok(String::from_utf8(self.chunk_data.clone()).map_err(Box::new)?)
+        match std::str::from_utf8(&self.data) {
+            Ok(text) => Ok(text.to_string()),
+            Err(e) => {
+                let offset = e.valid_up_to();
+                Err(Box::new(ChunkDecodingError::NotUtf8 {
+                    offset,
+                    snippet: hex_snippet(&self.data, offset),
+                }))
+            }
+        }
+    }
+
+    /// Parses this chunk's data as a `pHYs` physical resolution.
+    pub fn as_phys(&self) -> Result<Phys> {
+        if self.chunk_type.bytes() != *b"pHYs" {
+            return Err(Box::new(PhysError::WrongType(self.chunk_type.to_string())));
+        }
+        Phys::try_from(self.data())
+    }
+
+    /// Parses this chunk's data as an `IHDR` image header.
+    pub fn as_ihdr(&self) -> Result<Ihdr> {
+        if self.chunk_type.bytes() != *b"IHDR" {
+            return Err(Box::new(IhdrError::WrongType(self.chunk_type.to_string())));
+        }
+        Ihdr::try_from(self.data())
     }
 
     /// Returns this chunk as a byte sequences described by the PNG spec.
@@ -43,35 +259,152 @@ impl Chunk {
     /// 3. The data itself *(`length` bytes)*
     /// 4. The CRC of the chunk type and data *(4 bytes)*
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut vec: Vec<u8> = Vec<u8>::new();
+        let mut vec: Vec<u8> = Vec::with_capacity(self.data.len() + 12);
 
         //length
-        for byte in self.length.to_be_bytes() {
-            vec.push(byte);
-        }
-        
+        vec.extend_from_slice(&self.length.to_be_bytes());
         //chunk type
-        for byte in self.chunk_type.bytes() {
-            vec.push(byte);
-        }
+        vec.extend_from_slice(&self.chunk_type.bytes());
         //data
-        for byte in self.data {
-            vec.push(byte);
-        }
+        vec.extend_from_slice(&self.data);
         //crc
-        for byte in self.crc.to_be_bytes() {
-            vec.push(byte);
-        }
+        vec.extend_from_slice(&self.crc.to_be_bytes());
 
         vec
     }
+
+    /// The number of bytes this chunk takes on disk, the length of `as_bytes`.
+    pub fn total_bytes(&self) -> usize {
+        Self::OVERHEAD + self.data.len()
+    }
+
+    /// The Shannon entropy of the chunk data in bits per byte, from 0.0 for empty or
+    /// constant data up to 8.0. Compressed or encrypted data sits close to 8.0, so a high
+    /// value in an ancillary chunk hints at a hidden payload.
+    pub fn data_entropy(&self) -> f64 {
+        let mut counts = [0usize; 256];
+        for &byte in &self.data {
+            counts[byte as usize] += 1;
+        }
+        let total = self.data.len() as f64;
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| count as f64 / total)
+            .fold(0.0, |entropy, p| entropy + p * (1.0 / p).log2())
+    }
+}
+
+/// The number of bytes a chunk holding a `message_len`-byte message takes on disk:
+/// the message plus `Chunk::OVERHEAD` for its length, type and CRC.
+pub fn encoded_chunk_size(message_len: usize) -> usize {
+    Chunk::OVERHEAD + message_len
 }
 
 impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self> {
-        todo!()
+        if bytes.len() < 12 {
+            return Err(Box::new(ChunkDecodingError::TooShort(bytes.len())));
+        }
+
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let data_len = bytes.len() - 12;
+        if length as usize != data_len {
+            return Err(Box::new(ChunkDecodingError::LengthMismatch {
+                declared: length,
+                actual: data_len,
+            }));
+        }
+
+        let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
+        let data = bytes[8..8 + data_len].to_vec();
+        let crc_bytes = &bytes[8 + data_len..];
+        let crc = u32::from_be_bytes([crc_bytes[0], crc_bytes[1], crc_bytes[2], crc_bytes[3]]);
+
+        Self::from_parts(length, chunk_type, data, crc)
+    }
+}
+
+/// `Read::read_exact`, reporting a short read as `ChunkDecodingError::Incomplete`.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| -> Error {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Box::new(ChunkDecodingError::Incomplete)
+        } else {
+            Box::new(e)
+        }
+    })
+}
+
+/// Reads chunks from `reader` one at a time, so a caller can stop as soon as it has found
+/// what it needs without reading the rest of the input. Each item is one chunk read with
+/// `Chunk::try_from_reader`, CRC checked. Iteration ends cleanly at the end of the input,
+/// and after the first error nothing more is read.
+///
+/// Offsets are tracked as `u64`: chunk lengths are 32-bit, but a file holding many chunks
+/// can run past 4 GiB.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads a bare sequence of chunks, with no PNG signature in front.
+    pub fn new(reader: R) -> ChunkReader<R> {
+        ChunkReader::starting_at(reader, 0)
+    }
+
+    /// Reads chunks from a reader that is already `offset` bytes into its input, such as a
+    /// file that has been seeked, so `ChunkReader::offset` counts from the start.
+    pub fn starting_at(reader: R, offset: u64) -> ChunkReader<R> {
+        ChunkReader {
+            reader,
+            offset,
+            done: false,
+        }
+    }
+
+    /// Reads the chunks of a PNG file, first checking its signature.
+    pub fn png(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut signature = [0; 8];
+        read_exact(&mut reader, &mut signature)?;
+        if !crate::png::is_png_signature(&signature) {
+            return Err(Box::new(crate::png::PngDecodingError::BadHeader));
+        }
+        Ok(ChunkReader::starting_at(reader, signature.len() as u64))
+    }
+
+    /// The byte offset where the next chunk starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Gives back the underlying reader, positioned just after the last chunk read.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match Chunk::try_from_reader(&mut self.reader) {
+            Ok(chunk) => {
+                self.offset += Chunk::OVERHEAD as u64 + u64::from(chunk.length());
+                Some(Ok(chunk))
+            }
+            Err(e) => {
+                self.done = true;
+                (!ChunkDecodingError::is_eof(&e)).then_some(Err(e))
+            }
+        }
     }
 }
 
@@ -87,11 +420,36 @@ impl fmt::Display for Chunk {
     }
 }
 
+/// Up to four bytes either side of `offset` in hex, with the byte at `offset` bracketed,
+/// such as `6c 6c 6f [ff] 21`.
+fn hex_snippet(data: &[u8], offset: usize) -> String {
+    const CONTEXT: usize = 4;
+
+    let start = offset.saturating_sub(CONTEXT);
+    let end = (offset + CONTEXT + 1).min(data.len());
+    let bytes: Vec<String> = (start..end)
+        .map(|i| {
+            if i == offset {
+                format!("[{:02x}]", data[i])
+            } else {
+                format!("{:02x}", data[i])
+            }
+        })
+        .collect();
+    let mut snippet = bytes.join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "... ");
+    }
+    if end < data.len() {
+        snippet.push_str(" ...");
+    }
+    snippet
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk_type::ChunkType;
-    use std::str::FromStr;
+    use crate::chunk_type::{ChunkType, ChunkTypeDecodingError};
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -107,19 +465,40 @@ mod tests {
             .chain(crc.to_be_bytes().iter())
             .copied()
             .collect();
-        
+
         Chunk::try_from(chunk_data.as_ref()).unwrap()
     }
 
     #[test]
     fn test_new_chunk() {
         let chunk_type = ChunkType::from_str("RuSt").unwrap();
-        let data = "This is where your secret message will be!".as_bytes().to_vec();
+        let data = "This is where your secret message will be!"
+            .as_bytes()
+            .to_vec();
         let chunk = Chunk::new(chunk_type, data);
         assert_eq!(chunk.length(), 42);
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_chunk_try_from_parts() {
+        let data = b"This is where your secret message will be!".to_vec();
+        let chunk = Chunk::try_from_parts("RuSt", data).unwrap();
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk, testing_chunk());
+
+        let err = Chunk::try_from_parts("RuST!", Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ChunkTypeDecodingError::BadLength(5))
+        ));
+        let err = Chunk::try_from_parts("Ru5t", Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ChunkTypeDecodingError::BadByte(b'5'))
+        ));
+    }
+
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
@@ -146,6 +525,18 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_chunk_crc_input() {
+        let chunk = testing_chunk();
+        let input = chunk.crc_input();
+        assert!(input.starts_with(b"RuSt"));
+        assert_eq!(&input[4..], chunk.data());
+
+        let mut crc = Crc32::new();
+        crc.update(&input);
+        assert_eq!(crc.finalize(), chunk.crc());
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -209,9 +600,221 @@ mod tests {
             .chain(crc.to_be_bytes().iter())
             .copied()
             .collect();
-        
+
         let chunk: Chunk = TryFrom::try_from(chunk_data.as_ref()).unwrap();
-        
+
         let _chunk_string = format!("{}", chunk);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunks_from_reader() {
+        let first = testing_chunk();
+        let second = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        let bytes: Vec<u8> = first
+            .as_bytes()
+            .into_iter()
+            .chain(second.as_bytes())
+            .collect();
+        let mut reader = std::io::Cursor::new(bytes);
+
+        let chunk = Chunk::try_from_reader(&mut reader).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(
+            chunk.data_as_string().unwrap(),
+            first.data_as_string().unwrap()
+        );
+
+        let chunk = Chunk::try_from_reader(&mut reader).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "IEND");
+        assert_eq!(chunk.length(), 0);
+
+        let eof = Chunk::try_from_reader(&mut reader).unwrap_err();
+        assert!(ChunkDecodingError::is_eof(&eof));
+    }
+
+    #[test]
+    fn test_chunk_reader_stops_early() {
+        let filler = Chunk::new(ChunkType::from_str("fiLl").unwrap(), vec![0; 1000]);
+        let target = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"found".to_vec());
+        let mut bytes = crate::png::Png::STANDARD_HEADER.to_vec();
+        for _ in 0..10 {
+            bytes.extend(filler.as_bytes());
+        }
+        bytes.extend(target.as_bytes());
+        let before_rest = bytes.len();
+        for _ in 0..10_000 {
+            bytes.extend(filler.as_bytes());
+        }
+
+        let mut chunks = ChunkReader::png(std::io::Cursor::new(bytes)).unwrap();
+        let found = chunks
+            .by_ref()
+            .map(Result::unwrap)
+            .find(|chunk| chunk.chunk_type().to_string() == "ruSt")
+            .unwrap();
+        assert_eq!(found, target);
+        // Nothing past the target chunk was read.
+        assert_eq!(chunks.into_inner().position() as usize, before_rest);
+    }
+
+    #[test]
+    fn test_chunk_reader_offsets_do_not_wrap() {
+        let bytes = crate::png::tests::PNG_FILE;
+        let mut chunks = ChunkReader::png(&bytes[..]).unwrap();
+        let mut offsets = vec![chunks.offset()];
+        while let Some(chunk) = chunks.next() {
+            chunk.unwrap();
+            offsets.push(chunks.offset());
+        }
+        assert_eq!(offsets, [8, 33, 57, 69]);
+
+        // As if the reader had been seeked to just before the 4 GiB mark.
+        let start = u64::from(u32::MAX) - 20;
+        let mut chunks = ChunkReader::starting_at(&bytes[8..], start);
+        chunks.by_ref().for_each(|chunk| drop(chunk.unwrap()));
+        assert_eq!(chunks.offset(), start + 61);
+        assert!(chunks.offset() > u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_chunk_reader_ends_and_fuses() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes.extend(testing_chunk().as_bytes());
+        let chunks: Vec<Chunk> = ChunkReader::new(bytes.as_slice())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(chunks, [testing_chunk(), testing_chunk()]);
+
+        // A truncated chunk is one error, and then the iterator is finished.
+        let truncated = &bytes[..bytes.len() - 2];
+        let mut chunks = ChunkReader::new(truncated);
+        assert!(chunks.next().unwrap().is_ok());
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+
+        assert!(ChunkReader::png(&b"GIF89a\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_from_reader_incomplete() {
+        let bytes = testing_chunk().as_bytes();
+        for len in [2, 6, 20, bytes.len() - 1] {
+            let mut reader = &bytes[..len];
+            let error = Chunk::try_from_reader(&mut reader).unwrap_err();
+            assert!(!ChunkDecodingError::is_eof(&error));
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(ChunkDecodingError::Incomplete)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_data_as_string_reports_bad_offset() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"hello, w\xfforld!".to_vec(),
+        );
+        let err = chunk.data_as_string().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ChunkDecodingError::NotUtf8 { offset: 8, .. })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Chunk data is not valid UTF-8: bad byte at offset 8 \
+             (... 6f 2c 20 77 [ff] 6f 72 6c 64 ...)"
+        );
+
+        let short = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![b'a', 0xc3]);
+        assert!(short
+            .data_as_string()
+            .unwrap_err()
+            .to_string()
+            .ends_with("offset 1 (61 [c3])"));
+    }
+
+    #[test]
+    fn test_chunk_eq_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |chunk: &Chunk| {
+            let mut hasher = DefaultHasher::new();
+            chunk.hash(&mut hasher);
+            hasher.finish()
+        };
+        let first = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"same".to_vec());
+        let second = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"same".to_vec());
+        assert_eq!(first, second);
+        assert_eq!(hash(&first), hash(&second));
+        assert_eq!(Chunk::try_from(first.as_bytes().as_slice()).unwrap(), first);
+
+        let other_data = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"diff".to_vec());
+        let other_type = Chunk::new(ChunkType::from_str("ruSu").unwrap(), b"same".to_vec());
+        assert_ne!(first, other_data);
+        assert_ne!(first, other_type);
+    }
+
+    #[test]
+    fn test_new_text_chunk() {
+        let chunk = Chunk::new_text("Author", "Zoë").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+
+        let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+        let (keyword, value) = text::decode_text(parsed.data()).unwrap();
+        assert_eq!(keyword, "Author");
+        assert_eq!(value, "Zoë");
+    }
+
+    #[test]
+    fn test_new_text_bad_keyword() {
+        assert!(Chunk::new_text("", "value").is_err());
+        assert!(Chunk::new_text(&"k".repeat(80), "value").is_err());
+        assert!(Chunk::new_ztxt("", "value").is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_new_ztxt_chunk() {
+        let chunk = Chunk::new_ztxt("Comment", "squeezed").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        let (keyword, value) = text::decode_ztxt(chunk.data()).unwrap();
+        assert_eq!((keyword.as_str(), value.as_str()), ("Comment", "squeezed"));
+    }
+
+    #[test]
+    fn test_encoded_chunk_size() {
+        assert_eq!(encoded_chunk_size(0), 12);
+        for message in ["", "hi", "This is where your secret message will be!"] {
+            let chunk = Chunk::try_from_parts("RuSt", message.as_bytes().to_vec()).unwrap();
+            assert_eq!(encoded_chunk_size(message.len()), chunk.total_bytes());
+            assert_eq!(chunk.total_bytes(), chunk.as_bytes().len());
+        }
+    }
+
+    #[test]
+    fn test_data_entropy() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let mut rng = crate::rng::Rng::from_seed(11);
+        let random: Vec<u8> = (0..65536).map(|_| rng.below(256) as u8).collect();
+        let entropy = Chunk::new(chunk_type.clone(), random).data_entropy();
+        assert!(entropy > 7.99 && entropy <= 8.0, "{}", entropy);
+
+        let repetitive = b"abab".repeat(1000);
+        let entropy = Chunk::new(chunk_type.clone(), repetitive).data_entropy();
+        assert!((entropy - 1.0).abs() < 1e-9, "{}", entropy);
+        assert_eq!(
+            Chunk::new(chunk_type.clone(), vec![7; 100]).data_entropy(),
+            0.0
+        );
+        assert_eq!(Chunk::new(chunk_type, Vec::new()).data_entropy(), 0.0);
+    }
+
+    #[test]
+    fn test_chunk_from_reader_bad_crc() {
+        let mut bytes = testing_chunk().as_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(Chunk::try_from_reader(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -1,8 +1,9 @@
+use crate::rng::Rng;
+use crate::sha256::Sha256;
 use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
-use std::error::Error;
-
 
 #[derive(Debug)]
 pub enum ChunkTypeDecodingError {
@@ -20,12 +21,26 @@ impl fmt::Display for ChunkTypeDecodingError {
 
 impl Error for ChunkTypeDecodingError {}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ChunkType{
-    ct_bytes: [u8; 4]
+/// Named chunk types that can be used in place of a four-letter code.
+pub const PRESETS: [(&str, &str); 4] = [
+    ("secret", "ruSt"),
+    ("note", "noTe"),
+    ("tag", "taGs"),
+    ("private", "prVt"),
+];
+
+/// The chunk types defined by the PNG specification and its registered extensions.
+pub const STANDARD_TYPES: [&str; 32] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "acTL", "bKGD", "cHRM", "cICP", "cLLI", "dSIG", "eXIf", "fcTL",
+    "fdAT", "gAMA", "gIFg", "gIFx", "hIST", "iCCP", "iTXt", "mDCV", "oFFs", "pCAL", "pHYs", "sBIT",
+    "sCAL", "sPLT", "sRGB", "sTER", "tEXt", "tIME", "tRNS", "zTXt",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChunkType {
+    ct_bytes: [u8; 4],
 }
 
-#[allow(dead_code)]
 impl ChunkType {
     pub fn bytes(&self) -> [u8; 4] {
         self.ct_bytes
@@ -51,9 +66,149 @@ impl ChunkType {
         self.is_reserved_bit_valid()
     }
 
+    /// Whether this is one of the `STANDARD_TYPES`, rather than an application's own chunk.
+    pub fn is_standard(&self) -> bool {
+        STANDARD_TYPES.contains(&self.to_string().as_str())
+    }
+
     pub fn is_valid_byte(byte: u8) -> bool {
         byte.is_ascii_uppercase() || byte.is_ascii_lowercase()
     }
+
+    /// Checks that `s` would parse as a chunk type, without building one: it must be
+    /// exactly four ASCII letters.
+    pub fn validate_str(s: &str) -> Result<(), ChunkTypeDecodingError> {
+        if s.len() != 4 {
+            return Err(ChunkTypeDecodingError::BadLength(s.len()));
+        }
+        match s.bytes().find(|byte| !Self::is_valid_byte(*byte)) {
+            Some(byte) => Err(ChunkTypeDecodingError::BadByte(byte)),
+            None => Ok(()),
+        }
+    }
+
+    /// The names of the four properties ("critical", "public", "reserved_bit_valid" and
+    /// "safe_to_copy") on which this type and `other` disagree, in that order.
+    pub fn property_diff(&self, other: &ChunkType) -> Vec<&'static str> {
+        [
+            ("critical", self.is_critical(), other.is_critical()),
+            ("public", self.is_public(), other.is_public()),
+            (
+                "reserved_bit_valid",
+                self.is_reserved_bit_valid(),
+                other.is_reserved_bit_valid(),
+            ),
+            (
+                "safe_to_copy",
+                self.is_safe_to_copy(),
+                other.is_safe_to_copy(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, ours, theirs)| ours != theirs)
+        .map(|(name, _, _)| name)
+        .collect()
+    }
+
+    /// This chunk type with the first letter's case set so that `is_critical()` is `critical`.
+    pub fn with_critical(&self, critical: bool) -> ChunkType {
+        self.with_case(0, critical)
+    }
+
+    /// This chunk type with the second letter's case set so that `is_public()` is `public`.
+    pub fn with_public(&self, public: bool) -> ChunkType {
+        self.with_case(1, public)
+    }
+
+    /// This chunk type with the fourth letter's case set so that `is_safe_to_copy()` is
+    /// `safe_to_copy`.
+    pub fn with_safe_to_copy(&self, safe_to_copy: bool) -> ChunkType {
+        self.with_case(3, !safe_to_copy)
+    }
+
+    /// A copy of this chunk type with the letter at `index` upper or lower case.
+    fn with_case(&self, index: usize, uppercase: bool) -> ChunkType {
+        let mut bytes = self.ct_bytes;
+        bytes[index] = if uppercase {
+            bytes[index].to_ascii_uppercase()
+        } else {
+            bytes[index].to_ascii_lowercase()
+        };
+        ChunkType { ct_bytes: bytes }
+    }
+
+    /// A random ancillary chunk type with a valid reserved bit; the public and
+    /// safe-to-copy bits are random too.
+    pub fn random_ancillary(rng: &mut Rng) -> ChunkType {
+        let mut bytes = [0u8; 4];
+        for byte in bytes.iter_mut() {
+            let letter = b'a' + rng.below(26) as u8;
+            *byte = if rng.below(2) == 0 {
+                letter
+            } else {
+                letter.to_ascii_uppercase()
+            };
+        }
+        bytes[0] = bytes[0].to_ascii_lowercase();
+        bytes[2] = bytes[2].to_ascii_uppercase();
+
+        ChunkType { ct_bytes: bytes }
+    }
+
+    /// A random ancillary, private, safe-to-copy chunk type: the kind of type meant for
+    /// application data. No registered chunk type is private, so it cannot clash with one.
+    pub fn random_private(rng: &mut Rng) -> ChunkType {
+        let mut bytes = [0u8; 4];
+        for byte in bytes.iter_mut() {
+            *byte = b'a' + rng.below(26) as u8;
+        }
+        bytes[2] = bytes[2].to_ascii_uppercase();
+
+        ChunkType { ct_bytes: bytes }
+    }
+
+    /// Builds a chunk type from its four bytes packed into a big-endian `u32`, the same
+    /// layout as on disk. The bytes must still be ASCII letters.
+    pub fn from_u32(value: u32) -> Result<ChunkType, crate::Error> {
+        ChunkType::try_from(value.to_be_bytes())
+    }
+
+    /// The four bytes packed into a big-endian `u32`; the inverse of `from_u32`.
+    pub fn to_u32(&self) -> u32 {
+        u32::from_be_bytes(self.ct_bytes)
+    }
+
+    /// The sum of the four bytes, a cheap key for bucketing chunk types.
+    pub fn byte_sum(&self) -> u16 {
+        self.ct_bytes.iter().map(|b| *b as u16).sum()
+    }
+
+    /// Parses a chunk type like `FromStr`, after trimming leading and trailing ASCII
+    /// whitespace, for types read from CSV files or typed by hand. `from_str` itself stays
+    /// strict.
+    pub fn from_str_trimmed(s: &str) -> Result<ChunkType, crate::Error> {
+        ChunkType::from_str(s.trim_matches(|c: char| c.is_ascii_whitespace()))
+    }
+
+    /// Looks up one of the named `PRESETS`.
+    pub fn from_preset(name: &str) -> Option<ChunkType> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .and_then(|(_, chunk_type)| ChunkType::from_str(chunk_type).ok())
+    }
+
+    /// A chunk type derived from `label`, the same every time: the label's SHA-256 digest
+    /// seeds `random_private`, so the type is always private and ancillary, and never one of
+    /// the registered types whose data pngme would read differently.
+    pub fn from_label(label: &str) -> ChunkType {
+        let mut hasher = Sha256::new();
+        hasher.update(label.as_bytes());
+        let digest = hasher.finalize();
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&digest[..8]);
+        ChunkType::random_private(&mut Rng::from_seed(u64::from_be_bytes(seed)))
+    }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
@@ -61,7 +216,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
 
     fn try_from(bytes: [u8; 4]) -> Result<Self, Self::Error> {
         for byte in bytes.iter() {
-            if !Self::is_valid_byte(*byte){
+            if !Self::is_valid_byte(*byte) {
                 return Err(Box::new(ChunkTypeDecodingError::BadByte(*byte)));
             }
         }
@@ -70,6 +225,17 @@ impl TryFrom<[u8; 4]> for ChunkType {
     }
 }
 
+impl TryFrom<&[u8]> for ChunkType {
+    type Error = crate::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes: [u8; 4] = bytes
+            .try_into()
+            .map_err(|_| ChunkTypeDecodingError::BadLength(bytes.len()))?;
+        ChunkType::try_from(bytes)
+    }
+}
+
 impl fmt::Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for b in &self.bytes() {
@@ -83,22 +249,14 @@ impl FromStr for ChunkType {
     type Err = crate::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 4 {
-            return Err(Box::new(ChunkTypeDecodingError::BadLength(s.len())));
-        }
+        Self::validate_str(s)?;
 
         let mut str_bytes: [u8; 4] = [0; 4];
+        str_bytes.copy_from_slice(s.as_bytes());
 
-        for (index, byte) in s.as_bytes().iter().enumerate() {
-            if Self::is_valid_byte(*byte){
-                str_bytes[index] = *byte;
-            }
-            else{
-                return Err(Box::new(ChunkTypeDecodingError::BadByte(*byte)));
-            }
-        }
-
-        Ok(ChunkType { ct_bytes: str_bytes })
+        Ok(ChunkType {
+            ct_bytes: str_bytes,
+        })
     }
 }
 
@@ -116,6 +274,26 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_chunk_type_from_slice() {
+        let bytes: &[u8] = b"RuSt";
+        assert_eq!(ChunkType::try_from(bytes).unwrap().to_string(), "RuSt");
+
+        for (bytes, length) in [(&b"RuS"[..], 3), (&b"RuStx"[..], 5), (&b""[..], 0)] {
+            let err = ChunkType::try_from(bytes).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(&ChunkTypeDecodingError::BadLength(len)) if len == length
+            ));
+        }
+
+        let err = ChunkType::try_from(&b"Ru1t"[..]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ChunkTypeDecodingError::BadByte(b'1'))
+        ));
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -186,12 +364,163 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_property_diff() {
+        let upper = ChunkType::from_str("RuSt").unwrap();
+        let lower = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(upper.property_diff(&lower), ["critical"]);
+        assert!(upper.property_diff(&upper).is_empty());
+
+        let other = ChunkType::from_str("rUsT").unwrap();
+        assert_eq!(
+            upper.property_diff(&other),
+            ["critical", "public", "reserved_bit_valid", "safe_to_copy"]
+        );
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_chunk_type_from_preset() {
+        let chunk = ChunkType::from_preset("secret").unwrap();
+        assert_eq!(&chunk.to_string(), "ruSt");
+        assert!(ChunkType::from_preset("nonsense").is_none());
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_label() {
+        let chunk_type = ChunkType::from_label("project-x");
+        assert_eq!(chunk_type, ChunkType::from_label("project-x"));
+        assert_ne!(chunk_type, ChunkType::from_label("project-y"));
+        for label in ["", "a", "project-x", "🦀", "a much longer label than most"] {
+            let chunk_type = ChunkType::from_label(label);
+            assert!(chunk_type.is_valid());
+            assert!(!chunk_type.is_critical());
+        }
+        for n in 0..10_000 {
+            let chunk_type = ChunkType::from_label(&format!("label-{}", n));
+            assert!(!chunk_type.is_public() && !chunk_type.is_standard());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_presets_are_valid() {
+        for (name, _) in PRESETS.iter() {
+            assert!(ChunkType::from_preset(name).unwrap().is_valid());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_random_ancillary() {
+        let mut rng = Rng::from_seed(1);
+        for _ in 0..100 {
+            let chunk = ChunkType::random_ancillary(&mut rng);
+            assert!(chunk.is_valid());
+            assert!(!chunk.is_critical());
+            assert!(ChunkType::from_str(&chunk.to_string()).is_ok());
+        }
+
+        let a = ChunkType::random_ancillary(&mut Rng::from_seed(9));
+        let b = ChunkType::random_ancillary(&mut Rng::from_seed(9));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    pub fn test_chunk_type_byte_sum() {
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        // 'R' + 'u' + 'S' + 't' = 82 + 117 + 83 + 116
+        assert_eq!(chunk.byte_sum(), 398);
+    }
+
+    #[test]
+    pub fn test_chunk_type_u32_round_trip() {
+        let chunk = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(chunk.to_u32(), 0x5275_5374);
+        assert_eq!(ChunkType::from_u32(0x5275_5374).unwrap(), chunk);
+
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        assert_eq!(ChunkType::from_u32(idat.to_u32()).unwrap(), idat);
+
+        assert!(ChunkType::from_u32(0x5275_5334).is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_property_setters() {
+        let chunk = ChunkType::from_str("RUSt").unwrap();
+        let private = chunk.with_public(false);
+        assert!(!private.is_public());
+        assert_eq!(private.to_string(), "RuSt");
+
+        assert_eq!(chunk.with_critical(false).to_string(), "rUSt");
+        assert_eq!(chunk.with_critical(true), chunk);
+        assert_eq!(chunk.with_safe_to_copy(false).to_string(), "RUST");
+        assert!(!chunk.with_safe_to_copy(false).is_safe_to_copy());
+        assert!(chunk.with_public(false).with_public(true).is_public());
+    }
+
+    #[test]
+    pub fn test_chunk_type_random_private() {
+        let mut rng = Rng::from_seed(3);
+        for _ in 0..100 {
+            let chunk = ChunkType::random_private(&mut rng);
+            assert!(chunk.is_valid());
+            assert!(!chunk.is_critical());
+            assert!(!chunk.is_public());
+            assert!(chunk.is_safe_to_copy());
+        }
+
+        let a = ChunkType::random_private(&mut Rng::from_seed(42));
+        let b = ChunkType::random_private(&mut Rng::from_seed(42));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::from_str("IHDR").unwrap().is_standard());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("TEXT").unwrap().is_standard());
+    }
+
+    #[test]
+    pub fn test_validate_str() {
+        assert!(ChunkType::validate_str("RuSt").is_ok());
+        assert!(matches!(
+            ChunkType::validate_str("Ru"),
+            Err(ChunkTypeDecodingError::BadLength(2))
+        ));
+        assert!(matches!(
+            ChunkType::validate_str("RuStY"),
+            Err(ChunkTypeDecodingError::BadLength(5))
+        ));
+        assert!(matches!(
+            ChunkType::validate_str("Ru1t"),
+            Err(ChunkTypeDecodingError::BadByte(b'1'))
+        ));
+        // Multi-byte characters are counted in bytes, like the chunk type itself.
+        assert!(matches!(
+            ChunkType::validate_str("Rüt"),
+            Err(ChunkTypeDecodingError::BadByte(0xc3))
+        ));
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_str_trimmed() {
+        for padded in [" ruSt ", "ruSt", "\truSt\n", "  ruSt"] {
+            let chunk_type = ChunkType::from_str_trimmed(padded).unwrap();
+            assert_eq!(chunk_type.to_string(), "ruSt");
+            assert_eq!(ChunkType::from_str(padded).is_ok(), padded == "ruSt");
+        }
+        // Only the ends are trimmed, and only ASCII whitespace.
+        assert!(ChunkType::from_str_trimmed("ru St").is_err());
+        assert!(ChunkType::from_str_trimmed("\u{a0}ruSt").is_err());
+        assert!(ChunkType::from_str_trimmed("   ").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -199,4 +528,4 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,181 @@
+use crate::ihdr::Ihdr;
+use crate::Result;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ColorChunkError {
+    BadLength {
+        chunk_type: &'static str,
+        color_type: u8,
+        len: usize,
+    },
+    NotAllowed {
+        chunk_type: &'static str,
+        color_type: u8,
+    },
+}
+
+impl fmt::Display for ColorChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadLength {
+                chunk_type,
+                color_type,
+                len,
+            } => write!(
+                f,
+                "Bad {} length: {} bytes for color type {}",
+                chunk_type, len, color_type
+            ),
+            Self::NotAllowed {
+                chunk_type,
+                color_type,
+            } => write!(
+                f,
+                "{} is not allowed for color type {}",
+                chunk_type, color_type
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ColorChunkError {}
+
+/// Renders a chunk's data, interpreted using the image header.
+pub type Describer = fn(&[u8], &Ihdr) -> Result<String>;
+
+/// The chunks that can only be read knowing the image's color type, with the label `print`
+/// shows them under.
+pub const DESCRIBERS: [(&str, &str, Describer); 3] = [
+    ("bKGD", "Background color", describe_bkgd),
+    ("tRNS", "Transparency", describe_trns),
+    ("sBIT", "Significant bits", describe_sbit),
+];
+
+/// Reads the big-endian 16-bit samples that `bKGD` and `tRNS` use for gray and RGB values.
+fn samples(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+fn bad_length(chunk_type: &'static str, ihdr: &Ihdr, data: &[u8]) -> crate::Error {
+    Box::new(ColorChunkError::BadLength {
+        chunk_type,
+        color_type: ihdr.color_type,
+        len: data.len(),
+    })
+}
+
+/// Describes a `bKGD` background color, whose layout depends on the image's color type.
+pub fn describe_bkgd(data: &[u8], ihdr: &Ihdr) -> Result<String> {
+    match (ihdr.color_type, data.len()) {
+        (0 | 4, 2) => Ok(format!("gray {}", samples(data)[0])),
+        (2 | 6, 6) => {
+            let rgb = samples(data);
+            Ok(format!("RGB ({}, {}, {})", rgb[0], rgb[1], rgb[2]))
+        }
+        (3, 1) => Ok(format!("palette index {}", data[0])),
+        _ => Err(bad_length("bKGD", ihdr, data)),
+    }
+}
+
+/// Describes `tRNS` transparency: a single transparent color, or alpha values for the
+/// first palette entries of an indexed image.
+pub fn describe_trns(data: &[u8], ihdr: &Ihdr) -> Result<String> {
+    match (ihdr.color_type, data.len()) {
+        (0, 2) => Ok(format!("gray {} is transparent", samples(data)[0])),
+        (2, 6) => {
+            let rgb = samples(data);
+            Ok(format!(
+                "RGB ({}, {}, {}) is transparent",
+                rgb[0], rgb[1], rgb[2]
+            ))
+        }
+        (3, 1..=256) => {
+            let alphas: Vec<String> = data.iter().map(|alpha| alpha.to_string()).collect();
+            Ok(format!(
+                "alpha for {} palette entries: {}",
+                data.len(),
+                alphas.join(", ")
+            ))
+        }
+        (4 | 6, _) => Err(Box::new(ColorChunkError::NotAllowed {
+            chunk_type: "tRNS",
+            color_type: ihdr.color_type,
+        })),
+        _ => Err(bad_length("tRNS", ihdr, data)),
+    }
+}
+
+/// Describes `sBIT`, the number of significant bits in each channel of the original image.
+pub fn describe_sbit(data: &[u8], ihdr: &Ihdr) -> Result<String> {
+    let channels: &[&str] = match ihdr.color_type {
+        0 => &["gray"],
+        2 | 3 => &["red", "green", "blue"],
+        4 => &["gray", "alpha"],
+        6 => &["red", "green", "blue", "alpha"],
+        _ => return Err(bad_length("sBIT", ihdr, data)),
+    };
+    if data.len() != channels.len() {
+        return Err(bad_length("sBIT", ihdr, data));
+    }
+    let parts: Vec<String> = channels
+        .iter()
+        .zip(data)
+        .map(|(channel, bits)| format!("{} {}", channel, bits))
+        .collect();
+    Ok(parts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr(color_type: u8) -> Ihdr {
+        Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        }
+    }
+
+    #[test]
+    fn test_bkgd() {
+        assert_eq!(
+            describe_bkgd(&[0, 255, 0, 0, 0, 128], &ihdr(2)).unwrap(),
+            "RGB (255, 0, 128)"
+        );
+        assert_eq!(describe_bkgd(&[1, 0], &ihdr(4)).unwrap(), "gray 256");
+        assert_eq!(describe_bkgd(&[7], &ihdr(3)).unwrap(), "palette index 7");
+        assert!(describe_bkgd(&[0, 255], &ihdr(2)).is_err());
+    }
+
+    #[test]
+    fn test_trns() {
+        assert_eq!(
+            describe_trns(&[0, 255, 128], &ihdr(3)).unwrap(),
+            "alpha for 3 palette entries: 0, 255, 128"
+        );
+        assert_eq!(
+            describe_trns(&[0, 0], &ihdr(0)).unwrap(),
+            "gray 0 is transparent"
+        );
+        assert!(describe_trns(&[0, 0], &ihdr(6)).is_err());
+        assert!(describe_trns(&[], &ihdr(3)).is_err());
+    }
+
+    #[test]
+    fn test_sbit() {
+        assert_eq!(
+            describe_sbit(&[5, 6, 5], &ihdr(2)).unwrap(),
+            "red 5, green 6, blue 5"
+        );
+        assert_eq!(describe_sbit(&[4, 8], &ihdr(4)).unwrap(), "gray 4, alpha 8");
+        assert!(describe_sbit(&[5, 6, 5], &ihdr(6)).is_err());
+    }
+}
@@ -0,0 +1,4164 @@
+use crate::args::{
+    BatchArgs, BatchOperation, ByteLiteral, CapacityArgs, DecodeArgs, EncodeArgs, ExtractIccArgs,
+    GenTypeArgs, HasArgs, InfoArgs, ManifestArgs, ObfuscateArgs, PrintArgs, RemoveArgs,
+    RenameChunkArgs, RepairArgs, ScanArgs, VerifyArgs, WriteOptions,
+};
+use crate::batch;
+use crate::chunk::{Chunk, ChunkReader};
+use crate::chunk_type::ChunkType;
+use crate::color;
+use crate::crc::{self, Crc32};
+use crate::json::Json;
+use crate::lsb;
+use crate::png::{self, ParseOptions, ParseWarning, Png, PngDecodingError};
+use crate::progress::Progress;
+use crate::provenance;
+use crate::repair;
+use crate::rng::Rng;
+use crate::sha256::sha256_hex;
+use crate::storage::{FileSystem, PngSink, PngSource, PngStore};
+use crate::text;
+use crate::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::thread;
+
+#[derive(Debug)]
+pub enum CommandError {
+    BatchFailed {
+        failed: usize,
+        total: usize,
+    },
+    ChunkNotFound {
+        chunk_type: String,
+        suggestion: Option<String>,
+    },
+    CriticalChunkChanged(String),
+    CriticalRename(ChunkType),
+    CannotAppend(ChunkType),
+    Warnings {
+        path: PathBuf,
+        count: usize,
+    },
+    VerifyFailed {
+        path: PathBuf,
+        reason: String,
+    },
+    Invalid {
+        path: PathBuf,
+        problems: usize,
+        bad_crcs: usize,
+    },
+    TrailingData {
+        path: PathBuf,
+        length: usize,
+    },
+    Pipe {
+        command: String,
+        reason: String,
+    },
+    NoMessage,
+    NotAscii(char),
+    TooLarge {
+        size: usize,
+        limit: usize,
+    },
+    SelfTestFailed {
+        failed: usize,
+        total: usize,
+    },
+    UrlNotWritable(PathBuf),
+    #[cfg(not(feature = "http"))]
+    HttpDisabled,
+    Read {
+        path: PathBuf,
+        source: Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: Error,
+    },
+    Write {
+        path: PathBuf,
+        source: Error,
+    },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BatchFailed { failed, total } => {
+                write!(f, "{} of {} files failed", failed, total)
+            }
+            Self::ChunkNotFound {
+                chunk_type,
+                suggestion,
+            } => {
+                write!(f, "Chunk not found: {}", chunk_type)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean '{}'?)", suggestion)?;
+                }
+                Ok(())
+            }
+            Self::Warnings { path, count } => write!(
+                f,
+                "'{}' has {} parse warning(s) (--error-on-warning)",
+                path.display(),
+                count
+            ),
+            Self::CannotAppend(chunk_type) => write!(
+                f,
+                "Cannot append to {} chunks; --append only works on uncompressed ancillary chunks",
+                chunk_type
+            ),
+            Self::CriticalChunkChanged(chunk_type) => write!(
+                f,
+                "refusing to change critical chunk {} (--append-only)",
+                chunk_type
+            ),
+            Self::CriticalRename(chunk_type) => write!(
+                f,
+                "refusing to rename: {} is a critical chunk type, and decoders need critical \
+                 chunks to keep their types",
+                chunk_type
+            ),
+            Self::Pipe { command, reason } => write!(f, "--pipe '{}' failed: {}", command, reason),
+            Self::VerifyFailed { path, reason } => write!(
+                f,
+                "'{}' does not hold what was written: {}",
+                path.display(),
+                reason
+            ),
+            Self::Invalid { path, problems, .. } => write!(
+                f,
+                "'{}' failed verification with {} problem(s)",
+                path.display(),
+                problems
+            ),
+            Self::TrailingData { path, length } => write!(
+                f,
+                "'{}' has {} byte(s) after IEND that would be lost; \
+                 pass --drop-trailing-data to write it anyway",
+                path.display(),
+                length
+            ),
+            Self::NoMessage => write!(f, "no ancillary chunk holds printable text"),
+            Self::NotAscii(c) => write!(
+                f,
+                "message contains {:?} ({}), which is not printable ASCII",
+                c,
+                c.escape_unicode()
+            ),
+            Self::TooLarge { size, limit } => write!(
+                f,
+                "the data to store is {} bytes, more than --max-size {}",
+                size, limit
+            ),
+            Self::SelfTestFailed { failed, total } => {
+                write!(f, "{} of {} self-test checks failed", failed, total)
+            }
+            Self::UrlNotWritable(url) => {
+                write!(f, "cannot write to '{}': URLs are read-only", url.display())
+            }
+            #[cfg(not(feature = "http"))]
+            Self::HttpDisabled => write!(f, "reading from URLs needs the http feature"),
+            Self::Read { path, source } => {
+                write!(f, "failed to read '{}': {}", path.display(), source)
+            }
+            Self::Parse { path, source } => {
+                write!(f, "failed to parse '{}': {}", path.display(), source)
+            }
+            Self::Write { path, source } => {
+                write!(f, "failed to write '{}': {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::BatchFailed { .. }
+            | Self::CannotAppend(_)
+            | Self::Warnings { .. }
+            | Self::ChunkNotFound { .. }
+            | Self::CriticalChunkChanged(_)
+            | Self::CriticalRename(_)
+            | Self::NoMessage
+            | Self::NotAscii(_)
+            | Self::TooLarge { .. }
+            | Self::SelfTestFailed { .. }
+            | Self::UrlNotWritable(_)
+            | Self::Pipe { .. }
+            | Self::VerifyFailed { .. }
+            | Self::Invalid { .. }
+            | Self::TrailingData { .. } => None,
+            #[cfg(not(feature = "http"))]
+            Self::HttpDisabled => None,
+            Self::Read { source, .. } | Self::Parse { source, .. } | Self::Write { source, .. } => {
+                Some(source.as_ref())
+            }
+        }
+    }
+}
+
+/// Encodes a message into a PNG file and saves the result
+pub fn encode(args: EncodeArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    encode_with(&FileSystem, args, out, err)
+}
+
+/// `encode`, reading and writing the PNG files through `store`.
+pub fn encode_with(
+    store: &dyn PngStore,
+    args: EncodeArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let file_message;
+    let message = match &args.message_file {
+        Some(path) => {
+            file_message = read_input_from(store, path)?;
+            &file_message[..]
+        }
+        None => args.message.as_bytes(),
+    };
+
+    if args.ascii_only {
+        let is_printable = |c: &char| c.is_ascii() && !c.is_ascii_control();
+        if let Some(c) = String::from_utf8_lossy(message)
+            .chars()
+            .find(|c| !is_printable(c))
+        {
+            return Err(Box::new(CommandError::NotAscii(c)));
+        }
+    }
+
+    if args.append && (args.chunk_type.is_critical() || args.chunk_type.bytes() == *b"zTXt") {
+        return Err(Box::new(CommandError::CannotAppend(args.chunk_type)));
+    }
+
+    let mut png = read_png_from(store, &args.file_path)?;
+
+    if let Some(warning) = reserved_bit_warning(&args.chunk_type) {
+        writeln!(err, "{}", warning)?;
+    }
+    if args.random_type || args.type_from.is_some() {
+        writeln!(out, "Chunk type: {}", args.chunk_type)?;
+    }
+
+    // One piece of data per chunk to add; only --split-on gives more than one.
+    let mut data = Vec::new();
+    if let Some(inner) = &args.from_png {
+        // Only stash files that really are PNGs, so decode --as-png can trust them.
+        let bytes = read_input_from(store, inner)?;
+        parse_png(inner, &bytes)?;
+        data.push(bytes);
+    } else {
+        let fields = match &args.split_on {
+            Some(delimiter) => args
+                .message
+                .split(delimiter.as_str())
+                .map(str::as_bytes)
+                .collect(),
+            None => vec![message],
+        };
+        for field in fields {
+            data.push(if args.chunk_type.bytes() == *b"zTXt" {
+                text::encode_ztxt(&args.keyword, std::str::from_utf8(field)?)?
+            } else {
+                field.to_vec()
+            });
+        }
+    }
+    if let Some(command) = &args.pipe {
+        for data in data.iter_mut() {
+            *data = pipe_through(command, data)?;
+        }
+    }
+    if let Some(limit) = args.max_size {
+        if let Some(size) = data.iter().map(Vec::len).find(|&size| size > limit) {
+            return Err(Box::new(CommandError::TooLarge { size, limit }));
+        }
+    }
+    let critical = critical_chunks(&png);
+    if !args.lsb && png.splits_animation_frame(png.append_index()) {
+        writeln!(
+            err,
+            "warning: the new chunk goes inside an APNG frame, between its fcTL and frame data; \
+             the animation may no longer play"
+        )?;
+    }
+    if args.lsb {
+        // --split-on is refused with --lsb and --append, so there is one piece of data.
+        png = lsb::embed(&png, &data.concat())?;
+    } else if args.append {
+        png.append_to_chunk(&args.chunk_type, &data.concat());
+    } else {
+        for data in data {
+            png.append_chunk(Chunk::new(args.chunk_type.clone(), data));
+        }
+    }
+    if args.append_only {
+        check_append_only(&critical, &png)?;
+    }
+
+    let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+    write_png_to(store, output, &png, &args.file_path, &args.write)?;
+    writeln!(out, "Encoded message into {}", output.display())?;
+    Ok(())
+}
+
+/// Searches for a message hidden in a PNG file and prints the message if one is found
+pub fn decode(args: DecodeArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    decode_with(&FileSystem, args, out, err)
+}
+
+/// `decode`, reading the PNG file through `store`, and writing there with `--to-file`.
+pub fn decode_with(
+    store: &dyn PngStore,
+    args: DecodeArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let chunk_type = match &args.chunk_type {
+        Some(chunk_type) if !args.lsb && !args.count => chunk_type,
+        _ => {
+            let png = read_png_from(store, &args.file_path)?;
+            if args.lsb {
+                let data = lsb::extract(&png)?;
+                let text = || Ok(String::from_utf8(data.clone())?);
+                return write_data(store, "the LSB message", &data, text, &args, out, err);
+            }
+            let Some(chunk_type) = &args.chunk_type else {
+                return find_message(&png, out);
+            };
+            writeln!(
+                out,
+                "{}",
+                png.chunks_by_type(&chunk_type.to_string()).count()
+            )?;
+            return Ok(());
+        }
+    };
+
+    let streamed = if args.all {
+        Streamed::Unavailable
+    } else {
+        stream_chunk(store, &args.file_path, chunk_type)
+    };
+    let chunks = match streamed {
+        Streamed::Found(chunk) => vec![chunk],
+        Streamed::Missing(present) => {
+            if args.quiet_not_found {
+                return Ok(());
+            }
+            let chunk_type = chunk_type.to_string();
+            return Err(Box::new(CommandError::ChunkNotFound {
+                suggestion: png::suggest_chunk_type(present, &chunk_type),
+                chunk_type,
+            }));
+        }
+        Streamed::Unavailable => {
+            let png = read_png_from(store, &args.file_path)?;
+            let chunk_type = chunk_type.to_string();
+            let mut chunks: Vec<Chunk> = png.chunks_by_type(&chunk_type).cloned().collect();
+            if !args.all {
+                chunks.truncate(1);
+            }
+            if chunks.is_empty() {
+                if args.quiet_not_found {
+                    return Ok(());
+                }
+                return Err(Box::new(chunk_not_found(&png, &chunk_type)));
+            }
+            chunks
+        }
+    };
+
+    for chunk in &chunks {
+        write_chunk(store, chunk, &args, out, err)?;
+    }
+    Ok(())
+}
+
+/// Writes out the data of one chunk for `decode`, in the form `args` asks for.
+fn write_chunk(
+    sink: &dyn PngSink,
+    chunk: &Chunk,
+    args: &DecodeArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let text = || {
+        if chunk.chunk_type().bytes() == *b"zTXt" {
+            Ok(text::decode_ztxt(chunk.data())?.1)
+        } else {
+            chunk.data_as_string()
+        }
+    };
+    let what = format!("the {} chunk", chunk.chunk_type());
+    write_data(sink, &what, chunk.data(), text, args, out, err)
+}
+
+/// Writes out `data`, described as `what` in warnings, in the form `args` asks for, with
+/// `--to-file` through `sink`. `text`
+/// decodes the whole of `data` when it is printed as a message.
+fn write_data(
+    sink: &dyn PngSink,
+    what: &str,
+    data: &[u8],
+    text: impl FnOnce() -> Result<String>,
+    args: &DecodeArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    if args.as_png {
+        if let Err(e) = Png::try_from(data) {
+            writeln!(err, "Warning: {} does not hold a valid PNG: {}", what, e)?;
+        }
+    }
+
+    let piped;
+    let data = match &args.pipe {
+        Some(command) => {
+            piped = pipe_through(command, data)?;
+            &piped[..]
+        }
+        None => data,
+    };
+    let data = &data[..args.limit.map_or(data.len(), |limit| limit.min(data.len()))];
+
+    if let Some(path) = &args.to_file {
+        sink.write(path, data).map_err(|e| CommandError::Write {
+            path: path.clone(),
+            source: Box::new(e),
+        })?;
+        writeln!(out, "Wrote {} bytes to {}", data.len(), path.display())?;
+    } else if args.as_png || args.raw || args.pipe.is_some() {
+        out.write_all(data)?;
+    } else if let Some(literal) = args.literal {
+        write_message(out, &byte_literal(data, literal), args.no_newline)?;
+    } else {
+        write_message(out, limit_chars(&text()?, args.limit), args.no_newline)?;
+    }
+    Ok(())
+}
+
+/// Writes a decoded message, followed by a newline unless `no_newline` is set.
+fn write_message(out: &mut dyn Write, message: &str, no_newline: bool) -> Result<()> {
+    out.write_all(message.as_bytes())?;
+    if !no_newline {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The first `limit` characters of `text`, or all of it without a limit.
+fn limit_chars(text: &str, limit: Option<usize>) -> &str {
+    match limit.and_then(|limit| text.char_indices().nth(limit)) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Runs `command` with `input` on its stdin and returns what it writes to stdout. The
+/// command line is split on whitespace and run directly rather than through a shell, so
+/// only the program the user named is started.
+fn pipe_through(command: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let fail = |reason: String| CommandError::Pipe {
+        command: command.to_string(),
+        reason,
+    };
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| fail("no command given".to_string()))?;
+    let mut child = process::Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| fail(e.to_string()))?;
+
+    // Feed stdin from another thread so a command that writes as it reads cannot
+    // deadlock against us on a full pipe.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let (written, output) = thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output();
+        (writer.join().expect("pipe writer panicked"), output)
+    });
+    let output = output.map_err(|e| fail(e.to_string()))?;
+    if !output.status.success() {
+        return Err(Box::new(fail(output.status.to_string())));
+    }
+    match written {
+        // The command may finish without reading all of its input.
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(Box::new(fail(e.to_string()))),
+        _ => Ok(output.stdout),
+    }
+}
+
+/// `decode` without a chunk type: looks through the ancillary chunks for ones holding
+/// printable text. A lone candidate, or the only one in a private chunk type as `encode`
+/// would create, is printed as the message; otherwise every candidate is listed.
+fn find_message(png: &Png, out: &mut dyn Write) -> Result<()> {
+    let mut candidates: Vec<(&Chunk, String)> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_critical())
+        .filter_map(|chunk| Some((chunk, printable_text(chunk)?)))
+        .collect();
+    let private: Vec<usize> = (0..candidates.len())
+        .filter(|&i| !candidates[i].0.chunk_type().is_public())
+        .collect();
+
+    let likely = match (candidates.len(), private.as_slice()) {
+        (0, _) => return Err(Box::new(CommandError::NoMessage)),
+        (1, _) => Some(0),
+        (_, [only]) => Some(*only),
+        _ => None,
+    };
+    if let Some(index) = likely {
+        let (_, message) = candidates.swap_remove(index);
+        writeln!(out, "{}", message)?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "{} chunks hold text; pass a chunk type to decode one:",
+        candidates.len()
+    )?;
+    for (chunk, message) in candidates {
+        writeln!(out, "  {}  {}", chunk.chunk_type(), message)?;
+    }
+    Ok(())
+}
+
+/// The text a chunk holds, if it looks like a message: non-empty, with no control
+/// characters other than line breaks and tabs. Text chunks are unpacked first.
+fn printable_text(chunk: &Chunk) -> Option<String> {
+    let text = match &chunk.chunk_type().bytes() {
+        b"tEXt" => text::decode_text(chunk.data()).ok()?.1,
+        b"zTXt" => text::decode_ztxt(chunk.data()).ok()?.1,
+        _ => chunk.data_as_string().ok()?,
+    };
+    let printable = text
+        .chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'));
+    (printable && !text.trim().is_empty()).then_some(text)
+}
+
+/// `data` as a Rust slice or C array literal, with at most 12 bytes on each line.
+fn byte_literal(data: &[u8], literal: ByteLiteral) -> String {
+    const PER_LINE: usize = 12;
+
+    let lines: Vec<String> = data
+        .chunks(PER_LINE)
+        .map(|line| {
+            let bytes: Vec<String> = line.iter().map(|b| format!("0x{:02x}", b)).collect();
+            bytes.join(", ")
+        })
+        .collect();
+    let body = match lines.len() {
+        0 => String::new(),
+        1 => lines[0].clone(),
+        _ => format!("\n    {},\n", lines.join(",\n    ")),
+    };
+    match literal {
+        ByteLiteral::Rust => format!("&[{}]", body),
+        ByteLiteral::C => format!("const unsigned char data[{}] = {{{}}};", data.len(), body),
+    }
+}
+
+/// Copies of the critical chunks in `png`, in order.
+fn critical_chunks(png: &Png) -> Vec<Chunk> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical())
+        .cloned()
+        .collect()
+}
+
+/// Checks that `png` has exactly the critical chunks in `before`, so an operation has at
+/// most added or removed ancillary chunks. Names the first critical chunk that differs.
+fn check_append_only(before: &[Chunk], png: &Png) -> Result<()> {
+    let after = critical_chunks(png);
+    if after == before {
+        return Ok(());
+    }
+    let changed = after
+        .iter()
+        .zip(before)
+        .find(|(after, before)| after != before)
+        .map(|(after, _)| after)
+        .or_else(|| after.get(before.len()))
+        .or_else(|| before.get(after.len()))
+        .expect("critical chunks differ");
+    Err(Box::new(CommandError::CriticalChunkChanged(
+        changed.chunk_type().to_string(),
+    )))
+}
+
+/// The error for a chunk type missing from `png`, suggesting a present type that is close
+/// enough to be a typo.
+fn chunk_not_found(png: &Png, chunk_type: &str) -> CommandError {
+    CommandError::ChunkNotFound {
+        chunk_type: chunk_type.to_string(),
+        suggestion: png.suggest_chunk_type(chunk_type),
+    }
+}
+
+/// Removes a chunk from a PNG file and saves the result
+pub fn remove(args: RemoveArgs, out: &mut dyn Write) -> Result<()> {
+    remove_with(&FileSystem, args, out)
+}
+
+/// `remove`, reading and writing the PNG files through `store`.
+pub fn remove_with(store: &dyn PngStore, args: RemoveArgs, out: &mut dyn Write) -> Result<()> {
+    let mut png = read_png_from(store, &args.file_path)?;
+    let chunk_type = args.chunk_type.to_string();
+    let removed = png
+        .remove_first_chunk(&chunk_type)
+        .map_err(|_| chunk_not_found(&png, &chunk_type))?;
+
+    let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+    write_png_to(store, output, &png, &args.file_path, &args.write)?;
+    writeln!(
+        out,
+        "Removed {} chunk ({} bytes)",
+        removed.chunk_type(),
+        removed.length()
+    )?;
+    Ok(())
+}
+
+/// Changes the type of the first chunk of one type to another, keeping its data, and
+/// saves the result. Critical chunk types can be neither renamed nor renamed to, since
+/// either would leave an image decoders cannot read.
+pub fn rename_chunk(args: RenameChunkArgs, out: &mut dyn Write) -> Result<()> {
+    rename_chunk_with(&FileSystem, args, out)
+}
+
+/// `rename_chunk`, reading and writing the PNG files through `store`.
+pub fn rename_chunk_with(
+    store: &dyn PngStore,
+    args: RenameChunkArgs,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if let Some(critical) = [&args.old_type, &args.new_type]
+        .into_iter()
+        .find(|chunk_type| chunk_type.is_critical())
+    {
+        return Err(Box::new(CommandError::CriticalRename(critical.clone())));
+    }
+    let mut png = read_png_from(store, &args.file_path)?;
+    let old_type = args.old_type.to_string();
+    let renamed = if args.all {
+        png.retype_chunks(&args.old_type, &args.new_type)
+    } else {
+        png.rename_first_chunk(&old_type, args.new_type.clone())
+            .map_or(0, |()| 1)
+    };
+    if renamed == 0 {
+        return Err(Box::new(chunk_not_found(&png, &old_type)));
+    }
+
+    let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+    write_png_to(store, output, &png, &args.file_path, &args.write)?;
+    if args.all {
+        writeln!(
+            out,
+            "Renamed {} {} chunk(s) to {}",
+            renamed, old_type, args.new_type
+        )?;
+    } else {
+        writeln!(out, "Renamed {} chunk to {}", old_type, args.new_type)?;
+    }
+    Ok(())
+}
+
+/// Gives each distinct non-standard ancillary chunk type in a file fresh random letters,
+/// keeping the chunks' data and their public and safe-to-copy bits, and prints each old type next to its new one so the
+/// change can be reversed with `rename-chunk --all`. Standard and critical chunks are left
+/// alone. A file with no such chunks is not written.
+pub fn obfuscate(args: ObfuscateArgs, out: &mut dyn Write) -> Result<()> {
+    obfuscate_with(&FileSystem, args, out)
+}
+
+/// `obfuscate`, reading and writing the PNG files through `store`.
+pub fn obfuscate_with(
+    store: &dyn PngStore,
+    args: ObfuscateArgs,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut png = read_png_from(store, &args.file_path)?;
+    let mut rng = args.seed.map_or_else(Rng::from_time, Rng::from_seed);
+    let mut taken: HashSet<ChunkType> = png.chunk_types().into_iter().collect();
+
+    let mut custom: Vec<ChunkType> = Vec::new();
+    for chunk_type in png.chunk_types() {
+        if !chunk_type.is_critical() && !chunk_type.is_standard() && !custom.contains(&chunk_type) {
+            custom.push(chunk_type);
+        }
+    }
+
+    let mut mapping = Vec::with_capacity(custom.len());
+    for old in custom {
+        let new = loop {
+            // Only the letters change: whether editors may copy the chunk, and whether its
+            // type is public, stay as they were.
+            let candidate = ChunkType::random_ancillary(&mut rng)
+                .with_public(old.is_public())
+                .with_safe_to_copy(old.is_safe_to_copy());
+            if !candidate.is_standard() && !taken.contains(&candidate) {
+                break candidate;
+            }
+        };
+        png.retype_chunks(&old, &new);
+        taken.insert(new.clone());
+        mapping.push((old, new));
+    }
+
+    if mapping.is_empty() {
+        writeln!(out, "No custom chunks to obfuscate")?;
+        return Ok(());
+    }
+    let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+    write_png_to(store, output, &png, &args.file_path, &args.write)?;
+    for (old, new) in &mapping {
+        writeln!(out, "{} -> {}", old, new)?;
+    }
+    Ok(())
+}
+
+/// Prints all of the chunks in one or more PNG files. With several files, a file that
+/// fails to load is reported and skipped, and the command fails once the rest are printed.
+pub fn print_chunks(args: PrintArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    print_chunks_with(&FileSystem, args, out, err)
+}
+
+/// `print_chunks`, reading the PNG files through `source`.
+pub fn print_chunks_with(
+    source: &dyn PngSource,
+    args: PrintArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    if let [path] = args.file_paths.as_slice() {
+        return print_file(source, path, &args, out, err);
+    }
+
+    let mut failed = 0;
+    for (index, path) in args.file_paths.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "==> {} <==", path.display())?;
+        if let Err(e) = print_file(source, path, &args, out, err) {
+            failed += 1;
+            writeln!(err, "error: {}", e)?;
+        }
+    }
+    if failed > 0 {
+        return Err(Box::new(CommandError::BatchFailed {
+            failed,
+            total: args.file_paths.len(),
+        }));
+    }
+    Ok(())
+}
+
+/// The `print` listing for one file, or for each image in it with
+/// `--allow-duplicate-signature`. With `--offset` the image starts that far into the file,
+/// and `--scan` only lists where images start. Parse warnings go to `err`.
+fn print_file(
+    source: &dyn PngSource,
+    path: &Path,
+    args: &PrintArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let bytes = read_input_from(source, path)?;
+    if args.scan {
+        let offsets = png::find_signatures(&bytes);
+        if offsets.is_empty() {
+            writeln!(out, "No PNG signature found in {}", path.display())?;
+        }
+        for offset in offsets {
+            writeln!(out, "PNG signature at byte offset {}", offset)?;
+        }
+        return Ok(());
+    }
+
+    let bytes = bytes.get(args.offset.unwrap_or(0)..).unwrap_or_default();
+    if !args.allow_duplicate_signature {
+        let png = parse_png(path, bytes)?;
+        print_warnings(&png, path, args, err)?;
+        return print_png(&png, args, out);
+    }
+
+    let images = Png::parse_all(bytes).map_err(|e| CommandError::Parse {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    if images.len() > 1 {
+        writeln!(out, "Found {} concatenated images", images.len())?;
+    }
+    for (index, png) in images.iter().enumerate() {
+        if images.len() > 1 {
+            writeln!(out, "Image {}:", index + 1)?;
+        }
+        print_warnings(png, path, args, err)?;
+        print_png(png, args, out)?;
+    }
+    Ok(())
+}
+
+/// Reports the parse warnings for `png`, read from `path`, and with `--error-on-warning`
+/// fails if there were any.
+fn print_warnings(png: &Png, path: &Path, args: &PrintArgs, err: &mut dyn Write) -> Result<()> {
+    for warning in png.warnings() {
+        writeln!(err, "warning: {}", warning)?;
+    }
+    if args.error_on_warning && !png.warnings().is_empty() {
+        return Err(Box::new(CommandError::Warnings {
+            path: path.to_path_buf(),
+            count: png.warnings().len(),
+        }));
+    }
+    Ok(())
+}
+
+/// The `print` listing for a single image.
+fn print_png(png: &Png, args: &PrintArgs, out: &mut dyn Write) -> Result<()> {
+    if args.table {
+        for line in table_lines(png.chunks()) {
+            writeln!(out, "{}", line)?;
+        }
+    } else if args.pretty {
+        for line in grouped_lines(png.chunks(), args.expand_idat) {
+            writeln!(out, "{}", line)?;
+        }
+    } else {
+        writeln!(
+            out,
+            "{:>5}  {:<4}  {:>10}  {:<10}",
+            "INDEX", "TYPE", "LENGTH", "CRC"
+        )?;
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            writeln!(
+                out,
+                "{:>5}  {:<4}  {:>10}  {:#010x}",
+                index,
+                chunk.chunk_type(),
+                chunk.length(),
+                chunk.crc()
+            )?;
+        }
+    }
+
+    if args.entropy {
+        writeln!(out, "Entropy (bits per byte):")?;
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            writeln!(
+                out,
+                "{:>5}  {:<4}  {:.3}",
+                index,
+                chunk.chunk_type(),
+                chunk.data_entropy()
+            )?;
+        }
+    }
+
+    if let Some(chunk) = png.chunk_by_type("pHYs") {
+        match chunk.as_phys() {
+            Ok(phys) => writeln!(out, "Physical resolution: {}", phys)?,
+            Err(e) => writeln!(out, "Physical resolution: invalid pHYs chunk ({})", e)?,
+        }
+    }
+
+    if let Some(chunk) = png.chunk_by_type("iCCP") {
+        match text::iccp_profile_name(chunk.data()) {
+            Ok(name) => writeln!(out, "ICC profile: {}", text::escape_control(&name))?,
+            Err(e) => writeln!(out, "ICC profile: invalid iCCP chunk ({})", e)?,
+        }
+    }
+
+    let ihdr = png.chunk_by_type("IHDR").map(Chunk::as_ihdr);
+    for (chunk_type, label, describe) in color::DESCRIBERS {
+        let Some(chunk) = png.chunk_by_type(chunk_type) else {
+            continue;
+        };
+        let description = match &ihdr {
+            Some(Ok(ihdr)) => describe(chunk.data(), ihdr),
+            _ => Err("no valid IHDR chunk to interpret it with".into()),
+        };
+        match description {
+            Ok(text) => writeln!(out, "{}: {}", label, text)?,
+            Err(e) => writeln!(out, "{}: invalid {} chunk ({})", label, chunk_type, e)?,
+        }
+    }
+
+    if args.detect {
+        for detection in provenance::detect(png) {
+            let line = detection.to_string();
+            if args.raw_text {
+                writeln!(out, "{}", line)?;
+            } else {
+                writeln!(out, "{}", text::escape_control(&line))?;
+            }
+        }
+    }
+
+    if !args.no_summary {
+        for line in summary_lines(png) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// The footer `print` ends each listing with: how many chunks of each kind there are, the
+/// size of the file they make up, and any chunk types the PNG specification does not
+/// define, which is where hidden data usually sits.
+fn summary_lines(png: &Png) -> Vec<String> {
+    let chunks = png.chunks();
+    let critical = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical())
+        .count();
+    let size = Png::STANDARD_HEADER.len() + chunks.iter().map(Chunk::total_bytes).sum::<usize>();
+
+    let mut custom: Vec<String> = Vec::new();
+    for chunk in chunks {
+        let chunk_type = chunk.chunk_type();
+        if !chunk_type.is_standard() && !custom.contains(&chunk_type.to_string()) {
+            custom.push(chunk_type.to_string());
+        }
+    }
+    let custom = if custom.is_empty() {
+        "none".to_string()
+    } else {
+        custom.join(", ")
+    };
+
+    vec![
+        format!(
+            "Summary: {} chunks ({} critical, {} ancillary), {} bytes",
+            chunks.len(),
+            critical,
+            chunks.len() - critical,
+            size
+        ),
+        format!("Custom chunks: {}", custom),
+    ]
+}
+
+/// The lines of `print --table`: a box-drawn table of every chunk with its property
+/// flags, each column as wide as its widest cell.
+fn table_lines(chunks: &[Chunk]) -> Vec<String> {
+    const HEADER: [&str; 5] = ["INDEX", "TYPE", "LENGTH", "CRC", "FLAGS"];
+    const RIGHT_ALIGNED: [bool; 5] = [true, false, true, false, false];
+
+    let rows: Vec<[String; 5]> = chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            [
+                index.to_string(),
+                chunk.chunk_type().to_string(),
+                chunk.length().to_string(),
+                format!("{:#010x}", chunk.crc()),
+                chunk_flags(chunk.chunk_type()),
+            ]
+        })
+        .collect();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let rule = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let line = |cells: [&str; 5]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .zip(RIGHT_ALIGNED)
+            .map(|((cell, width), right)| {
+                if right {
+                    format!(" {:>width$} ", cell, width = width)
+                } else {
+                    format!(" {:<width$} ", cell, width = width)
+                }
+            })
+            .collect();
+        format!("│{}│", cells.join("│"))
+    };
+
+    let mut lines = vec![rule("┌", "┬", "┐"), line(HEADER), rule("├", "┼", "┤")];
+    for row in &rows {
+        lines.push(line(row.each_ref().map(String::as_str)));
+    }
+    lines.push(rule("└", "┴", "┘"));
+    lines
+}
+
+/// The properties encoded in the case of a chunk type's letters, for `print --table`, and
+/// whether the type is one the spec defines.
+fn chunk_flags(chunk_type: &ChunkType) -> String {
+    let mut flags = vec![if chunk_type.is_critical() {
+        "critical"
+    } else {
+        "ancillary"
+    }];
+    if !chunk_type.is_public() {
+        flags.push("private");
+    }
+    if !chunk_type.is_reserved_bit_valid() {
+        flags.push("reserved");
+    }
+    if chunk_type.is_safe_to_copy() {
+        flags.push("safe-to-copy");
+    }
+    // Not a property of the letters, but it is what makes a chunk worth a second look.
+    if !chunk_type.is_standard() {
+        flags.push("non-standard");
+    }
+    flags.join(", ")
+}
+
+/// The heading `print --pretty` files a chunk under.
+fn chunk_category(chunk_type: &ChunkType) -> &'static str {
+    if chunk_type.is_critical() {
+        return "Critical";
+    }
+    match &chunk_type.bytes() {
+        b"tEXt" | b"zTXt" | b"iTXt" => "Text",
+        b"cHRM" | b"cICP" | b"cLLI" | b"gAMA" | b"iCCP" | b"mDCV" | b"sBIT" | b"sRGB" | b"bKGD"
+        | b"hIST" | b"tRNS" | b"sPLT" => "Color",
+        b"tIME" | b"acTL" | b"fcTL" | b"fdAT" => "Timing",
+        b"pHYs" | b"sCAL" | b"oFFs" | b"pCAL" | b"eXIf" => "Other",
+        _ => "Custom",
+    }
+}
+
+/// The lines of `print --pretty`: chunks grouped under their category in file order, with
+/// the `IDAT` chunks summarised on a single line unless `expand_idat` is set.
+fn grouped_lines(chunks: &[Chunk], expand_idat: bool) -> Vec<String> {
+    const CATEGORIES: [&str; 6] = ["Critical", "Text", "Color", "Timing", "Other", "Custom"];
+
+    let idat: Vec<(usize, &Chunk)> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().bytes() == *b"IDAT")
+        .collect();
+
+    let mut lines = Vec::new();
+    for category in CATEGORIES {
+        let mut entries = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            if chunk_category(chunk.chunk_type()) != category {
+                continue;
+            }
+            let is_idat = chunk.chunk_type().bytes() == *b"IDAT";
+            if is_idat && !expand_idat && idat.len() > 1 {
+                // Only the first IDAT stands in for the rest.
+                if index == idat[0].0 {
+                    let total: u64 = idat.iter().map(|(_, c)| c.length() as u64).sum();
+                    entries.push(format!(
+                        "  [{}-{}] IDAT  {} chunks, {} bytes",
+                        idat[0].0,
+                        idat[idat.len() - 1].0,
+                        idat.len(),
+                        total
+                    ));
+                }
+                continue;
+            }
+            entries.push(format!(
+                "  [{}] {}  {} bytes",
+                index,
+                chunk.chunk_type(),
+                chunk.length()
+            ));
+        }
+
+        if !entries.is_empty() {
+            lines.push(format!("{}:", category));
+            lines.extend(entries);
+        }
+    }
+    lines
+}
+
+/// Describes the image header, including the pass layout of interlaced images
+pub fn info(args: InfoArgs, out: &mut dyn Write) -> Result<()> {
+    info_with(&FileSystem, args, out)
+}
+
+/// `info`, reading the PNG file through `source`.
+pub fn info_with(source: &dyn PngSource, args: InfoArgs, out: &mut dyn Write) -> Result<()> {
+    let png = read_png_from(source, &args.file_path)?;
+    let ihdr = png
+        .chunk_by_type("IHDR")
+        .ok_or_else(|| PngDecodingError::ChunkNotFound("IHDR".to_string()))?
+        .as_ihdr()?;
+
+    writeln!(out, "Dimensions: {}x{}", ihdr.width, ihdr.height)?;
+    writeln!(out, "Bit depth: {}", ihdr.bit_depth)?;
+    writeln!(
+        out,
+        "Color type: {} ({})",
+        ihdr.color_type,
+        ihdr.color_type_name().unwrap_or("unknown")
+    )?;
+    writeln!(out, "Chunks: {}", png.chunks().len())?;
+
+    if ihdr.is_adam7() {
+        writeln!(out, "Interlace: Adam7")?;
+        writeln!(
+            out,
+            "Note: the image data is stored in seven passes, each filtered separately, \
+             so IDAT data has to be de-interlaced after decompression."
+        )?;
+        for (pass, (width, height)) in ihdr.adam7_passes().iter().enumerate() {
+            writeln!(out, "  Pass {}: {}x{}", pass + 1, width, height)?;
+        }
+    } else {
+        writeln!(out, "Interlace: none")?;
+    }
+    Ok(())
+}
+
+/// Records the chunk types and a SHA-256 of every PNG in a directory as JSON, so that
+/// changes to the collection show up when two manifests are diffed
+pub fn manifest(args: ManifestArgs, out: &mut dyn Write) -> Result<()> {
+    let mut files = Vec::new();
+    find_pngs(&args.dir, args.recursive, &mut files)?;
+    files.sort();
+
+    let entries = files
+        .iter()
+        .map(|path| manifest_entry(&args.dir, path))
+        .collect();
+    let manifest = Json::object(vec![
+        ("directory", Json::string(args.dir.display().to_string())),
+        ("files", Json::Array(entries)),
+    ]);
+    let text = manifest.to_pretty_string() + "\n";
+
+    match &args.output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| CommandError::Write {
+                path: path.clone(),
+                source: Box::new(e),
+            })?;
+            writeln!(
+                out,
+                "Wrote manifest of {} files to {}",
+                files.len(),
+                path.display()
+            )?;
+        }
+        None => out.write_all(text.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Collects the `.png` files in `dir`, descending into subdirectories if `recursive`.
+/// Symbolic links to directories are not followed, so a link cycle cannot recurse forever.
+fn find_pngs(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|e| CommandError::Read {
+        path: dir.to_path_buf(),
+        source: Box::new(e),
+    })?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if recursive {
+                find_pngs(&path, recursive, files)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// One file's manifest record. Files that fail to parse are still listed, with the error.
+fn manifest_entry(dir: &Path, path: &Path) -> Json {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let mut fields = vec![("path", Json::string(relative.display().to_string()))];
+
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            fields.push(("error", Json::string(e.to_string())));
+            return Json::object(fields);
+        }
+    };
+    fields.push(("size", Json::Number(bytes.len() as u64)));
+    fields.push(("sha256", Json::string(sha256_hex(&bytes))));
+
+    match Png::try_from(bytes.as_slice()) {
+        Ok(png) => {
+            // Chunk types in the order they first appear, which is stable across runs.
+            let mut counts: Vec<(String, u64)> = Vec::new();
+            for chunk in png.chunks() {
+                let chunk_type = chunk.chunk_type().to_string();
+                match counts.iter_mut().find(|(t, _)| *t == chunk_type) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((chunk_type, 1)),
+                }
+            }
+            let chunks = counts
+                .into_iter()
+                .map(|(chunk_type, count)| {
+                    Json::object(vec![
+                        ("type", Json::String(chunk_type)),
+                        ("count", Json::Number(count)),
+                    ])
+                })
+                .collect();
+            fields.push(("chunks", Json::Array(chunks)));
+        }
+        Err(e) => fields.push(("error", Json::string(e.to_string()))),
+    }
+    Json::object(fields)
+}
+
+/// Rewrites chunk length fields that do not match the chunk data, finding each chunk's
+/// real end from its CRC
+pub fn fix_lengths(args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    fix_lengths_with(&FileSystem, args, out)
+}
+
+/// `fix_lengths`, reading and writing the PNG files through `store`.
+pub fn fix_lengths_with(store: &dyn PngStore, args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    let path = &args.file_path;
+    let bytes = read_input_from(store, path)?;
+    let (repaired, fixes) = repair::fix_lengths(&bytes).map_err(|e| CommandError::Parse {
+        path: path.clone(),
+        source: e,
+    })?;
+
+    if fixes.is_empty() {
+        writeln!(out, "All chunk lengths in {} are correct", path.display())?;
+        return Ok(());
+    }
+    for fix in &fixes {
+        writeln!(out, "Fixed {}", fix)?;
+    }
+
+    let png = Png::try_from(repaired.as_slice()).map_err(|e| CommandError::Parse {
+        path: path.clone(),
+        source: e,
+    })?;
+    let output = args.output_file.as_ref().unwrap_or(path);
+    write_png_to(store, output, &png, path, &args.write)?;
+    writeln!(
+        out,
+        "Repaired {} length field(s) in {}",
+        fixes.len(),
+        path.display()
+    )?;
+    Ok(())
+}
+
+/// Moves chunks that follow `IEND` in front of it, so that `IEND` is last again
+pub fn fix_iend(args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    fix_iend_with(&FileSystem, args, out)
+}
+
+/// `fix_iend`, reading and writing the PNG files through `store`.
+pub fn fix_iend_with(store: &dyn PngStore, args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    let path = &args.file_path;
+    let (png, fix) = repair::fix_iend(read_png_from(store, path)?);
+    if fix.is_empty() {
+        writeln!(out, "IEND is already the last chunk in {}", path.display())?;
+        return Ok(());
+    }
+
+    for chunk_type in &fix.moved {
+        writeln!(out, "Moved {} chunk before IEND", chunk_type)?;
+    }
+    if fix.duplicates_removed > 0 {
+        writeln!(
+            out,
+            "Removed {} duplicate IEND chunk(s)",
+            fix.duplicates_removed
+        )?;
+    }
+    if fix.added {
+        writeln!(out, "Added missing IEND chunk")?;
+    }
+    let output = args.output_file.as_ref().unwrap_or(path);
+    write_png_to(store, output, &png, path, &args.write)?;
+    Ok(())
+}
+
+/// Recomputes the chunk CRCs that are wrong, leaving the correct ones untouched
+pub fn fix_crcs(args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    fix_crcs_with(&FileSystem, args, out)
+}
+
+/// `fix_crcs`, reading and writing the PNG files through `store`.
+pub fn fix_crcs_with(store: &dyn PngStore, args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    let path = &args.file_path;
+    let bytes = read_input_from(store, path)?;
+    let (png, fixes) = repair::fix_crcs(&bytes).map_err(|e| CommandError::Parse {
+        path: path.clone(),
+        source: e,
+    })?;
+
+    if fixes.is_empty() {
+        writeln!(out, "All chunk CRCs in {} are correct", path.display())?;
+        return Ok(());
+    }
+    for fix in &fixes {
+        writeln!(out, "Fixed {}", fix)?;
+    }
+    let output = args.output_file.as_ref().unwrap_or(path);
+    write_png_to(store, output, &png, path, &args.write)?;
+    writeln!(
+        out,
+        "Recomputed {} CRC(s) in {}, skipped {} correct one(s)",
+        fixes.len(),
+        path.display(),
+        png.chunks().len() - fixes.len()
+    )?;
+    Ok(())
+}
+
+/// Reorders the chunks into the canonical order, as far as the spec allows
+pub fn normalize(args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    normalize_with(&FileSystem, args, out)
+}
+
+/// `normalize`, reading and writing the PNG files through `store`.
+pub fn normalize_with(store: &dyn PngStore, args: RepairArgs, out: &mut dyn Write) -> Result<()> {
+    let path = &args.file_path;
+    let (png, moved) = repair::normalize(read_png_from(store, path)?);
+    if moved == 0 {
+        writeln!(out, "{} is already in canonical order", path.display())?;
+        return Ok(());
+    }
+    let output = args.output_file.as_ref().unwrap_or(path);
+    write_png_to(store, output, &png, path, &args.write)?;
+    writeln!(out, "Moved {} chunk(s) in {}", moved, path.display())?;
+    Ok(())
+}
+
+/// Looks for hidden data in many files in parallel, listing the findings under each file
+pub fn scan(args: ScanArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    let results = batch::run(&args.files, args.jobs, |path| {
+        let findings: Vec<String> = scan_file(path)?
+            .iter()
+            .map(|finding| format!("  {}", finding))
+            .collect();
+        Ok(findings.join("\n"))
+    });
+
+    let mut failed = 0;
+    for result in &results {
+        match &result.outcome {
+            Ok(findings) if findings.is_empty() => {
+                writeln!(out, "{}:\n  nothing found", result.path.display())?
+            }
+            Ok(findings) => writeln!(out, "{}:\n{}", result.path.display(), findings)?,
+            Err(message) => {
+                failed += 1;
+                writeln!(err, "{}: error: {}", result.path.display(), message)?;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Box::new(CommandError::BatchFailed {
+            failed,
+            total: results.len(),
+        }));
+    }
+    Ok(())
+}
+
+/// What `scan` reports for one file: its parse warnings, which cover non-standard chunks,
+/// bad CRCs and data after `IEND`.
+fn scan_file(path: &Path) -> Result<Vec<png::ParseWarning>> {
+    let options = ParseOptions {
+        allow_bad_crc: true,
+        ..ParseOptions::default()
+    };
+    let png =
+        Png::parse_with_options(&read_input(path)?, &options).map_err(|e| CommandError::Parse {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+    Ok(png.warnings().to_vec())
+}
+
+/// Runs an encode or remove over many files in parallel, reporting each file's result
+pub fn batch(args: BatchArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    if let BatchOperation::Encode { chunk_type, .. } = &args.operation {
+        if let Some(warning) = reserved_bit_warning(chunk_type) {
+            writeln!(err, "{}", warning)?;
+        }
+    }
+
+    // Every output is worked out up front so a collision fails before any file is written.
+    let outputs = match (&args.rename_output, &args.output_dir) {
+        (Some(template), _) => batch::output_paths(template, &args.files)?,
+        (None, Some(dir)) => {
+            let outputs = batch::output_dir_paths(dir, &args.files)?;
+            fs::create_dir_all(dir).map_err(|e| CommandError::Write {
+                path: dir.clone(),
+                source: Box::new(e),
+            })?;
+            outputs
+        }
+        (None, None) => Vec::new(),
+    };
+    let outputs: HashMap<&Path, PathBuf> = args
+        .files
+        .iter()
+        .map(PathBuf::as_path)
+        .zip(outputs)
+        .collect();
+
+    let progress = Progress::stderr(args.files.len(), args.quiet);
+    let results = batch::run(&args.files, args.jobs, |path| {
+        let result = batch_file(&args, path, outputs.get(path).map(PathBuf::as_path));
+        progress.tick();
+        result
+    });
+    progress.finish();
+
+    let mut failed = 0;
+    for result in &results {
+        match &result.outcome {
+            Ok(message) => writeln!(out, "{}: {}", result.path.display(), message)?,
+            Err(message) => {
+                failed += 1;
+                writeln!(err, "{}: error: {}", result.path.display(), message)?;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Box::new(CommandError::BatchFailed {
+            failed,
+            total: results.len(),
+        }));
+    }
+    Ok(())
+}
+
+/// Runs the batch operation on one file, returning the message to report for it. The
+/// result is written to `output` when `--rename-output` or `--output-dir` gives one, and
+/// otherwise back to `path`.
+fn batch_file(args: &BatchArgs, path: &Path, output: Option<&Path>) -> Result<String> {
+    let mut png = read_png(path)?;
+    let done = match &args.operation {
+        BatchOperation::Encode {
+            chunk_type,
+            message,
+        } => {
+            png.append_chunk(Chunk::new(chunk_type.clone(), message.as_bytes().to_vec()));
+            "encoded"
+        }
+        BatchOperation::Remove { chunk_type } => {
+            png.remove_first_chunk(&chunk_type.to_string())?;
+            "removed"
+        }
+    };
+    match output {
+        Some(output) => {
+            write_png(output, &png, path, &args.write)?;
+            Ok(format!("{} to {}", done, output.display()))
+        }
+        None => {
+            write_png(path, &png, path, &args.write)?;
+            Ok(done.to_string())
+        }
+    }
+}
+
+/// Checks whether a PNG file has a chunk of the given type, printing only with `--verbose`
+pub fn has(args: HasArgs, out: &mut dyn Write) -> Result<bool> {
+    has_with(&FileSystem, args, out)
+}
+
+/// `has`, reading the PNG file through `source`.
+pub fn has_with(source: &dyn PngSource, args: HasArgs, out: &mut dyn Write) -> Result<bool> {
+    let png = read_png_from(source, &args.file_path)?;
+    let chunk_type = args.chunk_type.to_string();
+    let count = png.chunks_by_type(&chunk_type).count();
+
+    if args.verbose {
+        match count {
+            0 => writeln!(out, "{}: no {} chunk", args.file_path.display(), chunk_type)?,
+            _ => writeln!(
+                out,
+                "{}: {} {} chunk(s)",
+                args.file_path.display(),
+                count,
+                chunk_type
+            )?,
+        }
+    }
+    Ok(count > 0)
+}
+
+/// Prints a random ancillary, private, safe-to-copy chunk type
+pub fn gen_type(args: GenTypeArgs, out: &mut dyn Write) -> Result<()> {
+    let mut rng = args.seed.map_or_else(Rng::from_time, Rng::from_seed);
+    writeln!(out, "{}", ChunkType::random_private(&mut rng))?;
+    Ok(())
+}
+
+/// Inflates the ICC color profile embedded in the `iCCP` chunk and writes it to a file
+pub fn extract_icc(args: ExtractIccArgs, out: &mut dyn Write) -> Result<()> {
+    extract_icc_with(&FileSystem, args, out)
+}
+
+/// `extract_icc`, reading the PNG file and writing the profile through `store`.
+pub fn extract_icc_with(
+    store: &dyn PngStore,
+    args: ExtractIccArgs,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let png = read_png_from(store, &args.file_path)?;
+    let chunk = png
+        .chunk_by_type("iCCP")
+        .ok_or_else(|| chunk_not_found(&png, "iCCP"))?;
+    let (name, profile) = text::decode_iccp(chunk.data())?;
+
+    store
+        .write(&args.output, &profile)
+        .map_err(|e| CommandError::Write {
+            path: args.output.clone(),
+            source: Box::new(e),
+        })?;
+    writeln!(
+        out,
+        "Wrote {} bytes of ICC profile '{}' to {}",
+        profile.len(),
+        text::escape_control(&name),
+        args.output.display()
+    )?;
+    Ok(())
+}
+
+/// Checks everything about a PNG file at once: the signature, every chunk's CRC and the
+/// structure rules of `Png::validate_structure`. Prints a one-line pass, or every problem
+/// found, and fails if there were any.
+pub fn verify(args: VerifyArgs, out: &mut dyn Write) -> Result<()> {
+    verify_with(&FileSystem, args, out)
+}
+
+/// `verify`, reading the PNG file through `source`.
+pub fn verify_with(source: &dyn PngSource, args: VerifyArgs, out: &mut dyn Write) -> Result<()> {
+    let (problems, bad_crcs) = verify_problems(&read_input_from(source, &args.file_path)?);
+    if problems.is_empty() {
+        writeln!(
+            out,
+            "PASS  {}: signature, structure and CRCs are valid",
+            args.file_path.display()
+        )?;
+        return Ok(());
+    }
+
+    writeln!(out, "FAIL  {}", args.file_path.display())?;
+    for problem in &problems {
+        writeln!(out, "  - {}", problem)?;
+    }
+    Err(Box::new(CommandError::Invalid {
+        path: args.file_path,
+        problems: problems.len(),
+        bad_crcs,
+    }))
+}
+
+/// The problems `verify` reports for `bytes`, and how many of them are bad CRCs. Chunks
+/// with a bad CRC are kept so the rest of the file is still checked; anything that stops
+/// parsing is the only problem listed.
+fn verify_problems(bytes: &[u8]) -> (Vec<String>, usize) {
+    if let Err(e) = png::check_signature(bytes) {
+        return (vec![e.to_string()], 0);
+    }
+    let options = ParseOptions {
+        allow_bad_crc: true,
+        ..ParseOptions::default()
+    };
+    let png = match Png::parse_with_options(bytes, &options) {
+        Ok(png) => png,
+        Err(e) => return (vec![e.to_string()], 0),
+    };
+
+    let mut problems: Vec<String> = png
+        .warnings()
+        .iter()
+        .filter(|warning| matches!(warning, ParseWarning::BadCrc { .. }))
+        .map(ToString::to_string)
+        .collect();
+    let bad_crcs = problems.len();
+    problems.extend(png.structure_problems().iter().map(ToString::to_string));
+    (problems, bad_crcs)
+}
+
+/// Checks the CRC-32 implementation against its test vectors, printing a line for each
+pub fn self_test(out: &mut dyn Write) -> Result<()> {
+    check_crc_vectors(&crc::TEST_VECTORS, out)
+}
+
+/// Checks each vector both in one call and fed in two pieces, the way chunk CRCs are
+/// computed over the type and then the data.
+fn check_crc_vectors(vectors: &[crc::TestVector], out: &mut dyn Write) -> Result<()> {
+    let mut failed = 0;
+    for vector in vectors {
+        let (head, tail) = vector.input.split_at(vector.input.len() / 2);
+        let mut crc = Crc32::new();
+        crc.update(head);
+        crc.update(tail);
+        let results = [
+            ("", crc::crc32(vector.input)),
+            (" in two pieces", crc.finalize()),
+        ];
+        for (how, actual) in results {
+            if actual == vector.expected {
+                writeln!(out, "pass  CRC-32 of {}{}", vector.name, how)?;
+            } else {
+                failed += 1;
+                writeln!(
+                    out,
+                    "FAIL  CRC-32 of {}{}: expected {:#010x}, got {:#010x}",
+                    vector.name, how, vector.expected, actual
+                )?;
+            }
+        }
+    }
+
+    let total = vectors.len() * 2;
+    writeln!(out, "{} of {} checks passed", total - failed, total)?;
+    if failed > 0 {
+        return Err(Box::new(CommandError::SelfTestFailed { failed, total }));
+    }
+    Ok(())
+}
+
+/// Reports the file's size and roughly how much data could still be hidden in it. The
+/// ancillary chunks already in the file count toward the threshold, with a warning once
+/// they are past it.
+pub fn capacity(args: CapacityArgs, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    capacity_with(&FileSystem, args, out, err)
+}
+
+/// `capacity`, reading the PNG file through `source`.
+pub fn capacity_with(
+    source: &dyn PngSource,
+    args: CapacityArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let bytes = read_input_from(source, &args.file_path)?;
+    let png = parse_png(&args.file_path, &bytes)?;
+    let file_size = bytes.len() as u64;
+
+    let overhead = Chunk::OVERHEAD as u64;
+    let ancillary: u64 = png
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_critical())
+        .map(|chunk| u64::from(chunk.length()) + overhead)
+        .sum();
+    // Bigger chunks are valid PNG, but pngme's own parser would refuse to read them back.
+    let single_chunk = args
+        .threshold
+        .saturating_sub(ancillary)
+        .saturating_sub(overhead)
+        .min(ParseOptions::DEFAULT_MAX_CHUNK_SIZE as u64);
+
+    writeln!(out, "File size: {} bytes", file_size)?;
+    writeln!(out, "Chunks: {}", png.chunks().len())?;
+    writeln!(out, "Ancillary data: {} bytes", ancillary)?;
+    writeln!(out, "Threshold: {} bytes added", args.threshold)?;
+    writeln!(
+        out,
+        "Estimated capacity: {} bytes in a single chunk ({} bytes overhead per chunk)",
+        single_chunk, overhead
+    )?;
+    writeln!(
+        out,
+        "File size at threshold: {} bytes",
+        file_size.saturating_add(args.threshold.saturating_sub(ancillary))
+    )?;
+    if ancillary > args.threshold {
+        writeln!(
+            err,
+            "Warning: the ancillary chunks already hold {} bytes, past the {}-byte threshold",
+            ancillary, args.threshold
+        )?;
+    }
+    Ok(())
+}
+
+/// A warning for chunk types whose reserved bit is set, which the PNG spec does not allow yet.
+fn reserved_bit_warning(chunk_type: &ChunkType) -> Option<String> {
+    if chunk_type.is_reserved_bit_valid() {
+        return None;
+    }
+    Some(format!(
+        "warning: chunk type '{}' has its reserved bit set (third letter should be uppercase); \
+         PNG readers may reject it",
+        chunk_type
+    ))
+}
+
+/// The URL `path` holds, if it is an `http://` or `https://` URL rather than a file.
+fn as_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Reads the file at `path`, or fetches it if `path` is a URL, naming it in any error.
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    read_input_from(&FileSystem, path)
+}
+
+/// `read_input`, reading files that are not URLs through `source`.
+fn read_input_from(source: &dyn PngSource, path: &Path) -> Result<Vec<u8>> {
+    let bytes: Result<Vec<u8>> = match as_url(path) {
+        #[cfg(feature = "http")]
+        Some(url) => crate::http::get(url),
+        #[cfg(not(feature = "http"))]
+        Some(_) => Err(Box::new(CommandError::HttpDisabled)),
+        None => source.read(path).map_err(Into::into),
+    };
+    bytes.map_err(|e| {
+        CommandError::Read {
+            path: path.to_path_buf(),
+            source: e,
+        }
+        .into()
+    })
+}
+
+/// How far streaming a file got in looking for a chunk.
+#[derive(Debug, PartialEq)]
+enum Streamed {
+    Found(Chunk),
+    /// The stream reached IEND without finding the chunk; holds the types it passed.
+    Missing(Vec<String>),
+    /// The file could not be streamed, or a chunk before IEND could not be read.
+    Unavailable,
+}
+
+/// Reads chunks from the file at `path` only until the first one of `chunk_type`, so
+/// decoding a chunk near the start of a large file reads none of the rest. If the file
+/// cannot be streamed cleanly to IEND the result is `Unavailable`, and the caller reads
+/// the whole file to report the problem properly.
+fn stream_chunk(source: &dyn PngSource, path: &Path, chunk_type: &ChunkType) -> Streamed {
+    if as_url(path).is_some() {
+        return Streamed::Unavailable;
+    }
+    match source.open(path) {
+        Ok(reader) => first_chunk_of_type(reader, chunk_type),
+        Err(_) => Streamed::Unavailable,
+    }
+}
+
+/// The first chunk of `chunk_type` in the PNG read from `reader`, reading nothing past it
+/// or past IEND.
+fn first_chunk_of_type<R: io::Read>(reader: R, chunk_type: &ChunkType) -> Streamed {
+    let Ok(chunks) = ChunkReader::png(reader) else {
+        return Streamed::Unavailable;
+    };
+    let mut present = Vec::new();
+    for chunk in chunks {
+        let Ok(chunk) = chunk else {
+            return Streamed::Unavailable;
+        };
+        if chunk.chunk_type() == chunk_type {
+            return Streamed::Found(chunk);
+        }
+        let is_end = chunk.chunk_type().to_string() == "IEND";
+        present.push(chunk.chunk_type().to_string());
+        if is_end {
+            return Streamed::Missing(present);
+        }
+    }
+    Streamed::Unavailable
+}
+
+/// Reads and parses the PNG at `path`, naming the file in any error.
+fn read_png(path: &Path) -> Result<Png> {
+    read_png_from(&FileSystem, path)
+}
+
+/// `read_png`, reading through `source`.
+fn read_png_from(source: &dyn PngSource, path: &Path) -> Result<Png> {
+    parse_png(path, &read_input_from(source, path)?)
+}
+
+/// Parses `bytes`, read from `path`, naming the file in any error.
+fn parse_png(path: &Path, bytes: &[u8]) -> Result<Png> {
+    Png::try_from(bytes).map_err(|e| {
+        CommandError::Parse {
+            path: path.to_path_buf(),
+            source: e,
+        }
+        .into()
+    })
+}
+
+/// Writes `png` to `path`. `source` is the file the PNG was read from; with
+/// `--preserve-timestamps` its access and modification times, captured before writing,
+/// are restored on the written file.
+fn write_png(path: &Path, png: &Png, source: &Path, options: &WriteOptions) -> Result<()> {
+    write_png_to(&FileSystem, path, png, source, options)
+}
+
+/// `write_png`, writing through `store`.
+fn write_png_to(
+    store: &dyn PngStore,
+    path: &Path,
+    png: &Png,
+    source: &Path,
+    options: &WriteOptions,
+) -> Result<()> {
+    if as_url(path).is_some() {
+        return Err(Box::new(CommandError::UrlNotWritable(path.to_path_buf())));
+    }
+    if options.dedupe_header {
+        png.check_single_instance()?;
+    }
+    if let Some(length) = png.trailing_data().filter(|_| !options.drop_trailing_data) {
+        return Err(Box::new(CommandError::TrailingData {
+            path: source.to_path_buf(),
+            length,
+        }));
+    }
+    let times = if options.preserve_timestamps {
+        store.times(source)?
+    } else {
+        None
+    };
+
+    let write = || -> std::io::Result<()> {
+        if options.replace_symlinks {
+            store.unlink_symlink(path)?;
+        }
+        store.write(path, &png.as_bytes())?;
+        if let Some(times) = times {
+            store.set_times(path, times)?;
+        }
+        Ok(())
+    };
+    write().map_err(|e| CommandError::Write {
+        path: path.to_path_buf(),
+        source: e.into(),
+    })?;
+    if options.verify_after_write {
+        verify_written(store, path, png)?;
+    }
+    Ok(())
+}
+
+/// For `--verify-after-write`: re-reads `path` and checks that it parses, so every CRC is
+/// valid, and that it has exactly the chunks of `expected`. That covers an encoded chunk
+/// being present and a removed one being gone.
+fn verify_written(source: &dyn PngSource, path: &Path, expected: &Png) -> Result<()> {
+    let fail = |reason: String| CommandError::VerifyFailed {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let written = read_png_from(source, path).map_err(|e| fail(e.to_string()))?;
+    let expected = expected.chunks();
+    let written = written.chunks();
+    if let Some(index) =
+        (0..expected.len().max(written.len())).find(|&i| expected.get(i) != written.get(i))
+    {
+        let describe = |chunk: Option<&Chunk>| {
+            chunk.map_or("missing".to_string(), |chunk| {
+                chunk.chunk_type().to_string()
+            })
+        };
+        return Err(Box::new(fail(format!(
+            "chunk {} should be {} but is {}",
+            index,
+            describe(expected.get(index)),
+            describe(written.get(index))
+        ))));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+    use crate::storage::MemoryStore;
+    use std::str::FromStr;
+
+    /// A copy of the test image in a fresh temporary directory, removed on drop.
+    pub(crate) struct TempPng {
+        pub(crate) dir: PathBuf,
+        pub(crate) path: PathBuf,
+    }
+
+    impl TempPng {
+        pub(crate) fn new(name: &str) -> TempPng {
+            let dir = std::env::temp_dir().join(format!("pngme-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("image.png");
+            fs::write(&path, PNG_FILE).unwrap();
+            TempPng { dir, path }
+        }
+    }
+
+    impl Drop for TempPng {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn encode_args(path: &Path, chunk_type: &str, message: &str) -> EncodeArgs {
+        EncodeArgs {
+            file_path: path.to_path_buf(),
+            chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+            message: message.to_string(),
+            output_file: None,
+            random_type: false,
+            type_from: None,
+            keyword: "Comment".to_string(),
+            from_png: None,
+            message_file: None,
+            ascii_only: false,
+            append_only: false,
+            append: false,
+            pipe: None,
+            lsb: false,
+            max_size: None,
+            split_on: None,
+            write: WriteOptions::default(),
+        }
+    }
+
+    fn decode_args(path: &Path, chunk_type: &str) -> DecodeArgs {
+        DecodeArgs {
+            file_path: path.to_path_buf(),
+            chunk_type: Some(ChunkType::from_str(chunk_type).unwrap()),
+            as_png: false,
+            to_file: None,
+            count: false,
+            literal: None,
+            raw: false,
+            pipe: None,
+            lsb: false,
+            limit: None,
+            no_newline: false,
+            quiet_not_found: false,
+            all: false,
+        }
+    }
+
+    fn print_args(path: &Path) -> PrintArgs {
+        PrintArgs {
+            file_paths: vec![path.to_path_buf()],
+            pretty: false,
+            expand_idat: false,
+            allow_duplicate_signature: false,
+            detect: false,
+            offset: None,
+            scan: false,
+            error_on_warning: false,
+            raw_text: false,
+            table: false,
+            no_summary: false,
+            entropy: false,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let png = TempPng::new("round-trip");
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        let args = decode_args(&png.path, "ruSt");
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "hidden\n");
+    }
+
+    #[test]
+    fn test_encode_decode_type_from_label() {
+        let png = TempPng::new("type-from");
+        let parse = |line: String| {
+            crate::args::PngMeArgs::parse(line.split(' ').map(|s| s.to_string())).unwrap()
+        };
+        let path = png.path.to_str().unwrap();
+
+        let crate::args::PngMeArgs::Encode(args) = parse(format!(
+            "encode {} --type-from project-x labelled --in-place",
+            path
+        )) else {
+            panic!("expected encode args");
+        };
+        let mut out = Vec::new();
+        encode(args, &mut out, &mut Vec::new()).unwrap();
+        let chunk_type = ChunkType::from_label("project-x");
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with(&format!("Chunk type: {}\n", chunk_type)));
+
+        let crate::args::PngMeArgs::Decode(args) =
+            parse(format!("decode {} --type-from project-x", path))
+        else {
+            panic!("expected decode args");
+        };
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "labelled\n");
+    }
+
+    #[test]
+    fn test_encode_random_type() {
+        let png = TempPng::new("random-type");
+        let args = crate::args::PngMeArgs::parse(
+            [
+                "encode",
+                png.path.to_str().unwrap(),
+                "hidden",
+                "--random-type",
+                "--in-place",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+        let crate::args::PngMeArgs::Encode(args) = args else {
+            panic!("expected encode args");
+        };
+
+        let mut out = Vec::new();
+        encode(args, &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let reported = out
+            .lines()
+            .find_map(|line| line.strip_prefix("Chunk type: "))
+            .unwrap();
+        let chunk_type = ChunkType::from_str(reported).unwrap();
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+
+        let mut decoded = Vec::new();
+        let args = DecodeArgs {
+            chunk_type: Some(chunk_type),
+            ..decode_args(&png.path, "ruSt")
+        };
+        decode(args, &mut decoded, &mut Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(decoded).unwrap(), "hidden\n");
+    }
+
+    #[test]
+    fn test_encode_split_on_decode_all() {
+        let png = TempPng::new("split-on");
+        let mut args = encode_args(&png.path, "ruSt", "a|b|c");
+        args.split_on = Some("|".to_string());
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+
+        let image = read_png(&png.path).unwrap();
+        let data: Vec<&[u8]> = image.chunks_by_type("ruSt").map(Chunk::data).collect();
+        assert_eq!(data, [&b"a"[..], b"b", b"c"]);
+
+        let mut args = decode_args(&png.path, "ruSt");
+        args.all = true;
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"a\nb\nc\n");
+
+        // Without --all only the first field is printed.
+        let mut out = Vec::new();
+        decode(decode_args(&png.path, "ruSt"), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"a\n");
+    }
+
+    #[test]
+    fn test_encode_split_on_keeps_empty_fields() {
+        let png = TempPng::new("split-on-empty");
+        let mut args = encode_args(&png.path, "teSt", "::x::");
+        args.split_on = Some("::".to_string());
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            read_png(&png.path).unwrap().chunks_by_type("teSt").count(),
+            3
+        );
+
+        let mut args = decode_args(&png.path, "teSt");
+        args.all = true;
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"\nx\n\n");
+
+        let mut args = decode_args(&png.path, "ruSt");
+        args.all = true;
+        assert!(decode(args, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_encode_max_size() {
+        let png = TempPng::new("max-size");
+        let mut args = encode_args(&png.path, "ruSt", "twelve bytes");
+        args.max_size = Some(11);
+        let err = encode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::TooLarge {
+                size: 12,
+                limit: 11
+            })
+        ));
+        assert_eq!(fs::read(&png.path).unwrap(), PNG_FILE);
+
+        let mut args = encode_args(&png.path, "ruSt", "twelve bytes");
+        args.max_size = Some(12);
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_some());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_encode_decode_ztxt() {
+        let png = TempPng::new("ztxt");
+        let message = "compressed compressed compressed compressed";
+        encode(
+            encode_args(&png.path, "zTXt", message),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let image = read_png(&png.path).unwrap();
+        let chunk = image.chunk_by_type("zTXt").unwrap();
+        assert!(chunk.data().starts_with(b"Comment\0\0"));
+        assert!((chunk.length() as usize) < message.len());
+
+        let mut out = Vec::new();
+        let args = decode_args(&png.path, "zTXt");
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", message));
+    }
+
+    #[test]
+    fn test_encode_ascii_only() {
+        let png = TempPng::new("ascii-only");
+        let mut args = encode_args(&png.path, "ruSt", "hello 🦀");
+        args.ascii_only = true;
+        let err = encode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("'🦀'"));
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_none());
+
+        let mut args = encode_args(&png.path, "ruSt", "tab\there");
+        args.ascii_only = true;
+        assert!(encode(args, &mut Vec::new(), &mut Vec::new()).is_err());
+
+        let mut args = encode_args(&png.path, "ruSt", "plain ascii");
+        args.ascii_only = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+
+        // Without the flag anything UTF-8 is fine.
+        let args = encode_args(&png.path, "ruSt", "hello 🦀");
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_encode_decode_lsb() {
+        // A 16x16 grayscale image: each scanline is filter type 0 and 16 samples.
+        let png = TempPng::new("lsb");
+        let raw: Vec<u8> = (0..16 * 17).map(|i| (i % 17 * 15) as u8).collect();
+        let image = crate::png::PngBuilder::new(16, 16)
+            .color_type(0)
+            .idat(crate::zlib::compress(&raw))
+            .build();
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let mut args = encode_args(&png.path, "IDAT", "in the pixels");
+        args.lsb = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        let image = read_png(&png.path).unwrap();
+        image.validate_structure().unwrap();
+        assert_eq!(image.chunks().len(), 3);
+
+        let args = DecodeArgs {
+            chunk_type: None,
+            lsb: true,
+            ..decode_args(&png.path, "ruSt")
+        };
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "in the pixels\n");
+
+        // A binary message comes back whole through --to-file.
+        let payload = png.dir.join("payload.bin");
+        fs::write(&payload, [0xff, 0x00, 0xfe]).unwrap();
+        let mut args = encode_args(&png.path, "IDAT", "");
+        args.lsb = true;
+        args.message_file = Some(payload.clone());
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        let extracted = png.dir.join("extracted.bin");
+        let args = DecodeArgs {
+            chunk_type: None,
+            lsb: true,
+            to_file: Some(extracted.clone()),
+            ..decode_args(&png.path, "ruSt")
+        };
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("Wrote 3 bytes"));
+        assert_eq!(fs::read(&extracted).unwrap(), [0xff, 0x00, 0xfe]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_pipe_through_external_command() {
+        let png = TempPng::new("pipe");
+        let mut args = encode_args(&png.path, "ruSt", "shout this");
+        args.pipe = Some("tr a-z A-Z".to_string());
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        let image = read_png(&png.path).unwrap();
+        assert_eq!(image.chunk_by_type("ruSt").unwrap().data(), b"SHOUT THIS");
+
+        let args = DecodeArgs {
+            pipe: Some("tr A-Z a-z".to_string()),
+            ..decode_args(&png.path, "ruSt")
+        };
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"shout this");
+    }
+
+    #[test]
+    fn test_pipe_through_failures() {
+        #[cfg(unix)]
+        {
+            let err = pipe_through("false", b"input").unwrap_err();
+            assert!(err.to_string().starts_with("--pipe 'false' failed"));
+        }
+        assert!(pipe_through("pngme-no-such-command", b"").is_err());
+        assert!(pipe_through("  ", b"").is_err());
+    }
+
+    #[test]
+    fn test_verify_after_write() {
+        let png = TempPng::new("verify-write");
+        let mut args = encode_args(&png.path, "ruSt", "checked");
+        args.write.verify_after_write = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+
+        let mut expected = read_png(&png.path).unwrap();
+        verify_written(&FileSystem, &png.path, &expected).unwrap();
+
+        // A write that lost the new chunk.
+        fs::write(&png.path, PNG_FILE).unwrap();
+        let err = verify_written(&FileSystem, &png.path, &expected).unwrap_err();
+        assert!(err.to_string().ends_with("should be ruSt but is IEND"));
+
+        // A write that corrupted a byte, breaking a CRC.
+        let mut bytes = expected.as_bytes();
+        let last = bytes.len() - 20;
+        bytes[last] ^= 0xff;
+        fs::write(&png.path, bytes).unwrap();
+        assert!(verify_written(&FileSystem, &png.path, &expected).is_err());
+
+        // A removed chunk must really be gone.
+        fs::write(&png.path, expected.as_bytes()).unwrap();
+        expected.remove_first_chunk("ruSt").unwrap();
+        let err = verify_written(&FileSystem, &png.path, &expected).unwrap_err();
+        assert!(err.to_string().contains("should be IEND but is ruSt"));
+    }
+
+    #[test]
+    fn test_dedupe_header_refuses_duplicate_ihdr() {
+        let png = TempPng::new("dedupe-header");
+        let mut args = encode_args(&png.path, "IHDR", "second header");
+        args.write.dedupe_header = true;
+        let err = encode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("IHDR may only appear once"));
+        assert_eq!(fs::read(&png.path).unwrap(), PNG_FILE);
+
+        let args = encode_args(&png.path, "IHDR", "second header");
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            read_png(&png.path).unwrap().chunks_by_type("IHDR").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_write_refuses_to_drop_trailing_data() {
+        let png = TempPng::new("trailing-data");
+        let mut polyglot = PNG_FILE.to_vec();
+        polyglot.extend_from_slice(b"PK\x03\x04 an appended zip archive");
+        fs::write(&png.path, &polyglot).unwrap();
+
+        let err = encode(
+            encode_args(&png.path, "ruSt", "hi"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::TrailingData { length: 28, .. })
+        ));
+        assert_eq!(fs::read(&png.path).unwrap(), polyglot);
+
+        let (normalized, _) = repair::normalize(read_png(&png.path).unwrap());
+        assert_eq!(normalized.trailing_data(), Some(28));
+
+        let mut args = encode_args(&png.path, "ruSt", "hi");
+        args.write.drop_trailing_data = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert!(read_png(&png.path).unwrap().trailing_data().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_follows_symlinks_by_default() {
+        let png = TempPng::new("follow-symlinks");
+        let link = png.dir.join("link.png");
+        std::os::unix::fs::symlink(&png.path, &link).unwrap();
+
+        encode(
+            encode_args(&link, "ruSt", "through the link"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_replaces_symlink() {
+        let png = TempPng::new("no-follow-symlinks");
+        let link = png.dir.join("link.png");
+        std::os::unix::fs::symlink(&png.path, &link).unwrap();
+
+        let mut args = encode_args(&link, "ruSt", "replaces the link");
+        args.write.replace_symlinks = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_file());
+        assert!(read_png(&link).unwrap().chunk_by_type("ruSt").is_some());
+        assert_eq!(fs::read(&png.path).unwrap(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_encode_append_accumulates() {
+        let png = TempPng::new("append");
+        for message in ["running", " note", " grows"] {
+            let mut args = encode_args(&png.path, "ruSt", message);
+            args.append = true;
+            encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        }
+
+        let image = read_png(&png.path).unwrap();
+        assert_eq!(image.chunks_by_type("ruSt").count(), 1);
+        let mut out = Vec::new();
+        decode(decode_args(&png.path, "ruSt"), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"running note grows\n");
+    }
+
+    #[test]
+    fn test_encode_append_rejects_ztxt_and_critical() {
+        let png = TempPng::new("append-reject");
+        for chunk_type in ["zTXt", "IDAT"] {
+            let mut args = encode_args(&png.path, chunk_type, "more");
+            args.append = true;
+            let err = encode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(CommandError::CannotAppend(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_encode_append_only() {
+        let png = TempPng::new("append-only");
+        let mut args = encode_args(&png.path, "ruSt", "ancillary is fine");
+        args.append_only = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_some());
+
+        let mut args = encode_args(&png.path, "IDAT", "not image data");
+        args.append_only = true;
+        let err = encode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::CriticalChunkChanged(chunk_type)) if chunk_type == "IDAT"
+        ));
+        assert_eq!(
+            read_png(&png.path).unwrap().chunks_by_type("IDAT").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_check_append_only_rejects_changed_idat() {
+        let original = Png::try_from(&PNG_FILE[..]).unwrap();
+        let before = critical_chunks(&original);
+
+        let mut added = Png::try_from(&PNG_FILE[..]).unwrap();
+        added.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![1]));
+        check_append_only(&before, &added).unwrap();
+
+        let chunks = original
+            .into_chunks()
+            .into_iter()
+            .map(|chunk| match &chunk.chunk_type().bytes() {
+                b"IDAT" => Chunk::new(chunk.chunk_type().clone(), vec![0; 4]),
+                _ => chunk,
+            })
+            .collect();
+        let err = check_append_only(&before, &Png::from_chunks(chunks)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "refusing to change critical chunk IDAT (--append-only)"
+        );
+    }
+
+    #[test]
+    fn test_embedded_png_round_trip() {
+        let carrier = TempPng::new("png-in-png");
+        let inner = carrier.dir.join("inner.png");
+        fs::write(&inner, PNG_FILE).unwrap();
+
+        let mut args = encode_args(&carrier.path, "ruSt", "");
+        args.from_png = Some(inner);
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+
+        let extracted = carrier.dir.join("extracted.png");
+        let args = DecodeArgs {
+            as_png: true,
+            to_file: Some(extracted.clone()),
+            ..decode_args(&carrier.path, "ruSt")
+        };
+        let mut err = Vec::new();
+        decode(args, &mut Vec::new(), &mut err).unwrap();
+
+        assert!(err.is_empty());
+        assert_eq!(fs::read(&extracted).unwrap(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_decode_as_png_warns_on_non_png() {
+        let png = TempPng::new("png-in-png-warn");
+        encode(
+            encode_args(&png.path, "ruSt", "not an image"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let args = DecodeArgs {
+            as_png: true,
+            to_file: Some(png.dir.join("inner.png")),
+            ..decode_args(&png.path, "ruSt")
+        };
+        let mut err = Vec::new();
+        decode(args, &mut Vec::new(), &mut err).unwrap();
+        assert!(String::from_utf8(err)
+            .unwrap()
+            .contains("does not hold a valid PNG"));
+    }
+
+    #[test]
+    fn test_encode_from_png_rejects_non_png() {
+        let png = TempPng::new("png-in-png-reject");
+        let inner = png.dir.join("inner.png");
+        fs::write(&inner, b"plain text").unwrap();
+
+        let mut args = encode_args(&png.path, "ruSt", "");
+        args.from_png = Some(inner);
+        assert!(encode(args, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_encode_warns_on_reserved_bit() {
+        let png = TempPng::new("reserved-warn");
+        let mut err = Vec::new();
+        encode(
+            encode_args(&png.path, "Rust", "hidden"),
+            &mut Vec::new(),
+            &mut err,
+        )
+        .unwrap();
+
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.contains("warning"));
+        assert!(err.contains("'Rust'"));
+        assert!(read_png(&png.path).unwrap().chunk_by_type("Rust").is_some());
+    }
+
+    #[test]
+    fn test_encode_no_warning_for_valid_reserved_bit() {
+        let png = TempPng::new("reserved-ok");
+        let mut err = Vec::new();
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut err,
+        )
+        .unwrap();
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn test_encode_keeps_apng_frame_order() {
+        let png = TempPng::new("apng");
+        let frames = ["acTL", "fcTL", "IDAT", "fcTL", "fdAT"];
+        let mut chunks = read_png(&png.path).unwrap().chunks().to_vec();
+        chunks.retain(|chunk| chunk.chunk_type().to_string() != "IDAT");
+        for (i, frame) in frames.iter().enumerate() {
+            let chunk_type = ChunkType::from_str(frame).unwrap();
+            chunks.insert(i + 1, Chunk::new(chunk_type, vec![i as u8]));
+        }
+        fs::write(&png.path, Png::from_chunks(chunks).as_bytes()).unwrap();
+
+        let mut err = Vec::new();
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut err,
+        )
+        .unwrap();
+        assert!(err.is_empty());
+
+        let encoded = read_png(&png.path).unwrap();
+        let order: Vec<String> = encoded
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .filter(|chunk_type| frames.contains(&chunk_type.as_str()))
+            .collect();
+        assert_eq!(order, frames);
+        let last = encoded.chunks().len() - 1;
+        assert_eq!(encoded.chunks()[last - 1].chunk_type().to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_encode_warns_inside_apng_frame() {
+        let png = TempPng::new("apng-warn");
+        // A truncated animation whose last fcTL has no frame data before IEND.
+        let mut chunks = read_png(&png.path).unwrap().chunks().to_vec();
+        let last = chunks.len() - 1;
+        for (i, frame) in ["acTL", "fcTL"].iter().enumerate() {
+            let chunk_type = ChunkType::from_str(frame).unwrap();
+            chunks.insert(last + i, Chunk::new(chunk_type, Vec::new()));
+        }
+        fs::write(&png.path, Png::from_chunks(chunks).as_bytes()).unwrap();
+
+        let mut err = Vec::new();
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut err,
+        )
+        .unwrap();
+        assert!(String::from_utf8(err).unwrap().contains("APNG frame"));
+    }
+
+    #[test]
+    fn test_encode_preserve_timestamps() {
+        let png = TempPng::new("preserve-timestamps");
+        let past =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&png.path)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_accessed(past).set_modified(past))
+            .unwrap();
+
+        let mut args = encode_args(&png.path, "ruSt", "hidden");
+        args.write.preserve_timestamps = true;
+        encode(args, &mut Vec::new(), &mut Vec::new()).unwrap();
+
+        let metadata = fs::metadata(&png.path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), past);
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_remove() {
+        let png = TempPng::new("remove");
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let args = RemoveArgs {
+            file_path: png.path.clone(),
+            chunk_type: ChunkType::from_str("ruSt").unwrap(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        remove(args, &mut Vec::new()).unwrap();
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_rename_chunk() {
+        let png = TempPng::new("rename-chunk");
+        encode(
+            encode_args(&png.path, "ruSt", "misfiled"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let args = |old_type: &str| RenameChunkArgs {
+            file_path: png.path.clone(),
+            old_type: ChunkType::from_str(old_type).unwrap(),
+            new_type: ChunkType::from_str("teSt").unwrap(),
+            all: false,
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        rename_chunk(args("ruSt"), &mut out).unwrap();
+        assert_eq!(out, b"Renamed ruSt chunk to teSt\n");
+
+        let mut out = Vec::new();
+        decode(decode_args(&png.path, "teSt"), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"misfiled\n");
+        assert!(read_png(&png.path).unwrap().chunk_by_type("ruSt").is_none());
+
+        let err = rename_chunk(args("ruSt"), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::ChunkNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rename_chunk_refuses_critical_types() {
+        let png = TempPng::new("rename-critical");
+        encode(
+            encode_args(&png.path, "ruSt", "kept"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let before = fs::read(&png.path).unwrap();
+
+        for (old_type, new_type) in [("IDAT", "ruSt"), ("ruSt", "IDAT"), ("ruSt", "PLTE")] {
+            let args = RenameChunkArgs {
+                file_path: png.path.clone(),
+                old_type: ChunkType::from_str(old_type).unwrap(),
+                new_type: ChunkType::from_str(new_type).unwrap(),
+                all: false,
+                output_file: None,
+                write: WriteOptions::default(),
+            };
+            let err = rename_chunk(args, &mut Vec::new()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(CommandError::CriticalRename(chunk_type)) if chunk_type.is_critical()
+            ));
+        }
+        assert_eq!(fs::read(&png.path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_obfuscate() {
+        let png = TempPng::new("obfuscate");
+        let mut image = read_png(&png.path).unwrap();
+        let output = png.dir.join("obfuscated.png");
+        let args = || ObfuscateArgs {
+            file_path: png.path.clone(),
+            seed: Some(3),
+            output_file: Some(output.clone()),
+            write: WriteOptions::default(),
+        };
+
+        let mut out = Vec::new();
+        obfuscate(args(), &mut out).unwrap();
+        assert_eq!(out, b"No custom chunks to obfuscate\n");
+        assert!(!output.exists());
+
+        for data in [&b"secret"[..], b"more"] {
+            image.append_chunk(Chunk::new(
+                ChunkType::from_str("ruSt").unwrap(),
+                data.to_vec(),
+            ));
+        }
+        image.append_chunk(Chunk::new_text("Title", "public").unwrap());
+        write_png(&png.path, &image, &png.path, &WriteOptions::default()).unwrap();
+
+        let mut out = Vec::new();
+        obfuscate(args(), &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let new_type = out.trim().strip_prefix("ruSt -> ").unwrap();
+        let new_type = ChunkType::from_str(new_type).unwrap();
+        assert!(new_type.is_valid() && !new_type.is_critical() && !new_type.is_standard());
+
+        let obfuscated = read_png(&output).unwrap();
+        assert!(obfuscated.chunk_by_type("ruSt").is_none());
+        let chunk = obfuscated.chunk_by_type(&new_type.to_string()).unwrap();
+        assert_eq!(chunk.data(), b"secret");
+        assert!(obfuscated.chunk_by_type("tEXt").is_some());
+        assert_eq!(read_png(&png.path).unwrap().as_bytes(), image.as_bytes());
+
+        let mut out = Vec::new();
+        let args = RenameChunkArgs {
+            file_path: output.clone(),
+            old_type: new_type.clone(),
+            new_type: ChunkType::from_str("ruSt").unwrap(),
+            all: true,
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        rename_chunk(args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("Renamed 2 {} chunk(s) to ruSt\n", new_type)
+        );
+        assert_eq!(read_png(&output).unwrap().as_bytes(), image.as_bytes());
+    }
+
+    #[test]
+    fn test_obfuscate_keeps_property_bits() {
+        let png = TempPng::new("obfuscate-bits");
+        let mut image = read_png(&png.path).unwrap();
+        let originals = ["ruSt", "ruST", "rUSt", "rUST"];
+        for name in originals {
+            image.append_chunk(Chunk::new(
+                ChunkType::from_str(name).unwrap(),
+                name.as_bytes().to_vec(),
+            ));
+        }
+        write_png(&png.path, &image, &png.path, &WriteOptions::default()).unwrap();
+
+        for seed in 0..20 {
+            let mut out = Vec::new();
+            let args = ObfuscateArgs {
+                file_path: png.path.clone(),
+                seed: Some(seed),
+                output_file: Some(png.dir.join("obfuscated.png")),
+                write: WriteOptions::default(),
+            };
+            obfuscate(args, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), originals.len());
+            for line in out.lines() {
+                let (old, new) = line.split_once(" -> ").unwrap();
+                let (old, new) = (
+                    ChunkType::from_str(old).unwrap(),
+                    ChunkType::from_str(new).unwrap(),
+                );
+                assert_eq!(new.is_public(), old.is_public(), "{}", line);
+                assert_eq!(new.is_safe_to_copy(), old.is_safe_to_copy(), "{}", line);
+                assert!(!new.is_critical() && new.is_reserved_bit_valid());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_without_type_finds_message() {
+        let png = TempPng::new("decode-find");
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::new_text("Title", "a public note").unwrap());
+        image.append_chunk(Chunk::new(
+            ChunkType::from_str("biNa").unwrap(),
+            vec![0, 1, 2],
+        ));
+        fs::write(&png.path, image.as_bytes()).unwrap();
+        encode(
+            encode_args(&png.path, "ruSt", "hidden in plain sight"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let decode_all = || {
+            let args = DecodeArgs {
+                chunk_type: None,
+                ..decode_args(&png.path, "ruSt")
+            };
+            let mut out = Vec::new();
+            decode(args, &mut out, &mut Vec::new()).map(|()| String::from_utf8(out).unwrap())
+        };
+        assert_eq!(decode_all().unwrap(), "hidden in plain sight\n");
+
+        // A second private chunk leaves no single likely message, so both are listed.
+        encode(
+            encode_args(&png.path, "prIv", "another one"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let listing = decode_all().unwrap();
+        assert!(listing.starts_with("3 chunks hold text"));
+        assert!(listing.contains("  ruSt  hidden in plain sight\n"));
+        assert!(listing.contains("  prIv  another one\n"));
+        assert!(listing.contains("  tEXt  a public note\n"));
+    }
+
+    #[test]
+    fn test_decode_without_type_no_text() {
+        let png = TempPng::new("decode-find-none");
+        let args = DecodeArgs {
+            chunk_type: None,
+            ..decode_args(&png.path, "ruSt")
+        };
+        let err = decode(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CommandError::NoMessage)));
+    }
+
+    #[test]
+    fn test_decode_limit() {
+        let png = TempPng::new("decode-limit");
+        encode(
+            encode_args(&png.path, "ruSt", "héllo, this message goes on"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let decode_limited = |limit, as_png| {
+            let args = DecodeArgs {
+                as_png,
+                limit,
+                ..decode_args(&png.path, "ruSt")
+            };
+            let mut out = Vec::new();
+            decode(args, &mut out, &mut Vec::new()).unwrap();
+            out
+        };
+        // Text is cut at characters, so the two-byte 'é' counts once.
+        assert_eq!(decode_limited(Some(5), false), "héllo\n".as_bytes());
+        assert_eq!(
+            decode_limited(Some(500), false),
+            "héllo, this message goes on\n".as_bytes()
+        );
+        // Raw output is cut at bytes.
+        assert_eq!(decode_limited(Some(2), true), "h\u{c3}".as_bytes()[..2]);
+        assert_eq!(decode_limited(Some(0), true), b"");
+    }
+
+    #[test]
+    fn test_decode_no_newline() {
+        let png = TempPng::new("decode-no-newline");
+        encode(
+            encode_args(&png.path, "ruSt", "no newline"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let mut args = decode_args(&png.path, "ruSt");
+        args.no_newline = true;
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"no newline");
+
+        let mut out = Vec::new();
+        decode(decode_args(&png.path, "ruSt"), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"no newline\n");
+    }
+
+    #[test]
+    fn test_decode_count() {
+        let png = TempPng::new("decode-count");
+        let mut image = read_png(&png.path).unwrap();
+        for data in [vec![0xff, 0xfe], vec![0x80], vec![]] {
+            image.append_chunk(Chunk::new(ChunkType::from_str("biNa").unwrap(), data));
+        }
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let decode_count = |chunk_type: &str| {
+            let args = DecodeArgs {
+                count: true,
+                ..decode_args(&png.path, chunk_type)
+            };
+            let mut out = Vec::new();
+            decode(args, &mut out, &mut Vec::new()).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // The chunk data is not UTF-8, but counting never reads it as a string.
+        assert_eq!(decode_count("biNa"), "3\n");
+        assert_eq!(decode_count("ruSt"), "0\n");
+    }
+
+    #[test]
+    fn test_decode_as_rust() {
+        let png = TempPng::new("decode-as-rust");
+        encode(
+            encode_args(&png.path, "ruSt", "Rust"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let args = DecodeArgs {
+            literal: Some(ByteLiteral::Rust),
+            ..decode_args(&png.path, "ruSt")
+        };
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "&[0x52, 0x75, 0x73, 0x74]\n"
+        );
+    }
+
+    #[test]
+    fn test_byte_literal_wraps() {
+        let data: Vec<u8> = (0..14).collect();
+        assert_eq!(
+            byte_literal(&data, ByteLiteral::C),
+            "const unsigned char data[14] = {\n    \
+             0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,\n    \
+             0x0c, 0x0d,\n};"
+        );
+        assert_eq!(byte_literal(&[], ByteLiteral::Rust), "&[]");
+    }
+
+    #[test]
+    fn test_decode_missing_chunk() {
+        let png = TempPng::new("decode-missing");
+        let args = decode_args(&png.path, "ruSt");
+        assert!(decode(args, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+
+    /// A reader that fails every read, standing in for input that must not be reached.
+    struct Unreachable;
+
+    impl io::Read for Unreachable {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past the target chunk"))
+        }
+    }
+
+    #[test]
+    fn test_first_chunk_of_type_stops_at_target() {
+        let chunk = Chunk::try_from_parts("ruSt", b"early".to_vec()).unwrap();
+        // The signature and IHDR, then the target chunk, then nothing readable.
+        let mut bytes = PNG_FILE[..33].to_vec();
+        bytes.extend(chunk.as_bytes());
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let found =
+            first_chunk_of_type(io::Read::chain(bytes.as_slice(), Unreachable), &chunk_type);
+        assert_eq!(found, Streamed::Found(chunk));
+
+        let missing = ChunkType::from_str("teSt").unwrap();
+        assert_eq!(
+            first_chunk_of_type(io::Read::chain(bytes.as_slice(), Unreachable), &missing),
+            Streamed::Unavailable
+        );
+    }
+
+    #[test]
+    fn test_first_chunk_of_type_stops_at_iend() {
+        let missing = ChunkType::from_str("teSt").unwrap();
+        assert_eq!(
+            first_chunk_of_type(io::Read::chain(&PNG_FILE[..], Unreachable), &missing),
+            Streamed::Missing(vec!["IHDR".into(), "IDAT".into(), "IEND".into()])
+        );
+    }
+
+    #[test]
+    fn test_decode_ignores_damage_after_target() {
+        let png = TempPng::new("decode-stream");
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let mut bytes = fs::read(&png.path).unwrap();
+        // Break the CRC of IEND, the last chunk; the ruSt chunk comes before it.
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&png.path, bytes).unwrap();
+
+        let mut out = Vec::new();
+        decode(decode_args(&png.path, "ruSt"), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"hidden\n");
+
+        let err = decode(
+            decode_args(&png.path, "teSt"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_commands_with_memory_store() {
+        let store = MemoryStore::new();
+        let input = PathBuf::from("memory/image.png");
+        let output = PathBuf::from("memory/encoded.png");
+        store.insert(&input, PNG_FILE.to_vec());
+
+        let mut args = encode_args(&input, "ruSt", "in memory");
+        args.output_file = Some(output.clone());
+        args.write.verify_after_write = true;
+        let mut out = Vec::new();
+        encode_with(&store, args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(out, b"Encoded message into memory/encoded.png\n");
+        assert_eq!(store.get(&input).unwrap(), PNG_FILE);
+
+        let mut out = Vec::new();
+        decode_with(
+            &store,
+            decode_args(&output, "ruSt"),
+            &mut out,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(out, b"in memory\n");
+
+        let extracted = PathBuf::from("memory/message.txt");
+        let args = DecodeArgs {
+            to_file: Some(extracted.clone()),
+            ..decode_args(&output, "ruSt")
+        };
+        decode_with(&store, args, &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert_eq!(store.get(&extracted).unwrap(), b"in memory");
+
+        let args = RenameChunkArgs {
+            file_path: output.clone(),
+            old_type: ChunkType::from_str("ruSt").unwrap(),
+            new_type: ChunkType::from_str("teSt").unwrap(),
+            all: false,
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        rename_chunk_with(&store, args, &mut Vec::new()).unwrap();
+        let args = HasArgs {
+            file_path: output.clone(),
+            chunk_type: ChunkType::from_str("teSt").unwrap(),
+            verbose: false,
+        };
+        assert!(has_with(&store, args, &mut Vec::new()).unwrap());
+        let args = VerifyArgs {
+            file_path: output.clone(),
+        };
+        verify_with(&store, args, &mut Vec::new()).unwrap();
+
+        let args = RemoveArgs {
+            file_path: output.clone(),
+            chunk_type: ChunkType::from_str("teSt").unwrap(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        remove_with(&store, args, &mut Vec::new()).unwrap();
+        assert_eq!(store.get(&output).unwrap(), PNG_FILE);
+
+        let missing = encode_args(Path::new("memory/missing.png"), "ruSt", "x");
+        assert!(encode_with(&store, missing, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_decode_quiet_not_found() {
+        let png = TempPng::new("decode-quiet");
+        let mut args = decode_args(&png.path, "ruSt");
+        args.quiet_not_found = true;
+        let mut out = Vec::new();
+        decode(args, &mut out, &mut Vec::new()).unwrap();
+        assert!(out.is_empty());
+
+        // Only a missing chunk is quiet; a file that is not a PNG still fails.
+        fs::write(&png.path, b"not a png").unwrap();
+        let mut args = decode_args(&png.path, "ruSt");
+        args.quiet_not_found = true;
+        assert!(decode(args, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_missing_chunk_suggests_near_miss() {
+        let png = TempPng::new("suggest");
+        encode(
+            encode_args(&png.path, "ruSt", "hidden"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let decode_err = |chunk_type: &str| {
+            let args = decode_args(&png.path, chunk_type);
+            decode(args, &mut Vec::new(), &mut Vec::new())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            decode_err("ruSr"),
+            "Chunk not found: ruSr (did you mean 'ruSt'?)"
+        );
+        assert_eq!(decode_err("qqQq"), "Chunk not found: qqQq");
+
+        let args = RemoveArgs {
+            file_path: png.path.clone(),
+            chunk_type: ChunkType::from_str("ruST").unwrap(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        let err = remove(args, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().ends_with("(did you mean 'ruSt'?)"));
+    }
+
+    #[test]
+    fn test_has() {
+        let png = TempPng::new("has");
+        let has_args = |chunk_type: &str, verbose| HasArgs {
+            file_path: png.path.clone(),
+            chunk_type: ChunkType::from_str(chunk_type).unwrap(),
+            verbose,
+        };
+
+        let mut out = Vec::new();
+        assert!(has(has_args("IDAT", false), &mut out).unwrap());
+        assert!(!has(has_args("ruSt", false), &mut out).unwrap());
+        assert!(out.is_empty());
+
+        has(has_args("IDAT", true), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("1 IDAT chunk(s)"));
+    }
+
+    #[test]
+    fn test_gen_type_is_deterministic() {
+        let generate = |seed| {
+            let mut out = Vec::new();
+            gen_type(GenTypeArgs { seed: Some(seed) }, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let first = generate(7);
+        assert_eq!(first, generate(7));
+        let chunk_type = ChunkType::from_str(first.trim_end()).unwrap();
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+    }
+
+    #[test]
+    fn test_info() {
+        let png = TempPng::new("info");
+        let mut out = Vec::new();
+        info(
+            InfoArgs {
+                file_path: png.path.clone(),
+            },
+            &mut out,
+        )
+        .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Dimensions: 1x1"));
+        assert!(out.contains("Color type: 2 (truecolor)"));
+        assert!(out.contains("Interlace: none"));
+        assert!(!out.contains("Pass 1"));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = TempPng::new("manifest");
+        fs::write(dir.dir.join("second.png"), PNG_FILE).unwrap();
+        encode(
+            encode_args(&dir.dir.join("second.png"), "ruSt", "injected"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        fs::create_dir(dir.dir.join("nested")).unwrap();
+        fs::write(dir.dir.join("nested").join("third.png"), PNG_FILE).unwrap();
+
+        let output = dir.dir.join("manifest.json");
+        let args = ManifestArgs {
+            dir: dir.dir.clone(),
+            output: Some(output.clone()),
+            recursive: false,
+        };
+        let mut out = Vec::new();
+        manifest(args, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("manifest of 2 files"));
+
+        let json = fs::read_to_string(&output).unwrap();
+        assert!(json.contains("\"path\": \"image.png\""));
+        assert!(json.contains("\"path\": \"second.png\""));
+        assert!(!json.contains("third.png"));
+        assert!(json.contains(&format!("\"size\": {}", PNG_FILE.len())));
+        assert!(json.contains(&format!("\"sha256\": \"{}\"", sha256_hex(&PNG_FILE))));
+        assert_eq!(json.matches("\"type\": \"ruSt\"").count(), 1);
+        assert_eq!(json.matches("\"type\": \"IDAT\"").count(), 2);
+
+        let args = ManifestArgs {
+            dir: dir.dir.clone(),
+            output: None,
+            recursive: true,
+        };
+        let mut out = Vec::new();
+        manifest(args, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("third.png"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_manifest_skips_symlink_cycles() {
+        let dir = TempPng::new("manifest-cycle");
+        let nested = dir.dir.join("nested");
+        fs::create_dir(&nested).unwrap();
+        std::os::unix::fs::symlink(&dir.dir, nested.join("loop")).unwrap();
+
+        let args = ManifestArgs {
+            dir: dir.dir.clone(),
+            output: None,
+            recursive: true,
+        };
+        let mut out = Vec::new();
+        manifest(args, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("\"path\": \"image.png\"").count(), 1);
+    }
+
+    #[test]
+    fn test_fix_lengths() {
+        let png = TempPng::new("fix-lengths");
+        let mut damaged = PNG_FILE;
+        damaged[33..37].copy_from_slice(&7u32.to_be_bytes());
+        fs::write(&png.path, damaged).unwrap();
+        assert!(read_png(&png.path).is_err());
+
+        let fixed = png.dir.join("fixed.png");
+        let args = RepairArgs {
+            file_path: png.path.clone(),
+            output_file: Some(fixed.clone()),
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        fix_lengths(args, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("IDAT chunk at byte offset 33: length 7 corrected to 12"));
+        assert_eq!(fs::read(&fixed).unwrap(), PNG_FILE);
+        assert_eq!(fs::read(&png.path).unwrap(), damaged);
+    }
+
+    #[test]
+    fn test_fix_crcs() {
+        let png = TempPng::new("fix-crcs");
+        let mut damaged = PNG_FILE;
+        damaged[56] ^= 0xff;
+        fs::write(&png.path, damaged).unwrap();
+        assert!(read_png(&png.path).is_err());
+
+        let args = || RepairArgs {
+            file_path: png.path.clone(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        fix_crcs(args(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Recomputed 1 CRC(s)"));
+        assert!(out.contains("skipped 2 correct one(s)"));
+        assert_eq!(fs::read(&png.path).unwrap(), PNG_FILE);
+
+        let mut out = Vec::new();
+        fix_crcs(args(), &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("All chunk CRCs"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let png = TempPng::new("normalize");
+        let mut chunks = read_png(&png.path).unwrap().into_chunks();
+        // pHYs belongs before the image data.
+        chunks.insert(2, crate::phys::Phys::from_dpi(72.0).to_chunk());
+        fs::write(&png.path, Png::from_chunks(chunks).as_bytes()).unwrap();
+        assert_eq!(
+            read_png(&png.path).unwrap().chunks()[1]
+                .chunk_type()
+                .to_string(),
+            "IDAT"
+        );
+
+        let args = || RepairArgs {
+            file_path: png.path.clone(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        normalize(args(), &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("Moved 1 chunk(s)"));
+        let image = read_png(&png.path).unwrap();
+        assert_eq!(image.chunks()[1].chunk_type().to_string(), "pHYs");
+        assert!(image.validate_structure().is_ok());
+
+        let mut out = Vec::new();
+        normalize(args(), &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("already in canonical order"));
+    }
+
+    #[test]
+    fn test_fix_iend() {
+        let png = TempPng::new("fix-iend");
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"late".to_vec()).as_bytes());
+        fs::write(&png.path, bytes).unwrap();
+
+        let args = RepairArgs {
+            file_path: png.path.clone(),
+            output_file: None,
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        fix_iend(args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Moved ruSt chunk before IEND\n"
+        );
+
+        let image = read_png(&png.path).unwrap();
+        assert!(image.validate_structure().is_ok());
+        assert_eq!(image.chunks()[2].chunk_type().to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = TempPng::new("capacity");
+        let run = |threshold| {
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            let args = CapacityArgs {
+                file_path: png.path.clone(),
+                threshold,
+            };
+            capacity(args, &mut out, &mut err).unwrap();
+            (
+                String::from_utf8(out).unwrap(),
+                String::from_utf8(err).unwrap(),
+            )
+        };
+
+        let (out, err) = run(1024);
+        assert!(out.contains(&format!("File size: {} bytes", PNG_FILE.len())));
+        assert!(out.contains("Chunks: 3"));
+        assert!(out.contains("Estimated capacity: 1012 bytes"));
+        assert!(err.is_empty());
+
+        let (out, _) = run(u64::MAX);
+        assert!(out.contains(&format!("File size at threshold: {} bytes", u64::MAX)));
+
+        encode(
+            encode_args(&png.path, "ruSt", "already hidden"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let (out, err) = run(16);
+        assert!(out.contains("Ancillary data: 26 bytes"));
+        assert!(out.contains("Estimated capacity: 0 bytes"));
+        assert!(err.contains("past the 16-byte threshold"));
+    }
+
+    #[test]
+    fn test_batch_encode_parallel() {
+        let dir = TempPng::new("batch");
+        let mut files: Vec<PathBuf> = (0..8)
+            .map(|i| {
+                let path = dir.dir.join(format!("{}.png", i));
+                fs::write(&path, PNG_FILE).unwrap();
+                path
+            })
+            .collect();
+        let bad = dir.dir.join("bad.png");
+        fs::write(&bad, b"not a png").unwrap();
+        files.insert(3, bad.clone());
+
+        let args = BatchArgs {
+            operation: BatchOperation::Encode {
+                chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                message: "hidden".to_string(),
+            },
+            files: files.clone(),
+            jobs: 4,
+            quiet: true,
+            rename_output: None,
+            output_dir: None,
+            write: WriteOptions::default(),
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        assert!(batch(args, &mut out, &mut err).is_err());
+
+        let out = String::from_utf8(out).unwrap();
+        let err = String::from_utf8(err).unwrap();
+        assert_eq!(out.lines().count(), 8);
+        assert!(err.contains(&bad.display().to_string()));
+        for path in files.iter().filter(|path| **path != bad) {
+            assert!(out.contains(&format!("{}: encoded", path.display())));
+            let png = read_png(path).unwrap();
+            let chunk = png.chunk_by_type("ruSt").unwrap();
+            assert_eq!(chunk.data_as_string().unwrap(), "hidden");
+        }
+    }
+
+    #[test]
+    fn test_batch_rename_output() {
+        let dir = TempPng::new("batch-rename");
+        let files: Vec<PathBuf> = ["a.png", "b.png"]
+            .iter()
+            .map(|name| {
+                let path = dir.dir.join(name);
+                fs::write(&path, PNG_FILE).unwrap();
+                path
+            })
+            .collect();
+        let args = |template: &str| BatchArgs {
+            operation: BatchOperation::Encode {
+                chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                message: "hidden".to_string(),
+            },
+            files: files.clone(),
+            jobs: 2,
+            quiet: true,
+            rename_output: Some(template.to_string()),
+            output_dir: None,
+            write: WriteOptions::default(),
+        };
+
+        let mut out = Vec::new();
+        batch(args("{stem}.tagged.{ext}"), &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for (input, name) in files.iter().zip(["a.tagged.png", "b.tagged.png"]) {
+            let output = dir.dir.join(name);
+            assert!(out.contains(&format!("encoded to {}", output.display())));
+            assert_eq!(
+                read_png(&output)
+                    .unwrap()
+                    .chunk_by_type("ruSt")
+                    .unwrap()
+                    .data_as_string()
+                    .unwrap(),
+                "hidden"
+            );
+            assert_eq!(fs::read(input).unwrap(), PNG_FILE);
+        }
+
+        let err = batch(args("same.png"), &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(batch::BatchError::OutputCollision { .. })
+        ));
+        assert!(!dir.dir.join("same.png").exists());
+    }
+
+    #[test]
+    fn test_batch_output_dir() {
+        let dir = TempPng::new("batch-output-dir");
+        let files: Vec<PathBuf> = ["first/a.png", "second/b.png"]
+            .iter()
+            .map(|name| {
+                let path = dir.dir.join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, PNG_FILE).unwrap();
+                path
+            })
+            .collect();
+        let output_dir = dir.dir.join("processed");
+        let args = |files: Vec<PathBuf>| BatchArgs {
+            operation: BatchOperation::Encode {
+                chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                message: "hidden".to_string(),
+            },
+            files,
+            jobs: 2,
+            quiet: true,
+            rename_output: None,
+            output_dir: Some(output_dir.clone()),
+            write: WriteOptions::default(),
+        };
+
+        batch(args(files.clone()), &mut Vec::new(), &mut Vec::new()).unwrap();
+        for (input, name) in files.iter().zip(["a.png", "b.png"]) {
+            assert_eq!(
+                read_png(&output_dir.join(name))
+                    .unwrap()
+                    .chunk_by_type("ruSt")
+                    .unwrap()
+                    .data_as_string()
+                    .unwrap(),
+                "hidden"
+            );
+            assert_eq!(fs::read(input).unwrap(), PNG_FILE);
+        }
+
+        let clash = dir.dir.join("second/a.png");
+        fs::write(&clash, PNG_FILE).unwrap();
+        let err = batch(
+            args(vec![files[0].clone(), clash]),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(batch::BatchError::OutputCollision { .. })
+        ));
+
+        // The directory the input is already in, spelled another way.
+        let args = BatchArgs {
+            output_dir: Some(dir.dir.join("second").join("..").join("first")),
+            ..args(vec![files[0].clone()])
+        };
+        let err = batch(args, &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(batch::BatchError::OverwritesInput { .. })
+        ));
+        assert_eq!(fs::read(&files[0]).unwrap(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_scan_many_files() {
+        let dir = TempPng::new("scan");
+        let clean = dir.dir.join("clean.png");
+        fs::write(&clean, PNG_FILE).unwrap();
+        let hidden = dir.dir.join("hidden.png");
+        let mut image = Png::try_from(&PNG_FILE[..]).unwrap();
+        image.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"secret".to_vec(),
+        ));
+        fs::write(&hidden, image.as_bytes()).unwrap();
+
+        let args = ScanArgs {
+            files: vec![hidden.clone(), clean.clone()],
+            jobs: 2,
+        };
+        let mut out = Vec::new();
+        scan(args, &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{}:\n  Non-standard chunk ruSt at byte offset 57\n{}:\n  nothing found\n",
+                hidden.display(),
+                clean.display()
+            )
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_extract_icc() {
+        let png = TempPng::new("extract-icc");
+        let output = png.dir.join("profile.icc");
+        let args = || ExtractIccArgs {
+            file_path: png.path.clone(),
+            output: output.clone(),
+        };
+        let err = extract_icc(args(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::ChunkNotFound { chunk_type, .. }) if chunk_type == "iCCP"
+        ));
+
+        let profile: Vec<u8> = (0..=255).rev().cycle().take(1000).collect();
+        let mut data = b"Display P3\0\0".to_vec();
+        data.extend(text::compress(&profile).unwrap());
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::try_from_parts("iCCP", data).unwrap());
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        extract_icc(args(), &mut out).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), profile);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("Wrote 1000 bytes of ICC profile 'Display P3'"));
+
+        let mut out = Vec::new();
+        let print_args = PrintArgs {
+            no_summary: true,
+            ..print_args(&png.path)
+        };
+        print_chunks(print_args, &mut out, &mut Vec::new()).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("ICC profile: Display P3\n"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let png = TempPng::new("verify");
+        let verify_file = |bytes: &[u8]| {
+            fs::write(&png.path, bytes).unwrap();
+            let mut out = Vec::new();
+            let args = VerifyArgs {
+                file_path: png.path.clone(),
+            };
+            let result = verify(args, &mut out);
+            (result, String::from_utf8(out).unwrap())
+        };
+
+        let (result, out) = verify_file(&PNG_FILE);
+        assert!(result.is_ok());
+        assert!(out.starts_with("PASS"));
+
+        let mut bad_crc = PNG_FILE.to_vec();
+        *bad_crc.last_mut().unwrap() ^= 1;
+        let (result, out) = verify_file(&bad_crc);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(CommandError::Invalid {
+                problems: 1,
+                bad_crcs: 1,
+                ..
+            })
+        ));
+        assert!(out.starts_with("FAIL"));
+        assert!(out.contains("  - Bad CRC on chunk IEND"));
+
+        // The signature followed by IDAT and IEND, with no IHDR.
+        let mut no_ihdr = PNG_FILE[..8].to_vec();
+        no_ihdr.extend_from_slice(&PNG_FILE[33..]);
+        let (result, out) = verify_file(&no_ihdr);
+        assert!(result.is_err());
+        assert!(out.contains("  - Chunk not found: IHDR"));
+
+        let (result, out) = verify_file(b"GIF89a\x01\x00\x01\x00");
+        assert!(result.is_err());
+        assert!(out.contains("  - Bad header"));
+
+        let (result, out) = verify_file(b"");
+        assert!(result.is_err());
+        assert!(out.contains("  - Too short: 0 bytes"));
+    }
+
+    #[test]
+    fn test_verify_image_data_rules() {
+        let png = TempPng::new("verify-image-data");
+        let verify_chunks = |chunks: Vec<Chunk>| {
+            fs::write(&png.path, Png::from_chunks(chunks).as_bytes()).unwrap();
+            let mut out = Vec::new();
+            let args = VerifyArgs {
+                file_path: png.path.clone(),
+            };
+            let result = verify(args, &mut out);
+            assert!(matches!(
+                result.unwrap_err().downcast_ref(),
+                Some(CommandError::Invalid { bad_crcs: 0, .. })
+            ));
+            String::from_utf8(out).unwrap()
+        };
+        let chunk = |name: &str| Chunk::new(ChunkType::from_str(name).unwrap(), vec![0; 3]);
+        let indexed = || {
+            png::PngBuilder::new(1, 1)
+                .color_type(3)
+                .idat(vec![0])
+                .build()
+                .into_chunks()
+        };
+
+        // The only IDAT given another type.
+        let mut chunks = Png::try_from(&PNG_FILE[..]).unwrap().into_chunks();
+        chunks[1] = Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            chunks[1].data().to_vec(),
+        );
+        assert!(verify_chunks(chunks).contains("  - Chunk not found: IDAT"));
+
+        let mut chunks = Png::try_from(&PNG_FILE[..]).unwrap().into_chunks();
+        chunks.insert(2, chunk("ruSt"));
+        chunks.insert(3, chunk("IDAT"));
+        assert!(verify_chunks(chunks).contains("  - Split image data"));
+
+        assert!(verify_chunks(indexed()).contains("PLTE is required for indexed-color images"));
+
+        let mut chunks = indexed();
+        chunks.insert(2, chunk("PLTE"));
+        assert!(verify_chunks(chunks).contains("  - Misplaced chunk: PLTE"));
+    }
+
+    #[test]
+    fn test_self_test() {
+        let mut out = Vec::new();
+        self_test(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("pass  CRC-32 of IEND chunk\n"));
+        assert!(out.ends_with("14 of 14 checks passed\n"));
+
+        let wrong = [crc::TestVector {
+            name: "a wrong vector",
+            input: b"IEND",
+            expected: 0,
+        }];
+        let mut out = Vec::new();
+        let err = check_crc_vectors(&wrong, &mut out).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::SelfTestFailed {
+                failed: 2,
+                total: 2
+            })
+        ));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("FAIL  CRC-32 of a wrong vector: expected 0x00000000, got 0xae426082"));
+    }
+
+    #[test]
+    fn test_write_png_rejects_urls() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let url = Path::new("http://127.0.0.1/image.png");
+        let err = write_png(url, &png, url, &WriteOptions::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::UrlNotWritable(_))
+        ));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_print_fetched_png() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+            stream.write_all(&PNG_FILE).unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        let url = format!("http://127.0.0.1:{}/tiny.png", port);
+        let mut out = Vec::new();
+        print_chunks(print_args(Path::new(&url)), &mut out, &mut Vec::new()).unwrap();
+
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /tiny.png HTTP/1.0\r\n"));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("IHDR"));
+        assert!(out.contains("IEND"));
+    }
+
+    #[test]
+    fn test_print_offset_and_scan() {
+        let png = TempPng::new("print-offset");
+        let mut container = vec![0xaa; 1024];
+        container.extend_from_slice(&PNG_FILE);
+        container.extend_from_slice(b"rest of the container");
+        fs::write(&png.path, &container).unwrap();
+
+        let args = |offset, scan| PrintArgs {
+            offset,
+            scan,
+            ..print_args(&png.path)
+        };
+        let mut out = Vec::new();
+        print_chunks(args(None, true), &mut out, &mut Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PNG signature at byte offset 1024\n"
+        );
+
+        assert!(print_chunks(args(None, false), &mut Vec::new(), &mut Vec::new()).is_err());
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        print_chunks(args(Some(1024), false), &mut out, &mut err).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("IEND"));
+        assert!(String::from_utf8(err).unwrap().contains("trailing data"));
+    }
+
+    #[test]
+    fn test_print_error_on_warning() {
+        let png = TempPng::new("print-strict");
+        let mut bytes = fs::read(&png.path).unwrap();
+        bytes.extend_from_slice(b"junk");
+        fs::write(&png.path, bytes).unwrap();
+
+        let args = |error_on_warning| PrintArgs {
+            error_on_warning,
+            ..print_args(&png.path)
+        };
+        assert!(print_chunks(args(false), &mut Vec::new(), &mut Vec::new()).is_ok());
+        let err = print_chunks(args(true), &mut Vec::new(), &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CommandError::Warnings { count: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_print_detect() {
+        let png = TempPng::new("print-detect");
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::new_text("Software", "Adobe ImageReady").unwrap());
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let args = |detect| PrintArgs {
+            detect,
+            ..print_args(&png.path)
+        };
+        let mut out = Vec::new();
+        print_chunks(args(true), &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Software: Adobe ImageReady (detected: Adobe ImageReady)"));
+
+        let mut out = Vec::new();
+        print_chunks(args(false), &mut out, &mut Vec::new()).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("Software:"));
+    }
+
+    #[test]
+    fn test_print_table() {
+        let png = TempPng::new("print-table");
+        encode(
+            encode_args(&png.path, "ruSt", "hello"),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        let args = PrintArgs {
+            table: true,
+            no_summary: true,
+            ..print_args(&png.path)
+        };
+        print_chunks(args, &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "┌───────┬──────┬────────┬────────────┬────────────────────────────────────────────────┐"
+        );
+        assert_eq!(
+            lines[1],
+            "│ INDEX │ TYPE │ LENGTH │ CRC        │ FLAGS                                          │"
+        );
+        for chunk_type in ["IHDR", "IDAT", "ruSt", "IEND"] {
+            assert!(lines
+                .iter()
+                .any(|line| line.contains(&format!("│ {} │", chunk_type))));
+        }
+        assert!(out.contains("│ ruSt │      5 │"));
+        assert!(out.contains("ancillary, private, safe-to-copy, non-standard │"));
+        assert!(out.contains("│ IHDR │     13 │ 0x907753de │ critical "));
+        assert!(lines[lines.len() - 1].starts_with('└'));
+    }
+
+    #[test]
+    fn test_print_entropy() {
+        let png = TempPng::new("print-entropy");
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"aaaa".to_vec(),
+        ));
+        fs::write(&png.path, image.as_bytes()).unwrap();
+        let args = PrintArgs {
+            no_summary: true,
+            entropy: true,
+            ..print_args(&png.path)
+        };
+
+        let mut out = Vec::new();
+        print_chunks(args, &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Entropy (bits per byte):\n"));
+        assert!(out.contains("    2  ruSt  0.000\n"));
+    }
+
+    #[test]
+    fn test_print_summary() {
+        let png = TempPng::new("print-summary");
+        let args = |no_summary| PrintArgs {
+            no_summary,
+            ..print_args(&png.path)
+        };
+        let print = |args| {
+            let mut out = Vec::new();
+            print_chunks(args, &mut out, &mut Vec::new()).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = print(args(false));
+        assert!(out.ends_with(
+            "Summary: 3 chunks (3 critical, 0 ancillary), 69 bytes\nCustom chunks: none\n"
+        ));
+
+        for message in ["hello", "again"] {
+            encode(
+                encode_args(&png.path, "ruSt", message),
+                &mut Vec::new(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+        }
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::new_text("Comment", "hi").unwrap());
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        // Two 5-byte ruSt chunks and a 10-byte tEXt chunk, 12 bytes of overhead each.
+        let out = print(args(false));
+        assert!(out.ends_with(
+            "Summary: 6 chunks (3 critical, 3 ancillary), 125 bytes\nCustom chunks: ruSt\n"
+        ));
+        assert_eq!(image.as_bytes().len(), 125);
+
+        assert!(!print(args(true)).contains("Summary:"));
+    }
+
+    #[test]
+    fn test_print_escapes_text_values() {
+        let png = TempPng::new("print-escape");
+        let mut image = read_png(&png.path).unwrap();
+        image.append_chunk(Chunk::new_text("Comment", "hi\x1b[2J").unwrap());
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let args = |raw_text| PrintArgs {
+            detect: true,
+            raw_text,
+            ..print_args(&png.path)
+        };
+        let mut out = Vec::new();
+        print_chunks(args(false), &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Comment: hi\\x1b[2J\n"));
+        assert!(!out.contains('\x1b'));
+
+        let mut out = Vec::new();
+        print_chunks(args(true), &mut out, &mut Vec::new()).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("Comment: hi\x1b[2J\n"));
+    }
+
+    #[test]
+    fn test_print_reports_parse_warnings() {
+        let png = TempPng::new("print-warnings");
+        let mut bytes = fs::read(&png.path).unwrap();
+        bytes.extend_from_slice(b"junk");
+        fs::write(&png.path, bytes).unwrap();
+
+        let mut err = Vec::new();
+        print_chunks(print_args(&png.path), &mut Vec::new(), &mut err).unwrap();
+
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.starts_with("warning: 4 bytes of trailing data after IEND"));
+    }
+
+    #[test]
+    fn test_print_reports_resolution() {
+        let png = TempPng::new("print-phys");
+        let mut image = read_png(&png.path).unwrap();
+        image.set_dpi(72.0);
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        print_chunks(print_args(&png.path), &mut out, &mut Vec::new()).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Physical resolution: 2835x2835 pixels per metre (72x72 DPI)"));
+    }
+
+    fn idat_heavy_chunks() -> Vec<Chunk> {
+        [
+            "IHDR", "IDAT", "IDAT", "IDAT", "IEND", "tEXt", "tIME", "ruSt",
+        ]
+        .iter()
+        .map(|chunk_type| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 4]))
+        .collect()
+    }
+
+    #[test]
+    fn test_grouped_lines_collapse_idat() {
+        let lines = grouped_lines(&idat_heavy_chunks(), false);
+        assert_eq!(
+            lines,
+            vec![
+                "Critical:",
+                "  [0] IHDR  4 bytes",
+                "  [1-3] IDAT  3 chunks, 12 bytes",
+                "  [4] IEND  4 bytes",
+                "Text:",
+                "  [5] tEXt  4 bytes",
+                "Timing:",
+                "  [6] tIME  4 bytes",
+                "Custom:",
+                "  [7] ruSt  4 bytes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouped_lines_expand_idat() {
+        let lines = grouped_lines(&idat_heavy_chunks(), true);
+        let idat_lines = lines.iter().filter(|line| line.contains("IDAT")).count();
+        assert_eq!(idat_lines, 3);
+        assert!(lines.contains(&"  [2] IDAT  4 bytes".to_string()));
+    }
+
+    #[test]
+    fn test_print_reports_color_chunks() {
+        let png = TempPng::new("print-bkgd");
+        let mut image = read_png(&png.path).unwrap();
+        let chunk = |chunk_type, data| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data);
+        image.append_chunk(chunk("bKGD", vec![0, 255, 0, 0, 0, 128]));
+        image.append_chunk(chunk("sBIT", vec![5, 6]));
+        fs::write(&png.path, image.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        print_chunks(print_args(&png.path), &mut out, &mut Vec::new()).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Background color: RGB (255, 0, 128)"));
+        assert!(out.contains("Significant bits: invalid sBIT chunk (Bad sBIT length: 2 bytes"));
+        assert!(!out.contains("Transparency"));
+    }
+
+    #[test]
+    fn test_parse_error_names_file() {
+        let png = TempPng::new("parse-error");
+        let bad = png.dir.join("photo.png");
+        fs::write(&bad, b"not a png").unwrap();
+
+        let args = decode_args(&bad, "ruSt");
+        let err = decode(args, &mut Vec::new(), &mut Vec::new())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!(
+                "failed to parse '{}': Bad header: not a PNG signature",
+                bad.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_error_names_file() {
+        let png = TempPng::new("read-error");
+        let missing = png.dir.join("missing.png");
+        let err = read_png(&missing).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("failed to read '{}'", missing.display())));
+    }
+
+    #[test]
+    fn test_print_chunks() {
+        let png = TempPng::new("print");
+        let mut out = Vec::new();
+        print_chunks(print_args(&png.path), &mut out, &mut Vec::new()).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("IHDR"));
+        assert!(out.contains("IDAT"));
+        assert!(out.contains("IEND"));
+        assert!(!out.contains("==>"));
+    }
+
+    #[test]
+    fn test_print_concatenated_images() {
+        let png = TempPng::new("print-concatenated");
+        let bytes = [PNG_FILE, PNG_FILE].concat();
+        fs::write(&png.path, bytes).unwrap();
+
+        let args = |allow_duplicate_signature| PrintArgs {
+            allow_duplicate_signature,
+            ..print_args(&png.path)
+        };
+        assert!(print_chunks(args(false), &mut Vec::new(), &mut Vec::new()).is_err());
+
+        let mut out = Vec::new();
+        print_chunks(args(true), &mut out, &mut Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Found 2 concatenated images"));
+        assert!(out.contains("Image 2:"));
+        assert_eq!(out.matches("IEND").count(), 2);
+    }
+
+    #[test]
+    fn test_print_several_files() {
+        let png = TempPng::new("print-several");
+        let second = png.dir.join("second.png");
+        fs::write(&second, PNG_FILE).unwrap();
+        let broken = png.dir.join("broken.png");
+        fs::write(&broken, b"not a png").unwrap();
+
+        let args = |file_paths| PrintArgs {
+            file_paths,
+            ..print_args(&png.path)
+        };
+        let mut out = Vec::new();
+        print_chunks(
+            args(vec![png.path.clone(), second.clone()]),
+            &mut out,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(&format!("==> {} <==\n", png.path.display())));
+        assert!(out.contains(&format!("\n\n==> {} <==\n", second.display())));
+        assert_eq!(out.matches("IHDR").count(), 2);
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let result = print_chunks(
+            args(vec![broken.clone(), second.clone()]),
+            &mut out,
+            &mut err,
+        );
+        assert!(result.is_err());
+        assert!(String::from_utf8(out).unwrap().contains("IHDR"));
+        assert!(String::from_utf8(err).unwrap().contains("broken.png"));
+    }
+}
@@ -0,0 +1,155 @@
+/// Lookup table for the reflected CRC-32 polynomial used by PNG (ISO 3309 / ITU-T V.42).
+/// It is built at compile time, so no call to `Crc32` ever rebuilds it.
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            if c & 1 == 1 {
+                c = 0xedb8_8320 ^ (c >> 1);
+            } else {
+                c >>= 1;
+            }
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// A running CRC-32 over one or more byte slices.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { value: 0xffff_ffff }
+    }
+
+    /// Feeds `bytes` into the running checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.value = TABLE[((self.value ^ *byte as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    /// The checksum of every byte fed in so far.
+    pub fn finalize(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An input with a published CRC-32, checked by both the unit tests and `pngme selftest`.
+pub struct TestVector {
+    pub name: &'static str,
+    pub input: &'static [u8],
+    pub expected: u32,
+}
+
+/// The standard CRC-32 check values, and the type and data of a few chunks whose CRCs are
+/// known from real files.
+pub const TEST_VECTORS: [TestVector; 7] = [
+    TestVector {
+        name: "empty input",
+        input: b"",
+        expected: 0x0000_0000,
+    },
+    TestVector {
+        name: "check value \"123456789\"",
+        input: b"123456789",
+        expected: 0xcbf4_3926,
+    },
+    TestVector {
+        name: "\"a\"",
+        input: b"a",
+        expected: 0xe8b7_be43,
+    },
+    TestVector {
+        name: "\"abc\"",
+        input: b"abc",
+        expected: 0x3524_41c2,
+    },
+    TestVector {
+        name: "\"The quick brown fox jumps over the lazy dog\"",
+        input: b"The quick brown fox jumps over the lazy dog",
+        expected: 0x414f_a339,
+    },
+    TestVector {
+        name: "IEND chunk",
+        input: b"IEND",
+        expected: 0xae42_6082,
+    },
+    TestVector {
+        name: "IHDR chunk of a 1x1 truecolor image",
+        input: b"IHDR\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00",
+        expected: 0x9077_53de,
+    },
+];
+
+/// Computes the CRC-32 of `bytes` in one call.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_test_vectors() {
+        for vector in &TEST_VECTORS {
+            assert_eq!(crc32(vector.input), vector.expected, "{}", vector.name);
+        }
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_crc32_empty() {
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    /// 1 MiB of bytes counting up modulo a prime, so the pattern does not line up with the
+    /// table or with any power of two.
+    fn megabyte() -> Vec<u8> {
+        (0..1 << 20).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_crc32_one_megabyte() {
+        let data = megabyte();
+        assert_eq!(crc32(&data), 0xef0e_6054);
+
+        let mut crc = Crc32::new();
+        for piece in data.chunks(4096) {
+            crc.update(piece);
+        }
+        assert_eq!(crc.finalize(), 0xef0e_6054);
+    }
+
+    #[test]
+    fn test_crc32_incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"RuSt");
+        crc.update(b"This is where your secret message will be!");
+        assert_eq!(crc.finalize(), 2882656334);
+    }
+}
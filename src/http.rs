@@ -0,0 +1,211 @@
+//! Just enough HTTP to fetch a PNG for the read-only commands: a single `GET` over
+//! HTTP/1.0, so the response is never chunked and ends when the server closes the
+//! connection. There is no TLS, so `https://` URLs are refused.
+
+use crate::png::ParseOptions;
+use crate::Result;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum HttpError {
+    BadUrl(String),
+    Unsupported(String),
+    BadResponse,
+    Status(String),
+    TooLarge { limit: u64 },
+    TimedOut,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadUrl(url) => write!(f, "Bad URL: {}", url),
+            Self::Unsupported(url) => {
+                write!(
+                    f,
+                    "Unsupported URL: {} (only plain http:// is supported)",
+                    url
+                )
+            }
+            Self::BadResponse => write!(f, "Bad HTTP response"),
+            Self::Status(status) => write!(f, "HTTP request failed: {}", status),
+            Self::TooLarge { limit } => {
+                write!(
+                    f,
+                    "HTTP response is larger than the limit of {} bytes",
+                    limit
+                )
+            }
+            Self::TimedOut => write!(f, "HTTP response took too long"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the whole response may take, so a server trickling bytes cannot keep each
+/// read inside `TIMEOUT` forever.
+const RESPONSE_DEADLINE: Duration = Duration::from_secs(300);
+
+/// The largest response read: room for a few chunks of the largest size parsing accepts.
+const MAX_RESPONSE_SIZE: u64 = 4 * ParseOptions::DEFAULT_MAX_CHUNK_SIZE as u64;
+
+/// The host, port and path of an `http://` URL. An IPv6 address is written in brackets,
+/// as in `http://[::1]:8080/`, and returned without them.
+fn split_url(url: &str) -> Result<(&str, u16, &str)> {
+    let bad_url = || HttpError::BadUrl(url.to_string());
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err(Box::new(HttpError::Unsupported(url.to_string())));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed.split_once(']').ok_or_else(bad_url)?;
+            match after {
+                "" => (host, None),
+                _ => (host, Some(after.strip_prefix(':').ok_or_else(bad_url)?)),
+            }
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| bad_url())?,
+        None => 80,
+    };
+    if host.is_empty() {
+        return Err(Box::new(HttpError::BadUrl(url.to_string())));
+    }
+    Ok((host, port, path))
+}
+
+/// The body of a successful `GET` of `url`.
+pub fn get(url: &str) -> Result<Vec<u8>> {
+    let (host, port, path) = split_url(url)?;
+    let mut stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let host_header = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host_header
+    )?;
+
+    let response = read_limited(
+        &mut stream,
+        MAX_RESPONSE_SIZE,
+        Instant::now() + RESPONSE_DEADLINE,
+    )?;
+    body(&response)
+}
+
+/// Reads `reader` to the end, failing once more than `limit` bytes arrive or `deadline`
+/// passes.
+fn read_limited<R: Read>(reader: &mut R, limit: u64, deadline: Instant) -> Result<Vec<u8>> {
+    let mut response = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(response);
+        }
+        if (response.len() + read) as u64 > limit {
+            return Err(Box::new(HttpError::TooLarge { limit }));
+        }
+        if Instant::now() > deadline {
+            return Err(Box::new(HttpError::TimedOut));
+        }
+        response.extend_from_slice(&buf[..read]);
+    }
+}
+
+/// The body of a raw HTTP response, or an error unless its status is 200.
+fn body(response: &[u8]) -> Result<Vec<u8>> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or(HttpError::BadResponse)?;
+    let head = std::str::from_utf8(&response[..end]).map_err(|_| HttpError::BadResponse)?;
+    let status = head.lines().next().unwrap_or_default();
+    match status.split(' ').nth(1) {
+        Some("200") => Ok(response[end + 4..].to_vec()),
+        Some(_) => Err(Box::new(HttpError::Status(status.to_string()))),
+        None => Err(Box::new(HttpError::BadResponse)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("http://example.com/a/b.png").unwrap(),
+            ("example.com", 80, "/a/b.png")
+        );
+        assert_eq!(
+            split_url("http://127.0.0.1:8080").unwrap(),
+            ("127.0.0.1", 8080, "/")
+        );
+        assert!(split_url("https://example.com/b.png").is_err());
+        assert!(split_url("http://example.com:port/").is_err());
+        assert!(split_url("http:///b.png").is_err());
+    }
+
+    #[test]
+    fn test_split_url_ipv6() {
+        assert_eq!(
+            split_url("http://[::1]:8080/a.png").unwrap(),
+            ("::1", 8080, "/a.png")
+        );
+        assert_eq!(split_url("http://[::1]").unwrap(), ("::1", 80, "/"));
+        assert!(split_url("http://[::1/a.png").is_err());
+        assert!(split_url("http://[::1]8080/").is_err());
+    }
+
+    #[test]
+    fn test_read_limited() {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let data = vec![7u8; 20_000];
+        assert_eq!(
+            read_limited(&mut &data[..], 20_000, deadline).unwrap(),
+            data
+        );
+        let err = read_limited(&mut &data[..], 19_999, deadline).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(HttpError::TooLarge { limit: 19_999 })
+        ));
+        let past = Instant::now() - Duration::from_secs(1);
+        assert!(read_limited(&mut &data[..], 20_000, past).is_err());
+    }
+
+    #[test]
+    fn test_body() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: image/png\r\n\r\n\x89PNG";
+        assert_eq!(body(response).unwrap(), b"\x89PNG");
+
+        let err = body(b"HTTP/1.1 404 Not Found\r\n\r\nmissing").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HTTP request failed: HTTP/1.1 404 Not Found"
+        );
+        assert!(body(b"HTTP/1.0 200 OK").is_err());
+    }
+}
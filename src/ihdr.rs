@@ -0,0 +1,176 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// The starting column and row, then the column and row step, of each Adam7 pass.
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Debug)]
+pub enum IhdrError {
+    WrongType(String),
+    BadLength(usize),
+}
+
+impl fmt::Display for IhdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongType(chunk_type) => write!(f, "Not an IHDR chunk: {}", chunk_type),
+            Self::BadLength(len) => write!(f, "Bad IHDR length: {} (expected 13)", len),
+        }
+    }
+}
+
+impl std::error::Error for IhdrError {}
+
+/// The contents of an `IHDR` chunk: the image's dimensions and pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    /// 0 for no interlacing, 1 for Adam7.
+    pub interlace_method: u8,
+}
+
+impl Ihdr {
+    pub const INTERLACE_NONE: u8 = 0;
+    pub const INTERLACE_ADAM7: u8 = 1;
+
+    /// A readable name for the color type, if it is one the spec defines.
+    pub fn color_type_name(&self) -> Option<&'static str> {
+        match self.color_type {
+            0 => Some("grayscale"),
+            2 => Some("truecolor"),
+            3 => Some("indexed"),
+            4 => Some("grayscale with alpha"),
+            6 => Some("truecolor with alpha"),
+            _ => None,
+        }
+    }
+
+    pub fn is_adam7(&self) -> bool {
+        self.interlace_method == Self::INTERLACE_ADAM7
+    }
+
+    /// The width and height of each of the seven Adam7 passes. Passes that hold no pixels,
+    /// as happens for very small images, are 0 in one or both dimensions.
+    pub fn adam7_passes(&self) -> [(u32, u32); 7] {
+        let extent = |size: u32, start: u32, step: u32| {
+            if size > start {
+                (size - start).div_ceil(step)
+            } else {
+                0
+            }
+        };
+        ADAM7_PASSES.map(|(x, y, dx, dy)| (extent(self.width, x, dx), extent(self.height, y, dy)))
+    }
+
+    /// The 13 data bytes of an `IHDR` chunk.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(13);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type,
+            self.compression_method,
+            self.filter_method,
+            self.interlace_method,
+        ]);
+        bytes
+    }
+
+    /// Builds an `IHDR` chunk holding this header.
+    pub fn to_chunk(self) -> Chunk {
+        let chunk_type = ChunkType::from_str("IHDR").expect("IHDR is a valid chunk type");
+        Chunk::new(chunk_type, self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 13 {
+            return Err(Box::new(IhdrError::BadLength(bytes.len())));
+        }
+        Ok(Ihdr {
+            width: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            height: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            bit_depth: bytes[8],
+            color_type: bytes[9],
+            compression_method: bytes[10],
+            filter_method: bytes[11],
+            interlace_method: bytes[12],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr(width: u32, height: u32, interlace_method: u8) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: 2,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method,
+        }
+    }
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let data = vec![0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 1];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+        let ihdr = chunk.as_ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (256, 200));
+        assert_eq!(ihdr.color_type_name(), Some("truecolor with alpha"));
+        assert!(ihdr.is_adam7());
+    }
+
+    #[test]
+    fn test_ihdr_to_chunk() {
+        let header = ihdr(640, 480, 0);
+        assert_eq!(header.to_chunk().as_ihdr().unwrap(), header);
+    }
+
+    #[test]
+    fn test_ihdr_bad_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+        assert!(chunk.as_ihdr().is_err());
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 13]);
+        assert!(chunk.as_ihdr().is_err());
+    }
+
+    #[test]
+    fn test_adam7_passes() {
+        assert_eq!(
+            ihdr(8, 8, 1).adam7_passes(),
+            [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+        );
+        assert_eq!(
+            ihdr(5, 5, 1).adam7_passes(),
+            [(1, 1), (1, 1), (2, 1), (1, 2), (3, 1), (2, 3), (5, 2)]
+        );
+        // A single pixel only appears in the first pass.
+        assert_eq!(ihdr(1, 1, 1).adam7_passes()[1], (0, 1));
+    }
+}
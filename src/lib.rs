@@ -0,0 +1,29 @@
+//! The PNG parsing, chunk editing and storage code behind the `pngme` command, as a
+//! library so benchmarks under `benches/` can call it directly.
+
+pub mod args;
+pub mod batch;
+pub mod chunk;
+pub mod chunk_type;
+pub mod color;
+pub mod commands;
+pub mod crc;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod ihdr;
+pub mod json;
+pub mod lsb;
+pub mod phys;
+pub mod png;
+pub mod progress;
+pub mod provenance;
+pub mod repair;
+pub mod rng;
+pub mod sha256;
+pub mod storage;
+pub mod text;
+#[cfg(feature = "compression")]
+pub mod zlib;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,377 @@
+//! Hides a message in the least significant bits of the image samples themselves, so it
+//! survives tools that strip ancillary chunks. The image data is inflated and unfiltered,
+//! one bit is stored in the low bit of each sample, and the scanlines are written back
+//! unfiltered and recompressed into a single `IDAT` chunk. Every sample changes by at most
+//! one step.
+//!
+//! The message is preceded by its length as a 32-bit big-endian number, and bits are
+//! stored most significant first.
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::png::{Png, PngDecodingError};
+use crate::text;
+use crate::Result;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum LsbError {
+    Unsupported(String),
+    TooLong { length: usize, capacity: usize },
+    BadFilter(u8),
+    BadImageData { expected: usize, actual: usize },
+    ImageTooLarge { width: u32, height: u32 },
+    NoMessage,
+}
+
+impl fmt::Display for LsbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(reason) => write!(f, "LSB mode does not support {}", reason),
+            Self::TooLong { length, capacity } => write!(
+                f,
+                "Message too long: {} bytes, but the image holds at most {}",
+                length, capacity
+            ),
+            Self::BadFilter(filter) => write!(f, "Bad scanline filter type: {}", filter),
+            Self::BadImageData { expected, actual } => write!(
+                f,
+                "Bad image data: expected {} bytes of scanlines, found {}",
+                expected, actual
+            ),
+            Self::ImageTooLarge { width, height } => {
+                write!(f, "Image too large for LSB mode: {}x{}", width, height)
+            }
+            Self::NoMessage => write!(f, "No LSB message found in the image"),
+        }
+    }
+}
+
+impl std::error::Error for LsbError {}
+
+/// The bytes in the length prefix.
+const LENGTH_BYTES: usize = 4;
+
+/// The decoded samples of an image and how they are laid out.
+struct Pixels {
+    /// Scanlines without their filter type bytes.
+    data: Vec<u8>,
+    /// Bytes in each scanline.
+    stride: usize,
+    /// Bytes per sample: 1 at bit depth 8, 2 at bit depth 16.
+    sample_size: usize,
+}
+
+impl Pixels {
+    /// The index of the low byte of every sample, in order.
+    fn low_bytes(&self) -> impl Iterator<Item = usize> {
+        (self.sample_size - 1..self.data.len()).step_by(self.sample_size)
+    }
+
+    /// How many message bytes fit, after the length prefix.
+    fn capacity(&self) -> usize {
+        (self.data.len() / self.sample_size / 8).saturating_sub(LENGTH_BYTES)
+    }
+}
+
+/// The most message bytes `png` can hold in LSB mode.
+pub fn capacity(png: &Png) -> Result<usize> {
+    Ok(decode_pixels(png)?.capacity())
+}
+
+/// A copy of `png` with `message` hidden in its image data.
+pub fn embed(png: &Png, message: &[u8]) -> Result<Png> {
+    let mut pixels = decode_pixels(png)?;
+    if message.len() > pixels.capacity() {
+        return Err(Box::new(LsbError::TooLong {
+            length: message.len(),
+            capacity: pixels.capacity(),
+        }));
+    }
+
+    let length = (message.len() as u32).to_be_bytes();
+    let bits = length
+        .iter()
+        .chain(message)
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+    let targets: Vec<usize> = pixels.low_bytes().collect();
+    for (index, bit) in targets.into_iter().zip(bits) {
+        pixels.data[index] = (pixels.data[index] & !1) | bit;
+    }
+
+    let mut raw = Vec::with_capacity(pixels.data.len() + pixels.data.len() / pixels.stride);
+    for line in pixels.data.chunks(pixels.stride) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+    let idat = Chunk::new(
+        ChunkType::from_str("IDAT").expect("IDAT is a valid chunk type"),
+        text::compress(&raw)?,
+    );
+
+    // The new image data goes where the first `IDAT` was; any others are dropped.
+    let mut idat = Some(idat);
+    let chunks = png
+        .chunks()
+        .iter()
+        .filter_map(|chunk| match &chunk.chunk_type().bytes() {
+            b"IDAT" => idat.take(),
+            _ => Some(chunk.clone()),
+        })
+        .collect();
+    Ok(Png::from_chunks(chunks).with_warnings(png.warnings().to_vec()))
+}
+
+/// The message hidden in `png` by `embed`.
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let pixels = decode_pixels(png)?;
+    let mut bytes = pixels
+        .low_bytes()
+        .map(|index| pixels.data[index] & 1)
+        .collect::<Vec<u8>>()
+        .chunks_exact(8)
+        .map(|bits| bits.iter().fold(0, |byte, bit| (byte << 1) | bit))
+        .collect::<Vec<u8>>();
+
+    let Some(&[a, b, c, d]) = bytes.get(..LENGTH_BYTES) else {
+        return Err(unsupported("images with fewer than 32 samples"));
+    };
+    let length = u32::from_be_bytes([a, b, c, d]) as usize;
+    // A length the image cannot hold means the low bits were never written by `embed`.
+    if length > pixels.capacity() {
+        return Err(Box::new(LsbError::NoMessage));
+    }
+    bytes.truncate(LENGTH_BYTES + length);
+    bytes.drain(..LENGTH_BYTES);
+    Ok(bytes)
+}
+
+/// Inflates and unfilters the image data of `png`.
+fn decode_pixels(png: &Png) -> Result<Pixels> {
+    let ihdr = png
+        .chunk_by_type("IHDR")
+        .ok_or_else(|| PngDecodingError::ChunkNotFound("IHDR".to_string()))?
+        .as_ihdr()?;
+    let (stride, sample_size, pixel_size) = layout(&ihdr)?;
+    let height = ihdr.height as usize;
+    let expected = (stride + 1)
+        .checked_mul(height)
+        .ok_or_else(|| too_large(&ihdr))?;
+
+    let compressed: Vec<u8> = png
+        .chunks_by_type("IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    // Inflating past the size the header promises could only be wasted memory.
+    let raw = text::decompress(&compressed, expected)?;
+    if raw.len() != expected {
+        return Err(Box::new(LsbError::BadImageData {
+            expected,
+            actual: raw.len(),
+        }));
+    }
+
+    let mut data = Vec::with_capacity(stride * height);
+    for (row, line) in raw.chunks(stride + 1).enumerate() {
+        let above = row.checked_sub(1).map(|previous| previous * stride);
+        for (i, &filtered) in line[1..].iter().enumerate() {
+            let left = i
+                .checked_sub(pixel_size)
+                .map_or(0, |j| data[row * stride + j]);
+            let up = above.map_or(0, |start| data[start + i]);
+            let up_left = match (above, i.checked_sub(pixel_size)) {
+                (Some(start), Some(j)) => data[start + j],
+                _ => 0,
+            };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                filter => return Err(Box::new(LsbError::BadFilter(filter))),
+            };
+            data.push(filtered.wrapping_add(predicted));
+        }
+    }
+
+    Ok(Pixels {
+        data,
+        stride,
+        sample_size,
+    })
+}
+
+/// The bytes per scanline, per sample and per pixel, for the image formats LSB mode
+/// supports: non-interlaced, non-indexed, 8 or 16 bits per sample.
+fn layout(ihdr: &Ihdr) -> Result<(usize, usize, usize)> {
+    let channels = match ihdr.color_type {
+        0 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        3 => return Err(unsupported("indexed-color images")),
+        other => return Err(unsupported(&format!("color type {}", other))),
+    };
+    if ihdr.bit_depth != 8 && ihdr.bit_depth != 16 {
+        return Err(unsupported(&format!("bit depth {}", ihdr.bit_depth)));
+    }
+    if ihdr.is_adam7() {
+        return Err(unsupported("interlaced images"));
+    }
+
+    let sample_size = ihdr.bit_depth as usize / 8;
+    let pixel_size = channels * sample_size;
+    let stride = (ihdr.width as usize)
+        .checked_mul(pixel_size)
+        .filter(|stride| *stride < usize::MAX)
+        .ok_or_else(|| too_large(ihdr))?;
+    Ok((stride, sample_size, pixel_size))
+}
+
+fn unsupported(reason: &str) -> crate::Error {
+    Box::new(LsbError::Unsupported(reason.to_string()))
+}
+
+fn too_large(ihdr: &Ihdr) -> crate::Error {
+    Box::new(LsbError::ImageTooLarge {
+        width: ihdr.width,
+        height: ihdr.height,
+    })
+}
+
+/// The Paeth predictor from the PNG spec.
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+    use crate::png::PngBuilder;
+
+    /// An 8x8 truecolor image whose scanlines use every filter type.
+    fn test_image() -> (Png, Vec<u8>) {
+        let (width, height, pixel_size) = (8, 8, 3);
+        let stride = width * pixel_size;
+        let pixels: Vec<u8> = (0..stride * height).map(|i| (i * 7 % 256) as u8).collect();
+
+        let mut raw = Vec::new();
+        for row in 0..height {
+            let filter = (row % 5) as u8;
+            raw.push(filter);
+            for i in 0..stride {
+                let at = |row: usize, i: usize| pixels[row * stride + i];
+                let left = if i >= pixel_size {
+                    at(row, i - pixel_size)
+                } else {
+                    0
+                };
+                let up = if row > 0 { at(row - 1, i) } else { 0 };
+                let up_left = if row > 0 && i >= pixel_size {
+                    at(row - 1, i - pixel_size)
+                } else {
+                    0
+                };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                raw.push(at(row, i).wrapping_sub(predicted));
+            }
+        }
+
+        let png = PngBuilder::new(width as u32, height as u32)
+            .idat(crate::zlib::compress(&raw))
+            .build();
+        (png, pixels)
+    }
+
+    #[test]
+    fn test_decode_pixels_undoes_every_filter() {
+        let (png, pixels) = test_image();
+        assert_eq!(decode_pixels(&png).unwrap().data, pixels);
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let (png, original) = test_image();
+        let hidden = embed(&png, b"lsb secret").unwrap();
+
+        let reparsed = Png::try_from(hidden.as_bytes().as_slice()).unwrap();
+        reparsed.validate_structure().unwrap();
+        assert_eq!(extract(&reparsed).unwrap(), b"lsb secret");
+
+        // Each sample moves by at most one step.
+        let changed = decode_pixels(&reparsed).unwrap().data;
+        for (before, after) in original.iter().zip(&changed) {
+            assert!(before.abs_diff(*after) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_extract_without_message() {
+        let (png, _) = test_image();
+        let err = extract(&png).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LsbError::NoMessage)));
+    }
+
+    #[test]
+    fn test_embed_too_long() {
+        let (png, _) = test_image();
+        // 8 * 8 * 3 samples hold 24 bytes, 4 of which are the length.
+        assert_eq!(capacity(&png).unwrap(), 20);
+        assert!(embed(&png, &[b'x'; 20]).is_ok());
+        let err = embed(&png, &[b'x'; 21]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(LsbError::TooLong {
+                length: 21,
+                capacity: 20
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_formats() {
+        let indexed = PngBuilder::new(1, 1).color_type(3).build();
+        assert!(embed(&indexed, b"").is_err());
+        let interlaced = PngBuilder::new(1, 1).interlace_method(1).build();
+        assert!(extract(&interlaced).is_err());
+    }
+
+    #[test]
+    fn test_huge_dimensions() {
+        let huge = PngBuilder::new(u32::MAX, u32::MAX)
+            .bit_depth(16)
+            .color_type(6)
+            .build();
+        let err = extract(&huge).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(LsbError::ImageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_image_data_inflates_no_further_than_expected() {
+        let bomb = PngBuilder::new(1, 1)
+            .idat(text::compress(&vec![0; 1 << 20]).unwrap())
+            .build();
+        let err = extract(&bomb).unwrap_err();
+        assert!(err.downcast_ref::<crate::zlib::ZlibError>().is_some());
+    }
+}
@@ -1,12 +1,116 @@
-//mod args;
-mod chunk;
-mod chunk_type;
-//mod commands;
-//mod png;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
-
-fn main() -> Result<()> {
-    todo!()
-}
\ No newline at end of file
+use pngme::args::{ArgsError, PngMeArgs};
+use pngme::chunk::ChunkDecodingError;
+use pngme::chunk_type::ChunkTypeDecodingError;
+use pngme::commands::{self, CommandError};
+use pngme::png::PngDecodingError;
+use pngme::Result;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+
+/// The exit codes for each kind of failure, so scripts can tell them apart. Anything
+/// else that goes wrong exits with 1, as does `has` when the chunk is missing.
+const EXIT_USAGE: u8 = 2;
+const EXIT_FILE_NOT_FOUND: u8 = 3;
+const EXIT_PARSE: u8 = 4;
+const EXIT_CHUNK_NOT_FOUND: u8 = 5;
+const EXIT_BAD_CRC: u8 = 6;
+
+fn main() -> ExitCode {
+    let args = match PngMeArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(e.as_ref()))
+        }
+    }
+}
+
+/// The exit code for `error`. Errors wrap the ones that caused them, so the innermost
+/// error with a code of its own decides: a bad CRC found while parsing a file exits with
+/// `EXIT_BAD_CRC`, not `EXIT_PARSE`.
+fn exit_code(error: &(dyn std::error::Error + 'static)) -> u8 {
+    let mut code = 1;
+    let mut next = Some(error);
+    while let Some(error) = next {
+        if let Some(code_here) = own_exit_code(error) {
+            code = code_here;
+        }
+        next = error.source();
+    }
+    code
+}
+
+fn own_exit_code(error: &(dyn std::error::Error + 'static)) -> Option<u8> {
+    if error.is::<ArgsError>() {
+        return Some(EXIT_USAGE);
+    }
+    if let Some(e) = error.downcast_ref::<io::Error>() {
+        return (e.kind() == io::ErrorKind::NotFound).then_some(EXIT_FILE_NOT_FOUND);
+    }
+    if let Some(e) = error.downcast_ref::<CommandError>() {
+        return match e {
+            CommandError::ChunkNotFound { .. } => Some(EXIT_CHUNK_NOT_FOUND),
+            CommandError::Invalid {
+                problems, bad_crcs, ..
+            } if problems == bad_crcs => Some(EXIT_BAD_CRC),
+            CommandError::Parse { .. } | CommandError::Invalid { .. } => Some(EXIT_PARSE),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<PngDecodingError>() {
+        return match e {
+            PngDecodingError::ChunkNotFound(_) => Some(EXIT_CHUNK_NOT_FOUND),
+            _ => Some(EXIT_PARSE),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ChunkDecodingError>() {
+        return match e {
+            ChunkDecodingError::BadCrc { .. } => Some(EXIT_BAD_CRC),
+            _ => Some(EXIT_PARSE),
+        };
+    }
+    error.is::<ChunkTypeDecodingError>().then_some(EXIT_PARSE)
+}
+
+fn run(args: PngMeArgs) -> Result<ExitCode> {
+    let mut out = io::stdout().lock();
+    let mut err = io::stderr().lock();
+
+    match args {
+        PngMeArgs::Encode(args) => commands::encode(args, &mut out, &mut err)?,
+        PngMeArgs::Decode(args) => commands::decode(args, &mut out, &mut err)?,
+        PngMeArgs::Remove(args) => commands::remove(args, &mut out)?,
+        PngMeArgs::RenameChunk(args) => commands::rename_chunk(args, &mut out)?,
+        PngMeArgs::Obfuscate(args) => commands::obfuscate(args, &mut out)?,
+        PngMeArgs::Print(mut args) => {
+            args.table &= out.is_terminal();
+            commands::print_chunks(args, &mut out, &mut err)?
+        }
+        PngMeArgs::Capacity(args) => commands::capacity(args, &mut out, &mut err)?,
+        PngMeArgs::Has(args) => {
+            if !commands::has(args, &mut out)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        PngMeArgs::Batch(args) => commands::batch(args, &mut out, &mut err)?,
+        PngMeArgs::GenType(args) => commands::gen_type(args, &mut out)?,
+        PngMeArgs::Info(args) => commands::info(args, &mut out)?,
+        PngMeArgs::Manifest(args) => commands::manifest(args, &mut out)?,
+        PngMeArgs::FixLengths(args) => commands::fix_lengths(args, &mut out)?,
+        PngMeArgs::FixIend(args) => commands::fix_iend(args, &mut out)?,
+        PngMeArgs::FixCrcs(args) => commands::fix_crcs(args, &mut out)?,
+        PngMeArgs::Normalize(args) => commands::normalize(args, &mut out)?,
+        PngMeArgs::Scan(args) => commands::scan(args, &mut out, &mut err)?,
+        PngMeArgs::Verify(args) => commands::verify(args, &mut out)?,
+        PngMeArgs::ExtractIcc(args) => commands::extract_icc(args, &mut out)?,
+        PngMeArgs::SelfTest => commands::self_test(&mut out)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
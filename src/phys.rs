@@ -0,0 +1,155 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const METRES_PER_INCH: f64 = 0.0254;
+
+#[derive(Debug)]
+pub enum PhysError {
+    WrongType(String),
+    BadLength(usize),
+}
+
+impl fmt::Display for PhysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongType(chunk_type) => write!(f, "Not a pHYs chunk: {}", chunk_type),
+            Self::BadLength(len) => write!(f, "Bad pHYs length: {} (expected 9)", len),
+        }
+    }
+}
+
+impl std::error::Error for PhysError {}
+
+/// The contents of a `pHYs` chunk: the intended pixel size or aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phys {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    /// 1 if the values are pixels per metre, 0 if they only give an aspect ratio.
+    pub unit: u8,
+}
+
+impl Phys {
+    pub const UNIT_UNKNOWN: u8 = 0;
+    pub const UNIT_METRE: u8 = 1;
+
+    /// A resolution of `dpi` dots per inch on both axes.
+    pub fn from_dpi(dpi: f64) -> Phys {
+        let pixels_per_metre = (dpi / METRES_PER_INCH).round() as u32;
+        Phys {
+            pixels_per_unit_x: pixels_per_metre,
+            pixels_per_unit_y: pixels_per_metre,
+            unit: Self::UNIT_METRE,
+        }
+    }
+
+    /// The horizontal and vertical dots per inch, if the unit is the metre.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        if self.unit != Self::UNIT_METRE {
+            return None;
+        }
+        Some((
+            self.pixels_per_unit_x as f64 * METRES_PER_INCH,
+            self.pixels_per_unit_y as f64 * METRES_PER_INCH,
+        ))
+    }
+
+    /// The 9 data bytes of a `pHYs` chunk.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9);
+        bytes.extend_from_slice(&self.pixels_per_unit_x.to_be_bytes());
+        bytes.extend_from_slice(&self.pixels_per_unit_y.to_be_bytes());
+        bytes.push(self.unit);
+        bytes
+    }
+
+    /// Builds a `pHYs` chunk holding this resolution.
+    pub fn to_chunk(self) -> Chunk {
+        let chunk_type = ChunkType::from_str("pHYs").expect("pHYs is a valid chunk type");
+        Chunk::new(chunk_type, self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Phys {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 9 {
+            return Err(Box::new(PhysError::BadLength(bytes.len())));
+        }
+        Ok(Phys {
+            pixels_per_unit_x: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            pixels_per_unit_y: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            unit: bytes[8],
+        })
+    }
+}
+
+impl fmt::Display for Phys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dpi() {
+            Some((x, y)) => write!(
+                f,
+                "{}x{} pixels per metre ({:.0}x{:.0} DPI)",
+                self.pixels_per_unit_x, self.pixels_per_unit_y, x, y
+            ),
+            None => write!(
+                f,
+                "{}x{} pixels per unknown unit",
+                self.pixels_per_unit_x, self.pixels_per_unit_y
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phys_chunk() -> Chunk {
+        // 2835 pixels per metre on both axes, unit is the metre: 72 DPI.
+        let data = vec![0, 0, 0x0b, 0x13, 0, 0, 0x0b, 0x13, 1];
+        Chunk::new(ChunkType::from_str("pHYs").unwrap(), data)
+    }
+
+    #[test]
+    fn test_phys_from_chunk() {
+        let phys = phys_chunk().as_phys().unwrap();
+        assert_eq!(phys.pixels_per_unit_x, 2835);
+        assert_eq!(phys.pixels_per_unit_y, 2835);
+        assert_eq!(phys.unit, Phys::UNIT_METRE);
+
+        let (x, y) = phys.dpi().unwrap();
+        assert_eq!(x.round(), 72.0);
+        assert_eq!(y.round(), 72.0);
+    }
+
+    #[test]
+    fn test_phys_from_dpi() {
+        let phys = Phys::from_dpi(300.0);
+        assert_eq!(phys.pixels_per_unit_x, 11811);
+        assert_eq!(phys.to_chunk().as_phys().unwrap(), phys);
+    }
+
+    #[test]
+    fn test_phys_unknown_unit_has_no_dpi() {
+        let phys = Phys::try_from(&[0, 0, 0, 1, 0, 0, 0, 2, 0][..]).unwrap();
+        assert!(phys.dpi().is_none());
+    }
+
+    #[test]
+    fn test_phys_bad_length() {
+        let chunk = Chunk::new(ChunkType::from_str("pHYs").unwrap(), vec![0; 8]);
+        assert!(chunk.as_phys().is_err());
+    }
+
+    #[test]
+    fn test_phys_wrong_type() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 9]);
+        assert!(chunk.as_phys().is_err());
+    }
+}
@@ -0,0 +1,1728 @@
+use crate::chunk::{Chunk, ChunkDecodingError};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::phys::Phys;
+use crate::{Error, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum PngDecodingError {
+    BadHeader,
+    TooShort(usize),
+    Truncated {
+        offset: usize,
+    },
+    ChunkNotFound(String),
+    DuplicateChunk(String),
+    MisplacedChunk(String),
+    SplitImageData,
+    PaletteRequired,
+    TooManyChunks {
+        limit: usize,
+    },
+    TrailingData {
+        offset: usize,
+    },
+    ChunkTooLong {
+        offset: usize,
+        length: u32,
+        limit: u32,
+    },
+}
+
+impl fmt::Display for PngDecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadHeader => write!(f, "Bad header: not a PNG signature"),
+            Self::TooShort(length) => write!(
+                f,
+                "Too short: {} bytes is less than the 8-byte PNG signature",
+                length
+            ),
+            Self::Truncated { offset } => write!(f, "Truncated chunk at byte offset {}", offset),
+            Self::ChunkNotFound(chunk_type) => write!(f, "Chunk not found: {}", chunk_type),
+            Self::DuplicateChunk(chunk_type) => {
+                write!(f, "Duplicate chunk: {} may only appear once", chunk_type)
+            }
+            Self::MisplacedChunk(chunk_type) => {
+                write!(f, "Misplaced chunk: {} is out of order", chunk_type)
+            }
+            Self::SplitImageData => {
+                write!(f, "Split image data: IDAT chunks must be consecutive")
+            }
+            Self::PaletteRequired => {
+                write!(
+                    f,
+                    "Chunk not found: PLTE is required for indexed-color images"
+                )
+            }
+            Self::TrailingData { offset } => {
+                write!(f, "Unexpected data after IEND at byte offset {}", offset)
+            }
+            Self::TooManyChunks { limit } => {
+                write!(f, "Too many chunks: more than the limit of {}", limit)
+            }
+            Self::ChunkTooLong {
+                offset,
+                length,
+                limit,
+            } => write!(
+                f,
+                "Chunk at byte offset {} declares {} bytes of data, more than the limit of {}",
+                offset, length, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PngDecodingError {}
+
+/// Something odd but harmless found while parsing. Parsing carries on, and the warnings are
+/// kept on the `Png` for commands to report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A chunk type that is not in the PNG specification, such as a hidden message.
+    NonStandardChunk {
+        offset: usize,
+        chunk_type: ChunkType,
+    },
+    /// A chunk whose stored CRC is wrong, kept because `ParseOptions::allow_bad_crc` is
+    /// set. The chunk's CRC is recomputed.
+    BadCrc {
+        offset: usize,
+        chunk_type: ChunkType,
+        expected: u32,
+        actual: u32,
+    },
+    /// Bytes after `IEND` that do not parse as chunks, which readers ignore.
+    TrailingData { offset: usize, length: usize },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonStandardChunk { offset, chunk_type } => write!(
+                f,
+                "Non-standard chunk {} at byte offset {}",
+                chunk_type, offset
+            ),
+            Self::BadCrc {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Bad CRC on chunk {} at byte offset {}: expected {:#010x}, computed {:#010x}",
+                chunk_type, offset, expected, actual
+            ),
+            Self::TrailingData { offset, length } => write!(
+                f,
+                "{} bytes of trailing data after IEND at byte offset {}",
+                length, offset
+            ),
+        }
+    }
+}
+
+/// Limits applied while parsing, to guard against hostile input.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// The most chunks a file may contain.
+    pub max_chunks: usize,
+    /// The largest data length accepted for any single chunk. It is checked against the
+    /// declared length before any of the chunk is read.
+    pub max_chunk_size: u32,
+    /// Keep chunks with a bad CRC, recording a `ParseWarning`, instead of failing.
+    pub allow_bad_crc: bool,
+}
+
+impl ParseOptions {
+    /// 64 MiB: far more than real ancillary chunks need, and image data is normally split
+    /// into many smaller `IDAT` chunks.
+    pub const DEFAULT_MAX_CHUNK_SIZE: u32 = 64 * 1024 * 1024;
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_chunks: 100_000,
+            max_chunk_size: Self::DEFAULT_MAX_CHUNK_SIZE,
+            allow_bad_crc: false,
+        }
+    }
+}
+
+/// A parsed PNG. Cloning copies every chunk's data, so a clone can be changed freely, for
+/// example to try an edit and compare the result, without touching the original.
+#[derive(Debug, Clone)]
+pub struct Png {
+    chunks: Vec<Chunk>,
+    warnings: Vec<ParseWarning>,
+}
+
+impl Png {
+    /// The 8-byte signature every PNG file starts with.
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    /// Creates a `Png` from a list of chunks, kept in exactly the given order. Nothing is
+    /// moved or checked, so making the sequence a valid PNG (IHDR first, IEND last, and so
+    /// on) is up to the caller; see `Png::validate_structure`.
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The warnings recorded while this PNG was parsed, in file order. A `Png` that was
+    /// built rather than parsed has none.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// This PNG with `warnings` in place of its own, for an image rebuilt from the chunks
+    /// of a parsed one that should still report what parsing found.
+    pub fn with_warnings(mut self, warnings: Vec<ParseWarning>) -> Png {
+        self.warnings = warnings;
+        self
+    }
+
+    /// How many bytes followed `IEND` in the parsed file without being chunks. Writing the
+    /// image out loses them, such as the archive in a PNG+ZIP polyglot.
+    pub fn trailing_data(&self) -> Option<usize> {
+        self.warnings.iter().find_map(|warning| match warning {
+            ParseWarning::TrailingData { length, .. } => Some(*length),
+            _ => None,
+        })
+    }
+
+    /// Adds a chunk to the image. If the image ends with an `IEND` chunk the new chunk is
+    /// inserted just before it, so the result is still a well-formed PNG. In an APNG that
+    /// is after the last frame's data, so the frames are left in order.
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        let index = self.append_index();
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Adds `data` to the end of the first chunk of type `chunk_type`, recomputing its
+    /// CRC, or appends a new chunk holding `data` if there is no such chunk.
+    pub fn append_to_chunk(&mut self, chunk_type: &ChunkType, data: &[u8]) {
+        match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == chunk_type)
+        {
+            Some(index) => {
+                let mut combined = self.chunks[index].data().to_vec();
+                combined.extend_from_slice(data);
+                self.chunks[index] = Chunk::new(chunk_type.clone(), combined);
+            }
+            None => self.append_chunk(Chunk::new(chunk_type.clone(), data.to_vec())),
+        }
+    }
+
+    /// The index `append_chunk` inserts at: just before a final `IEND`, or the end.
+    pub fn append_index(&self) -> usize {
+        match self.chunks.last() {
+            Some(last) if last.chunk_type().to_string() == "IEND" => self.chunks.len() - 1,
+            _ => self.chunks.len(),
+        }
+    }
+
+    /// The byte offset of the first chunk of type `chunk_type` in the file this PNG
+    /// serializes to. It is a `u64` so the sum never wraps, however many chunks come first.
+    pub fn find_chunk_offset(&self, chunk_type: &str) -> Option<u64> {
+        let mut offset = Self::STANDARD_HEADER.len() as u64;
+        for chunk in &self.chunks {
+            if chunk.chunk_type().to_string() == chunk_type {
+                return Some(offset);
+            }
+            offset += Chunk::OVERHEAD as u64 + u64::from(chunk.length());
+        }
+        None
+    }
+
+    /// Whether this is an animated PNG, which has an `acTL` chunk.
+    pub fn is_animated(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    /// Whether a chunk inserted at `index` would land inside an APNG frame: straight after
+    /// a `fcTL` chunk, or between two `IDAT` or `fdAT` chunks of frame data. Readers expect
+    /// every `fcTL` to be followed directly by the data it describes.
+    pub fn splits_animation_frame(&self, index: usize) -> bool {
+        if !self.is_animated() {
+            return false;
+        }
+        let is_frame_data =
+            |chunk: &Chunk| matches!(&chunk.chunk_type().bytes(), b"IDAT" | b"fdAT");
+        let before = index.checked_sub(1).and_then(|i| self.chunks.get(i));
+        let after = self.chunks.get(index);
+        match (before, after) {
+            (Some(before), _) if before.chunk_type().bytes() == *b"fcTL" => true,
+            (Some(before), Some(after)) => is_frame_data(before) && is_frame_data(after),
+            _ => false,
+        }
+    }
+
+    /// The index of the first critical chunk after `index`, for placing ancillary chunks
+    /// within the right group of critical chunks. `None` if every later chunk is ancillary.
+    pub fn first_critical_after(&self, index: usize) -> Option<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .skip(index.saturating_add(1))
+            .find(|(_, chunk)| chunk.chunk_type().is_critical())
+            .map(|(i, _)| i)
+    }
+
+    /// Removes the first chunk with the given type and returns it.
+    pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .ok_or_else(|| PngDecodingError::ChunkNotFound(chunk_type.to_string()))?;
+        Ok(self.chunks.remove(index))
+    }
+
+    /// Changes the type of the first chunk with type `chunk_type` to `new_type`, keeping
+    /// its data and position. The CRC covers the type, so it is recomputed.
+    pub fn rename_first_chunk(&mut self, chunk_type: &str, new_type: ChunkType) -> Result<()> {
+        let chunk = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+            .ok_or_else(|| PngDecodingError::ChunkNotFound(chunk_type.to_string()))?;
+        *chunk = Chunk::new(new_type, chunk.data().to_vec());
+        Ok(())
+    }
+
+    /// Changes the type of every chunk of type `old` to `new`, keeping their data and
+    /// recomputing their CRCs. Returns how many chunks were changed.
+    pub fn retype_chunks(&mut self, old: &ChunkType, new: &ChunkType) -> usize {
+        let mut changed = 0;
+        for chunk in self.chunks.iter_mut().filter(|c| c.chunk_type() == old) {
+            *chunk = Chunk::new(new.clone(), chunk.data().to_vec());
+            changed += 1;
+        }
+        changed
+    }
+
+    /// The header of this PNG.
+    pub fn header(&self) -> &[u8; 8] {
+        &Self::STANDARD_HEADER
+    }
+
+    /// All of the chunks in this PNG, in file order.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// The type of every chunk, in file order, as a compact fingerprint of the structure.
+    pub fn chunk_types(&self) -> Vec<ChunkType> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().clone())
+            .collect()
+    }
+
+    /// Takes the chunks out of this PNG, in file order.
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
+    /// Keeps only the chunks for which `pred` returns true, in their original order, like
+    /// `Vec::retain`.
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, pred: F) {
+        self.chunks.retain(pred);
+    }
+
+    /// The chunk at `index` in file order, as numbered by `print`, if there is one.
+    pub fn chunk_at(&self, index: usize) -> Option<&Chunk> {
+        self.chunks.get(index)
+    }
+
+    /// The first chunk with the given type, if any.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// The chunk type present in this PNG that is closest to `chunk_type`, if one is within
+    /// two single-letter edits of it. A type that is present is not suggested for itself.
+    pub fn suggest_chunk_type(&self, chunk_type: &str) -> Option<String> {
+        suggest_chunk_type(
+            self.chunks
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string()),
+            chunk_type,
+        )
+    }
+
+    /// Every chunk with the given type, in file order.
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Sets the image's physical resolution to `dpi` on both axes, replacing any existing
+    /// `pHYs` chunk or adding one before the image data, where the spec requires it to be.
+    pub fn set_dpi(&mut self, dpi: f64) {
+        let chunk = Phys::from_dpi(dpi).to_chunk();
+        let position = |name: &str| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type().to_string() == name)
+        };
+        if let Some(index) = position("pHYs") {
+            self.chunks[index] = chunk;
+        } else if let Some(index) = position("IDAT") {
+            self.chunks.insert(index, chunk);
+        } else {
+            self.append_chunk(chunk);
+        }
+    }
+
+    /// Each chunk type with how many times it occurs and its total data bytes, sorted by
+    /// total bytes descending. Types with equal totals keep their first-appearance order.
+    pub fn chunk_type_histogram(&self) -> Vec<(ChunkType, usize, u64)> {
+        let mut histogram: Vec<(ChunkType, usize, u64)> = Vec::new();
+        for chunk in &self.chunks {
+            let length = chunk.length() as u64;
+            match histogram
+                .iter_mut()
+                .find(|(chunk_type, _, _)| chunk_type == chunk.chunk_type())
+            {
+                Some(entry) => {
+                    entry.1 += 1;
+                    entry.2 += length;
+                }
+                None => histogram.push((chunk.chunk_type().clone(), 1, length)),
+            }
+        }
+        histogram.sort_by_key(|(_, _, bytes)| std::cmp::Reverse(*bytes));
+        histogram
+    }
+
+    /// Checks the critical chunks the spec allows only once: exactly one `IHDR`, which comes
+    /// first, at most one `PLTE`, and exactly one `IEND`, which comes last. Also checks the
+    /// image data: at least one `IDAT`, all of them consecutive, and a `PLTE` before them
+    /// that indexed-color images must have. Parsing alone accepts any sequence of
+    /// well-formed chunks.
+    pub fn validate_structure(&self) -> Result<()> {
+        match self.structure_problems().into_iter().next() {
+            Some(problem) => Err(Box::new(problem)),
+            None => Ok(()),
+        }
+    }
+
+    /// Every rule `Png::validate_structure` checks that this PNG breaks, in the order it
+    /// checks them, rather than only the first.
+    pub fn structure_problems(&self) -> Vec<PngDecodingError> {
+        let mut problems = self.duplicated_singletons();
+
+        let is_type = |chunk: Option<&Chunk>, name: &str| {
+            chunk.is_some_and(|chunk| chunk.chunk_type().to_string() == name)
+        };
+        for (name, expected_at) in [("IHDR", self.chunks.first()), ("IEND", self.chunks.last())] {
+            if self.chunk_by_type(name).is_none() {
+                problems.push(PngDecodingError::ChunkNotFound(name.to_string()));
+            } else if !is_type(expected_at, name) {
+                problems.push(PngDecodingError::MisplacedChunk(name.to_string()));
+            }
+        }
+
+        let position = |name: &str| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type().to_string() == name)
+        };
+        let idat = position("IDAT");
+        match idat {
+            None => problems.push(PngDecodingError::ChunkNotFound("IDAT".to_string())),
+            Some(first) => {
+                let count = self.chunks_by_type("IDAT").count();
+                let run = self.chunks[first..]
+                    .iter()
+                    .take_while(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                    .count();
+                if run != count {
+                    problems.push(PngDecodingError::SplitImageData);
+                }
+            }
+        }
+
+        let indexed = self
+            .chunk_by_type("IHDR")
+            .and_then(|ihdr| Ihdr::try_from(ihdr.data()).ok())
+            .is_some_and(|ihdr| ihdr.color_type == 3);
+        match position("PLTE") {
+            None if indexed => problems.push(PngDecodingError::PaletteRequired),
+            Some(plte) if idat.is_some_and(|idat| plte > idat) => {
+                problems.push(PngDecodingError::MisplacedChunk("PLTE".to_string()))
+            }
+            _ => {}
+        }
+        problems
+    }
+
+    /// Checks that none of the critical chunks the spec allows only once, `IHDR`, `PLTE`
+    /// and `IEND`, appear more than once. Unlike `Png::validate_structure` it does not
+    /// mind a missing or misplaced chunk, so it suits PNGs still being built.
+    pub fn check_single_instance(&self) -> Result<()> {
+        match self.duplicated_singletons().into_iter().next() {
+            Some(problem) => Err(Box::new(problem)),
+            None => Ok(()),
+        }
+    }
+
+    fn duplicated_singletons(&self) -> Vec<PngDecodingError> {
+        ["IHDR", "PLTE", "IEND"]
+            .into_iter()
+            .filter(|singleton| self.chunks_by_type(singleton).count() > 1)
+            .map(|singleton| PngDecodingError::DuplicateChunk(singleton.to_string()))
+            .collect()
+    }
+
+    /// Like `Png::as_bytes`, but fails rather than serialize a PNG that breaks the
+    /// single-instance rules of `Png::check_single_instance`.
+    pub fn as_bytes_checked(&self) -> Result<Vec<u8>> {
+        self.check_single_instance()?;
+        Ok(self.as_bytes())
+    }
+
+    /// Returns this PNG as a byte sequence: the standard header followed by every chunk, in
+    /// the order they are stored.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.as_bytes_into(&mut bytes);
+        bytes
+    }
+
+    /// Like `Png::as_bytes`, but clears `buf` and writes into it, so a caller serializing
+    /// many PNGs can reuse one allocation.
+    pub fn as_bytes_into(&self, buf: &mut Vec<u8>) {
+        let size: usize = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.data().len() + Chunk::OVERHEAD)
+            .sum();
+        buf.clear();
+        buf.reserve(Self::STANDARD_HEADER.len() + size);
+        buf.extend_from_slice(&Self::STANDARD_HEADER);
+        for chunk in &self.chunks {
+            buf.extend_from_slice(&chunk.length().to_be_bytes());
+            buf.extend_from_slice(&chunk.chunk_type().bytes());
+            buf.extend_from_slice(chunk.data());
+            buf.extend_from_slice(&chunk.crc().to_be_bytes());
+        }
+    }
+
+    /// Parses a bare sequence of chunks with no PNG signature in front, as used by formats
+    /// that borrow the PNG chunk layout. Real PNG files should go through `Png::try_from`.
+    pub fn from_chunks_bytes(bytes: &[u8]) -> Result<Png> {
+        let (chunks, warnings) = parse_chunks(bytes, 0, &ParseOptions::default())?;
+        Ok(Png { chunks, warnings })
+    }
+
+    /// Parses a PNG file like `Png::try_from`, but with the given limits in place of the
+    /// defaults.
+    pub fn parse_with_options(bytes: &[u8], options: &ParseOptions) -> Result<Png> {
+        check_signature(bytes)?;
+
+        let (chunks, warnings) = parse_chunks(&bytes[8..], 8, options)?;
+        Ok(Png { chunks, warnings })
+    }
+
+    /// Parses as much of a PNG file as possible, for recovering data from truncated or
+    /// damaged files. Returns every chunk read before the first failure, together with that
+    /// failure, or `None` if the whole input parsed. The returned `Png` may be missing
+    /// chunks the format requires, such as `IEND`.
+    pub fn parse_partial(bytes: &[u8]) -> (Png, Option<Error>) {
+        if let Err(e) = check_signature(bytes) {
+            return (Png::from_chunks(Vec::new()), Some(Box::new(e)));
+        }
+
+        let (chunks, warnings, error) =
+            parse_chunks_partial(&bytes[8..], 8, &ParseOptions::default());
+        (Png { chunks, warnings }, error)
+    }
+
+    /// Parses one or more complete PNG files stored back to back, as happens when an image is
+    /// smuggled after the end of another. Each image runs from its signature through its
+    /// `IEND` chunk; anything after the last `IEND` other than another signature is an error.
+    pub fn parse_all(bytes: &[u8]) -> Result<Vec<Png>> {
+        let mut images = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = start + image_length(&bytes[start..])?;
+            images.push(Png::try_from(&bytes[start..end])?);
+            if end == bytes.len() {
+                return Ok(images);
+            }
+            if !is_png_signature(&bytes[end..]) {
+                return Err(Box::new(PngDecodingError::TrailingData { offset: end }));
+            }
+            start = end;
+        }
+    }
+
+    /// Returns every chunk as a byte sequence without the PNG signature; the inverse of
+    /// `Png::from_chunks_bytes`.
+    pub fn as_chunks_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for chunk in &self.chunks {
+            bytes.extend(chunk.as_bytes());
+        }
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Png::parse_with_options(bytes, &ParseOptions::default())
+    }
+}
+
+/// The Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The type in `present` that is closest to `chunk_type`, as `Png::suggest_chunk_type`
+/// picks it, for when the chunk types were seen without keeping the whole PNG.
+pub fn suggest_chunk_type(
+    present: impl IntoIterator<Item = String>,
+    chunk_type: &str,
+) -> Option<String> {
+    present
+        .into_iter()
+        .map(|present| (edit_distance(&present, chunk_type), present))
+        .filter(|(distance, _)| (1..=2).contains(distance))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, present)| present)
+}
+
+/// Whether `bytes` starts with the PNG signature, for sniffing a file before parsing it.
+pub fn is_png_signature(bytes: &[u8]) -> bool {
+    bytes.starts_with(&Png::STANDARD_HEADER)
+}
+
+/// Fails with `TooShort` if `bytes` cannot even hold a PNG signature, such as an empty
+/// file, and with `BadHeader` if it holds something else.
+pub fn check_signature(bytes: &[u8]) -> std::result::Result<(), PngDecodingError> {
+    if bytes.len() < Png::STANDARD_HEADER.len() {
+        return Err(PngDecodingError::TooShort(bytes.len()));
+    }
+    if !is_png_signature(bytes) {
+        return Err(PngDecodingError::BadHeader);
+    }
+    Ok(())
+}
+
+/// Every byte offset in `bytes` where a PNG signature starts, such as the images embedded
+/// in an archive or a game's asset file.
+pub fn find_signatures(bytes: &[u8]) -> Vec<usize> {
+    bytes
+        .windows(Png::STANDARD_HEADER.len())
+        .enumerate()
+        .filter(|(_, window)| *window == Png::STANDARD_HEADER)
+        .map(|(offset, _)| offset)
+        .collect()
+}
+
+/// The length of the PNG file at the start of `bytes`: its signature and every chunk up to
+/// and including the first `IEND`, found by following the length fields. Chunk contents
+/// are not checked here.
+fn image_length(bytes: &[u8]) -> Result<usize> {
+    if !is_png_signature(bytes) {
+        return Err(Box::new(PngDecodingError::BadHeader));
+    }
+    let mut offset = 8;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        offset += Chunk::OVERHEAD + length;
+        if &header[4..] == b"IEND" {
+            return Ok(offset.min(bytes.len()));
+        }
+    }
+    // No IEND: let the parser report what is wrong with the whole remainder.
+    Ok(bytes.len())
+}
+
+/// Parses a bare sequence of chunks, with the warnings found along the way. `base_offset`
+/// is where `bytes` starts within the original input, and is only used in messages.
+fn parse_chunks(
+    bytes: &[u8],
+    base_offset: usize,
+    options: &ParseOptions,
+) -> Result<(Vec<Chunk>, Vec<ParseWarning>)> {
+    match parse_chunks_partial(bytes, base_offset, options) {
+        (chunks, warnings, None) => Ok((chunks, warnings)),
+        (_, _, Some(error)) => Err(error),
+    }
+}
+
+/// Like `parse_chunks`, but keeps the chunks read before the first failure. Bytes that
+/// fail to parse once an `IEND` has been read are trailing data, a warning, not a failure,
+/// unless they start another PNG signature.
+fn parse_chunks_partial(
+    bytes: &[u8],
+    base_offset: usize,
+    options: &ParseOptions,
+) -> (Vec<Chunk>, Vec<ParseWarning>, Option<Error>) {
+    let mut chunks = Vec::new();
+    let mut warnings = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        if chunks.len() == options.max_chunks {
+            let limit = options.max_chunks;
+            return (
+                chunks,
+                warnings,
+                Some(Box::new(PngDecodingError::TooManyChunks { limit })),
+            );
+        }
+        let rest = &bytes[offset..];
+        match read_chunk(rest, base_offset + offset, options, &mut warnings) {
+            Ok((chunk, length)) => {
+                if !chunk.chunk_type().is_standard() {
+                    warnings.push(ParseWarning::NonStandardChunk {
+                        offset: base_offset + offset,
+                        chunk_type: chunk.chunk_type().clone(),
+                    });
+                }
+                chunks.push(chunk);
+                offset += length;
+            }
+            // A second image is not mere trailing data; see `Png::parse_all`.
+            Err(_)
+                if chunks.iter().any(|c| c.chunk_type().bytes() == *b"IEND")
+                    && !is_png_signature(rest) =>
+            {
+                warnings.push(ParseWarning::TrailingData {
+                    offset: base_offset + offset,
+                    length: rest.len(),
+                });
+                break;
+            }
+            Err(error) => return (chunks, warnings, Some(error)),
+        }
+    }
+    (chunks, warnings, None)
+}
+
+/// Reads the chunk at the start of `rest`, which is at `offset` in the input, and returns
+/// it with the number of bytes it took up.
+fn read_chunk(
+    rest: &[u8],
+    offset: usize,
+    options: &ParseOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<(Chunk, usize)> {
+    let truncated = PngDecodingError::Truncated { offset };
+    if rest.len() < 12 {
+        return Err(Box::new(truncated));
+    }
+    let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+    if length > options.max_chunk_size {
+        return Err(Box::new(PngDecodingError::ChunkTooLong {
+            offset,
+            length,
+            limit: options.max_chunk_size,
+        }));
+    }
+    let end = 12 + length as usize;
+    if rest.len() < end {
+        return Err(Box::new(truncated));
+    }
+    let error = match Chunk::try_from(&rest[..end]) {
+        Ok(chunk) => return Ok((chunk, end)),
+        Err(error) => error,
+    };
+    match error.downcast_ref() {
+        Some(&ChunkDecodingError::BadCrc { expected, actual }) if options.allow_bad_crc => {
+            let chunk_type = ChunkType::try_from([rest[4], rest[5], rest[6], rest[7]])?;
+            warnings.push(ParseWarning::BadCrc {
+                offset,
+                chunk_type: chunk_type.clone(),
+                expected,
+                actual,
+            });
+            Ok((Chunk::new(chunk_type, rest[8..end - 4].to_vec()), end))
+        }
+        _ => Err(error),
+    }
+}
+
+impl fmt::Display for Png {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Png {{")?;
+        for chunk in &self.chunks {
+            writeln!(f, "  {} ({} bytes)", chunk.chunk_type(), chunk.length())?;
+        }
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+/// Assembles a PNG from scratch: an `IHDR` built from the given parameters, any extra
+/// chunks, the image data and a closing `IEND`, in that order.
+///
+/// The builder does not encode pixels; `idat` takes data that is already filtered and
+/// compressed.
+#[derive(Debug)]
+pub struct PngBuilder {
+    ihdr: Ihdr,
+    chunks: Vec<Chunk>,
+    idat: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    /// Starts an 8-bit truecolor, non-interlaced image of the given size.
+    pub fn new(width: u32, height: u32) -> PngBuilder {
+        PngBuilder {
+            ihdr: Ihdr {
+                width,
+                height,
+                bit_depth: 8,
+                color_type: 2,
+                compression_method: 0,
+                filter_method: 0,
+                interlace_method: Ihdr::INTERLACE_NONE,
+            },
+            chunks: Vec::new(),
+            idat: Vec::new(),
+        }
+    }
+
+    pub fn bit_depth(mut self, bit_depth: u8) -> PngBuilder {
+        self.ihdr.bit_depth = bit_depth;
+        self
+    }
+
+    pub fn color_type(mut self, color_type: u8) -> PngBuilder {
+        self.ihdr.color_type = color_type;
+        self
+    }
+
+    pub fn interlace_method(mut self, interlace_method: u8) -> PngBuilder {
+        self.ihdr.interlace_method = interlace_method;
+        self
+    }
+
+    /// Adds a chunk to go between `IHDR` and the image data, such as `PLTE` or `tEXt`.
+    pub fn chunk(mut self, chunk: Chunk) -> PngBuilder {
+        self.chunks.push(chunk);
+        self
+    }
+
+    /// Adds an `IDAT` chunk. Call it more than once to split the image data.
+    pub fn idat(mut self, data: Vec<u8>) -> PngBuilder {
+        let chunk_type = ChunkType::from_str("IDAT").expect("IDAT is a valid chunk type");
+        self.idat.push(Chunk::new(chunk_type, data));
+        self
+    }
+
+    pub fn build(self) -> Png {
+        let iend = ChunkType::from_str("IEND").expect("IEND is a valid chunk type");
+        let mut chunks = vec![self.ihdr.to_chunk()];
+        chunks.extend(self.chunks);
+        chunks.extend(self.idat);
+        chunks.push(Chunk::new(iend, Vec::new()));
+        Png::from_chunks(chunks)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::convert::TryFrom;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(testing_chunks())
+    }
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        let data: Vec<u8> = data.bytes().collect();
+
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        let chunks = testing_chunks();
+        let png = Png::from_chunks(chunks);
+
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_from_chunks_preserves_odd_order() {
+        let order = ["IEND", "ruSt", "IDAT", "IHDR", "teXt"];
+        let chunks = order
+            .iter()
+            .map(|chunk_type| chunk_from_strings(chunk_type, chunk_type).unwrap())
+            .collect();
+        let png = Png::from_chunks(chunks);
+
+        let expected: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(order.iter().flat_map(|chunk_type| {
+                chunk_from_strings(chunk_type, chunk_type)
+                    .unwrap()
+                    .as_bytes()
+            }))
+            .collect();
+        assert_eq!(png.as_bytes(), expected);
+
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let types: Vec<String> = reparsed
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, order);
+    }
+
+    #[test]
+    fn test_valid_from_bytes() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_standard_header_starts_as_bytes() {
+        let png = testing_png();
+        assert_eq!(Png::STANDARD_HEADER, *b"\x89PNG\r\n\x1a\n");
+        assert_eq!(png.header(), &Png::STANDARD_HEADER);
+        assert_eq!(png.as_bytes()[..8], Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_too_short() {
+        for length in [0, 5] {
+            let err = Png::try_from(&PNG_FILE[..length]).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(PngDecodingError::TooShort(l)) if *l == length
+            ));
+
+            let (png, error) = Png::parse_partial(&PNG_FILE[..length]);
+            assert!(png.chunks().is_empty());
+            assert!(matches!(
+                error.unwrap().downcast_ref(),
+                Some(PngDecodingError::TooShort(_))
+            ));
+        }
+        assert!(matches!(
+            check_signature(&[0; 8]),
+            Err(PngDecodingError::BadHeader)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let mut chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        #[rustfmt::skip]
+        let mut bad_chunk = vec![
+            0, 0, 0, 5,         // length
+            32, 117, 83, 116,   // Chunk Type (bad)
+            65, 64, 65, 66, 67, // Data
+            1, 2, 3, 4, 5       // CRC (bad)
+        ];
+
+        chunk_bytes.append(&mut bad_chunk);
+
+        let png = Png::try_from(chunk_bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_list_chunks() {
+        let png = testing_png();
+        let chunks = png.chunks();
+        assert_eq!(chunks.len(), 3);
+    }
+
+    #[test]
+    fn test_chunk_by_type() {
+        let png = testing_png();
+        let chunk = png.chunk_by_type("FrSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Again").unwrap());
+        let chunks: Vec<&Chunk> = png.chunks_by_type("miDl").collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[1].data_as_string().unwrap(), "Again");
+        assert_eq!(png.chunks_by_type("nONe").count(), 0);
+    }
+
+    #[test]
+    fn test_append_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"junk");
+        let original = Png::try_from(bytes.as_slice()).unwrap();
+
+        let mut copy = original.clone();
+        assert_eq!(copy.as_bytes(), original.as_bytes());
+        assert_eq!(copy.warnings(), original.warnings());
+        copy.append_chunk(chunk_from_strings("ruSt", "only in the copy").unwrap());
+        copy.remove_first_chunk("IDAT").unwrap();
+
+        assert_eq!(original.as_bytes(), PNG_FILE);
+        assert!(original.chunk_by_type("ruSt").is_none());
+        assert_eq!(copy.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let chunks = png.chunks();
+        assert_eq!(&chunks[chunks.len() - 2].chunk_type().to_string(), "ruSt");
+        assert_eq!(&chunks[chunks.len() - 1].chunk_type().to_string(), "IEND");
+    }
+
+    /// The chunk order of a two-frame APNG, with the `IDAT` as the first frame.
+    const APNG_ORDER: [&str; 8] = [
+        "IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fdAT", "IEND",
+    ];
+
+    fn apng_like() -> Png {
+        Png::from_chunks(
+            APNG_ORDER
+                .iter()
+                .map(|chunk_type| chunk_from_strings(chunk_type, "").unwrap())
+                .collect(),
+        )
+    }
+
+    fn chunk_order(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_append_chunk_keeps_animation_frames_in_order() {
+        let mut png = apng_like();
+        assert!(png.is_animated());
+        assert!(!png.splits_animation_frame(png.append_index()));
+
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let mut expected = APNG_ORDER.to_vec();
+        expected.insert(7, "ruSt");
+        assert_eq!(chunk_order(&png), expected);
+    }
+
+    #[test]
+    fn test_splits_animation_frame() {
+        let png = apng_like();
+        // Between acTL and the first fcTL, and between the frames, is fine.
+        assert!(!png.splits_animation_frame(2));
+        assert!(!png.splits_animation_frame(4));
+        // Straight after a fcTL, or between the fdAT chunks of a frame, is not.
+        assert!(png.splits_animation_frame(3));
+        assert!(png.splits_animation_frame(5));
+        assert!(png.splits_animation_frame(6));
+
+        // Without acTL the image is not animated, so nothing is a frame.
+        assert!(!Png::try_from(&PNG_FILE[..])
+            .unwrap()
+            .splits_animation_frame(2));
+    }
+
+    #[test]
+    fn test_first_critical_after() {
+        // IHDR, acTL, fcTL, IDAT, fcTL, fdAT, fdAT, IEND
+        let png = apng_like();
+        assert_eq!(png.first_critical_after(0), Some(3));
+        assert_eq!(png.first_critical_after(1), Some(3));
+        assert_eq!(png.first_critical_after(3), Some(7));
+        assert_eq!(png.first_critical_after(7), None);
+        assert_eq!(png.first_critical_after(100), None);
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.first_critical_after(0), Some(1));
+        assert_eq!(png.first_critical_after(1), Some(2));
+    }
+
+    #[test]
+    fn test_append_to_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        png.append_to_chunk(&chunk_type, b"first");
+        png.append_to_chunk(&chunk_type, b" second");
+
+        let chunks: Vec<&Chunk> = png.chunks_by_type("ruSt").collect();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(
+            chunks[0],
+            &chunk_from_strings("ruSt", "first second").unwrap()
+        );
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+    }
+
+    #[test]
+    fn test_remove_first_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.remove_first_chunk("TeSt").unwrap();
+        let chunk = png.chunk_by_type("TeSt");
+        assert!(chunk.is_none());
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        assert!(png.remove_first_chunk("TeSt").is_err());
+    }
+
+    #[test]
+    fn test_chunk_types() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let types: Vec<String> = png.chunk_types().iter().map(ToString::to_string).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+
+        assert_eq!(
+            apng_like()
+                .chunk_types()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            APNG_ORDER
+        );
+    }
+
+    #[test]
+    fn test_rename_first_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let index = png.chunks().len() - 1;
+        png.rename_first_chunk("ruSt", ChunkType::from_str("teSt").unwrap())
+            .unwrap();
+
+        let renamed = &png.chunks()[index];
+        assert_eq!(renamed, &chunk_from_strings("teSt", "Message").unwrap());
+        assert!(png.chunk_by_type("ruSt").is_none());
+        assert!(png
+            .rename_first_chunk("ruSt", ChunkType::from_str("teSt").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_retype_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("ruSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("ruSt", "second").unwrap());
+        let old = ChunkType::from_str("ruSt").unwrap();
+        let new = ChunkType::from_str("teSt").unwrap();
+
+        assert_eq!(png.retype_chunks(&old, &new), 2);
+        assert!(png.chunk_by_type("ruSt").is_none());
+        let data: Vec<&[u8]> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == &new)
+            .map(Chunk::data)
+            .collect();
+        assert_eq!(data, [&b"first"[..], &b"second"[..]]);
+        assert_eq!(png.retype_chunks(&old, &new), 0);
+    }
+
+    #[test]
+    fn test_png_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]);
+        assert!(png.is_ok());
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        let png = Png::try_from(&PNG_FILE[..PNG_FILE.len() - 3]);
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let actual = png.as_bytes();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_chunks_bytes_round_trip() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let stream = png.as_chunks_bytes();
+        assert_eq!(&stream[..], &PNG_FILE[8..]);
+
+        let parsed = Png::from_chunks_bytes(&stream).unwrap();
+        assert_eq!(parsed.chunks().len(), 3);
+        assert_eq!(parsed.as_chunks_bytes(), stream);
+    }
+
+    #[test]
+    fn test_chunks_bytes_requires_explicit_choice() {
+        assert!(Png::try_from(&PNG_FILE[8..]).is_err());
+        assert!(Png::from_chunks_bytes(&PNG_FILE[..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_partial_stops_at_truncation() {
+        // Cut the file part way through the IDAT chunk's data.
+        let (png, error) = Png::parse_partial(&PNG_FILE[..45]);
+        assert_eq!(png.chunks().len(), 1);
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "IHDR");
+        assert!(matches!(
+            error.unwrap().downcast_ref(),
+            Some(PngDecodingError::Truncated { offset: 33 })
+        ));
+    }
+
+    #[test]
+    fn test_parse_partial_complete_file() {
+        let (png, error) = Png::parse_partial(&PNG_FILE);
+        assert!(error.is_none());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_partial_bad_header() {
+        let (png, error) = Png::parse_partial(&PNG_FILE[8..]);
+        assert!(png.chunks().is_empty());
+        assert!(error.is_some());
+    }
+
+    #[test]
+    fn test_validate_structure() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.validate_structure().is_ok());
+    }
+
+    #[test]
+    fn test_validate_structure_duplicate_ihdr() {
+        let mut chunks = Png::try_from(&PNG_FILE[..]).unwrap().chunks;
+        let ihdr = Chunk::try_from(&chunks[0].as_bytes()[..]).unwrap();
+        chunks.insert(1, ihdr);
+        let png = Png::from_chunks(chunks);
+
+        // The parser itself accepts the doubled header...
+        assert!(Png::try_from(&png.as_bytes()[..]).is_ok());
+        // ...but the structure check does not.
+        let err = png.validate_structure().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::DuplicateChunk(chunk_type)) if chunk_type == "IHDR"
+        ));
+    }
+
+    #[test]
+    fn test_structure_problems_lists_every_rule_broken() {
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "no header").unwrap()]);
+        let problems: Vec<String> = png
+            .structure_problems()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "Chunk not found: IHDR",
+                "Chunk not found: IEND",
+                "Chunk not found: IDAT"
+            ]
+        );
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.structure_problems().is_empty());
+    }
+
+    #[test]
+    fn test_as_bytes_checked_rejects_duplicate_ihdr() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.as_bytes_checked().unwrap(), PNG_FILE);
+
+        let ihdr = png.chunk_by_type("IHDR").unwrap().clone();
+        png.append_chunk(ihdr);
+        let err = png.as_bytes_checked().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::DuplicateChunk(chunk_type)) if chunk_type == "IHDR"
+        ));
+
+        // A PNG still missing its IEND is not a single-instance problem.
+        let png = Png::from_chunks(vec![chunk_from_strings("IHDR", "").unwrap()]);
+        assert!(png.check_single_instance().is_ok());
+    }
+
+    #[test]
+    fn test_structure_problems_image_data() {
+        let problems = |png: &Png| -> Vec<String> {
+            png.structure_problems()
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        let plte = || Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; 3]);
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.chunks.remove(1);
+        assert_eq!(problems(&png), ["Chunk not found: IDAT"]);
+
+        let png = PngBuilder::new(1, 1)
+            .chunk(chunk_from_strings("ruSt", "between").unwrap())
+            .idat(vec![1])
+            .idat(vec![2])
+            .build();
+        // IHDR, IDAT, ruSt, IDAT, IEND
+        let mut chunks = png.into_chunks();
+        chunks.swap(1, 2);
+        assert_eq!(
+            problems(&Png::from_chunks(chunks)),
+            ["Split image data: IDAT chunks must be consecutive"]
+        );
+
+        let png = PngBuilder::new(1, 1).color_type(3).idat(vec![1]).build();
+        assert_eq!(
+            problems(&png),
+            ["Chunk not found: PLTE is required for indexed-color images"]
+        );
+
+        let png = PngBuilder::new(1, 1).color_type(3).idat(vec![1]).build();
+        let mut chunks = png.into_chunks();
+        chunks.insert(2, plte());
+        assert_eq!(
+            problems(&Png::from_chunks(chunks)),
+            ["Misplaced chunk: PLTE is out of order"]
+        );
+
+        let png = PngBuilder::new(1, 1)
+            .color_type(3)
+            .chunk(plte())
+            .idat(vec![1])
+            .build();
+        assert!(problems(&png).is_empty());
+    }
+
+    #[test]
+    fn test_validate_structure_duplicate_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.chunks.push(chunk_from_strings("IEND", "").unwrap());
+        assert!(png.validate_structure().is_err());
+    }
+
+    #[test]
+    fn test_validate_structure_order() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.chunks.swap(0, 1);
+        assert!(png.validate_structure().is_err());
+
+        let png = Png::from_chunks(testing_chunks());
+        assert!(png.validate_structure().is_err());
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("teXt", "a note").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+            chunk_from_strings("ruSt", "secret").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_max_chunks() {
+        let options = ParseOptions {
+            max_chunks: 2,
+            ..ParseOptions::default()
+        };
+        let err = Png::parse_with_options(&PNG_FILE, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::TooManyChunks { limit: 2 })
+        ));
+
+        let options = ParseOptions {
+            max_chunks: 3,
+            ..ParseOptions::default()
+        };
+        assert!(Png::parse_with_options(&PNG_FILE, &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_chunk_size() {
+        let options = ParseOptions {
+            max_chunk_size: 12,
+            ..ParseOptions::default()
+        };
+        // IHDR holds 13 bytes of data.
+        let err = Png::parse_with_options(&PNG_FILE, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::ChunkTooLong { offset: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_oversized_length_rejected_by_default() {
+        // An IHDR chunk header claiming 2 GiB of data, with nothing behind it.
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&0x7fff_ffffu32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&[0; 8]);
+
+        let err = Png::try_from(bytes.as_slice()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::ChunkTooLong {
+                length: 0x7fff_ffff,
+                limit: ParseOptions::DEFAULT_MAX_CHUNK_SIZE,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_chunk_at() {
+        let png = testing_png();
+        assert_eq!(png.chunk_at(0).unwrap().chunk_type().to_string(), "FrSt");
+        assert_eq!(png.chunk_at(2).unwrap().chunk_type().to_string(), "LASt");
+        assert!(png.chunk_at(3).is_none());
+    }
+
+    #[test]
+    fn test_find_chunk_offset() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.find_chunk_offset("IHDR"), Some(8));
+        assert_eq!(png.find_chunk_offset("IDAT"), Some(33));
+        assert_eq!(png.find_chunk_offset("IEND"), Some(57));
+        assert_eq!(png.find_chunk_offset("ruSt"), None);
+
+        // 400 chunks of 10 MiB would pass u32::MAX; empty ones just check the sum's type.
+        let filler = chunk_from_strings("fiLl", "").unwrap();
+        let mut chunks = vec![filler; 400];
+        chunks.push(chunk_from_strings("ruSt", "").unwrap());
+        let offset: u64 = Png::from_chunks(chunks).find_chunk_offset("ruSt").unwrap();
+        assert_eq!(offset, 8 + 400 * 12);
+    }
+
+    #[test]
+    fn test_is_png_signature() {
+        assert!(is_png_signature(&Png::STANDARD_HEADER));
+        assert!(is_png_signature(&PNG_FILE));
+        // A JPEG starts with an SOI marker and then an APP0 or APP1 marker.
+        assert!(!is_png_signature(&[
+            0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F'
+        ]));
+        assert!(!is_png_signature(&Png::STANDARD_HEADER[..7]));
+    }
+
+    #[test]
+    fn test_find_signatures() {
+        let mut container = b"container header".to_vec();
+        container.extend_from_slice(&PNG_FILE);
+        container.extend_from_slice(b"more data");
+        container.extend_from_slice(&PNG_FILE);
+        assert_eq!(find_signatures(&container), [16, 16 + PNG_FILE.len() + 9]);
+        assert!(find_signatures(b"no images here").is_empty());
+        assert!(find_signatures(&Png::STANDARD_HEADER[..7]).is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("ruSt", "ruSt"), 0);
+        assert_eq!(edit_distance("ruSr", "ruSt"), 1);
+        assert_eq!(edit_distance("ruSt", "RuST"), 2);
+        assert_eq!(edit_distance("abcd", "wxyz"), 4);
+        assert_eq!(edit_distance("", "IEND"), 4);
+    }
+
+    #[test]
+    fn test_suggest_chunk_type() {
+        let png = testing_png();
+        assert_eq!(png.suggest_chunk_type("miDI").as_deref(), Some("miDl"));
+        assert_eq!(png.suggest_chunk_type("FRST").as_deref(), Some("FrSt"));
+        assert!(png.suggest_chunk_type("zzzz").is_none());
+        assert!(png.suggest_chunk_type("miDl").is_none());
+    }
+
+    #[test]
+    fn test_as_bytes_into_matches_as_bytes() {
+        let png = testing_png();
+        let mut buf = b"left over from a previous image".to_vec();
+        png.as_bytes_into(&mut buf);
+        assert_eq!(buf, png.as_bytes());
+
+        let parsed = Png::try_from(&PNG_FILE[..]).unwrap();
+        parsed.as_bytes_into(&mut buf);
+        assert_eq!(buf, PNG_FILE);
+    }
+
+    #[test]
+    fn test_builder_one_pixel() {
+        // A zlib stream holding one scanline: filter type 0, then one gray pixel.
+        let idat = vec![0x78, 0x9c, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01];
+        let png = PngBuilder::new(1, 1)
+            .color_type(0)
+            .chunk(chunk_from_strings("tEXt", "Title\0dot").unwrap())
+            .idat(idat)
+            .build();
+
+        let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        parsed.validate_structure().unwrap();
+        let types: Vec<String> = parsed
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IDAT", "IEND"]);
+
+        let ihdr = parsed.chunks()[0].as_ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height, ihdr.color_type), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_parse_all_concatenated() {
+        let mut second = Png::try_from(&PNG_FILE[..]).unwrap();
+        second.append_chunk(chunk_from_strings("ruSt", "the second image").unwrap());
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(second.as_bytes());
+
+        // Strict parsing does not accept the second signature.
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let images = Png::parse_all(&bytes).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].as_bytes(), PNG_FILE);
+        assert!(images[1].chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_parse_all_single_and_trailing_garbage() {
+        assert_eq!(Png::parse_all(&PNG_FILE).unwrap().len(), 1);
+
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"junk");
+        let err = Png::parse_all(&bytes).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PngDecodingError::TrailingData { offset: 69 })
+        ));
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().warnings().is_empty());
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "hidden").unwrap());
+        let mut bytes = png.as_bytes();
+        let custom_offset = bytes.len() - 12 - (12 + 6);
+        let trailing_offset = bytes.len();
+        bytes.extend_from_slice(b"junk");
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(
+            png.warnings(),
+            [
+                ParseWarning::NonStandardChunk {
+                    offset: custom_offset,
+                    chunk_type: ChunkType::from_str("ruSt").unwrap(),
+                },
+                ParseWarning::TrailingData {
+                    offset: trailing_offset,
+                    length: 4,
+                },
+            ]
+        );
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+    }
+
+    #[test]
+    fn test_parse_bad_crc_allowed_with_warning() {
+        let mut bytes = PNG_FILE.to_vec();
+        // The last byte of the IEND chunk's CRC.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let options = ParseOptions {
+            allow_bad_crc: true,
+            ..ParseOptions::default()
+        };
+        let png = Png::parse_with_options(&bytes, &options).unwrap();
+        assert!(matches!(
+            png.warnings(),
+            [ParseWarning::BadCrc { offset, .. }] if *offset == bytes.len() - 12
+        ));
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_set_dpi() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_dpi(300.0);
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "pHYs");
+        assert_eq!(&png.chunks()[2].chunk_type().to_string(), "IDAT");
+
+        png.set_dpi(72.0);
+        assert_eq!(png.chunks().len(), 4);
+        let (x, _) = png
+            .chunk_by_type("pHYs")
+            .unwrap()
+            .as_phys()
+            .unwrap()
+            .dpi()
+            .unwrap();
+        assert_eq!(x.round(), 72.0);
+    }
+
+    #[test]
+    fn test_chunk_type_histogram() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "1234567890123").unwrap(),
+            chunk_from_strings("IDAT", "first image data").unwrap(),
+            chunk_from_strings("teXt", "a note").unwrap(),
+            chunk_from_strings("IDAT", "second image data").unwrap(),
+            chunk_from_strings("IDAT", "third").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+
+        let histogram: Vec<(String, usize, u64)> = png
+            .chunk_type_histogram()
+            .into_iter()
+            .map(|(chunk_type, count, bytes)| (chunk_type.to_string(), count, bytes))
+            .collect();
+
+        assert_eq!(
+            histogram,
+            vec![
+                ("IDAT".to_string(), 3, 38),
+                ("IHDR".to_string(), 1, 13),
+                ("teXt".to_string(), 1, 6),
+                ("IEND".to_string(), 1, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+        let _png_string = format!("{}", png);
+    }
+
+    /// A 1x1 RGB image: IHDR, IDAT, IEND.
+    pub(crate) const PNG_FILE: [u8; 69] = [
+        137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1, 8, 2,
+        0, 0, 0, 144, 119, 83, 222, 0, 0, 0, 12, 73, 68, 65, 84, 120, 156, 99, 248, 207, 192, 0, 0,
+        3, 1, 1, 0, 201, 254, 146, 239, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+    ];
+}
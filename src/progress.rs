@@ -0,0 +1,130 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const BAR_WIDTH: usize = 30;
+
+/// A files-completed progress bar drawn on one line of stderr.
+///
+/// It is only shown when stderr is a terminal and `--quiet` was not given, so piped or
+/// redirected output never contains it. Without the `progress` feature it is never shown.
+///
+/// Only `batch` draws one. Single-file commands such as `encode` read the file once and
+/// write it once, with nothing in between to count.
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    sink: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Progress {
+    /// A progress bar on stderr for `total` items, if one should be shown.
+    pub fn stderr(total: usize, quiet: bool) -> Progress {
+        let sink: Option<Box<dyn Write + Send>> =
+            enabled(quiet, io::stderr().is_terminal()).then(|| Box::new(io::stderr()) as _);
+        Progress::new(total, sink)
+    }
+
+    /// A progress bar drawn on `sink`, or a silent one without a sink.
+    pub fn new(total: usize, sink: Option<Box<dyn Write + Send>>) -> Progress {
+        Progress {
+            total,
+            done: AtomicUsize::new(0),
+            sink: sink.map(Mutex::new),
+        }
+    }
+
+    /// Records one finished item and redraws the bar.
+    pub fn tick(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.draw(&format!("\r{}", bar(done, self.total)));
+    }
+
+    /// Clears the bar so later stderr output starts on a clean line.
+    pub fn finish(&self) {
+        self.draw(&format!("\r{}\r", " ".repeat(BAR_WIDTH + 24)));
+    }
+
+    fn draw(&self, text: &str) {
+        if let Some(sink) = &self.sink {
+            let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+            // A progress bar that fails to draw is not worth failing the command over.
+            let _ = sink.write_all(text.as_bytes()).and_then(|()| sink.flush());
+        }
+    }
+}
+
+/// Whether to show progress, given `--quiet` and whether stderr is a terminal.
+fn enabled(quiet: bool, is_terminal: bool) -> bool {
+    cfg!(feature = "progress") && !quiet && is_terminal
+}
+
+/// One frame of the bar, such as `[=========>          ] 3/10 files`.
+fn bar(done: usize, total: usize) -> String {
+    let filled = (done * BAR_WIDTH)
+        .checked_div(total)
+        .unwrap_or(BAR_WIDTH)
+        .min(BAR_WIDTH);
+    let rest = match BAR_WIDTH - filled {
+        0 => String::new(),
+        empty => format!(">{}", " ".repeat(empty - 1)),
+    };
+    format!("[{}{}] {}/{} files", "=".repeat(filled), rest, done, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A sink the test can read back after the bar has written to it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_suppressed_without_terminal_or_with_quiet() {
+        assert!(!enabled(false, false));
+        assert!(!enabled(true, true));
+        assert_eq!(enabled(false, true), cfg!(feature = "progress"));
+    }
+
+    #[test]
+    fn test_silent_progress_draws_nothing() {
+        let progress = Progress::new(3, None);
+        assert!(progress.sink.is_none());
+        progress.tick();
+        progress.finish();
+    }
+
+    #[test]
+    fn test_bar_frames() {
+        assert_eq!(bar(0, 2), format!("[>{}] 0/2 files", " ".repeat(29)));
+        assert_eq!(
+            bar(1, 2),
+            format!("[{}>{}] 1/2 files", "=".repeat(15), " ".repeat(14))
+        );
+        assert_eq!(bar(2, 2), format!("[{}] 2/2 files", "=".repeat(30)));
+        assert_eq!(bar(0, 0), format!("[{}] 0/0 files", "=".repeat(30)));
+    }
+
+    #[test]
+    fn test_tick_draws_on_sink() {
+        let buffer = SharedBuffer::default();
+        let progress = Progress::new(2, Some(Box::new(buffer.clone())));
+        progress.tick();
+        progress.tick();
+        let drawn = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(drawn.contains("1/2 files"));
+        assert!(drawn.ends_with("2/2 files"));
+    }
+}
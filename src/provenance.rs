@@ -0,0 +1,107 @@
+//! Recognises the software that last wrote an image from the `Software` and `Comment`
+//! text chunks that editors leave behind, for `print --detect`.
+
+use crate::png::Png;
+use crate::text;
+use std::fmt;
+
+/// Text that identifies an editor, matched case-insensitively anywhere in the value, and the
+/// editor's name. More specific patterns come first.
+pub const EDITORS: [(&str, &str); 12] = [
+    ("imageready", "Adobe ImageReady"),
+    ("photoshop", "Adobe Photoshop"),
+    ("adobe", "Adobe"),
+    ("gimp", "GIMP"),
+    ("graphicsmagick", "GraphicsMagick"),
+    ("imagemagick", "ImageMagick"),
+    ("paint.net", "Paint.NET"),
+    ("inkscape", "Inkscape"),
+    ("krita", "Krita"),
+    ("pillow", "Pillow (Python)"),
+    ("optipng", "OptiPNG"),
+    ("pngcrush", "pngcrush"),
+];
+
+/// The text chunk keywords that usually name the software.
+const KEYWORDS: [&str; 2] = ["Software", "Comment"];
+
+/// A `Software` or `Comment` value, with the editor it names if it is a known one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub keyword: String,
+    pub value: String,
+    pub editor: Option<&'static str>,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.value)?;
+        if let Some(editor) = self.editor {
+            write!(f, " (detected: {})", editor)?;
+        }
+        Ok(())
+    }
+}
+
+/// The editor named in `value`, if it matches one of the `EDITORS`.
+pub fn identify(value: &str) -> Option<&'static str> {
+    let value = value.to_lowercase();
+    EDITORS
+        .iter()
+        .find(|(pattern, _)| value.contains(pattern))
+        .map(|(_, editor)| *editor)
+}
+
+/// Every `Software` and `Comment` value in the `tEXt`, `zTXt` and `iTXt` chunks of `png`,
+/// in file order. Chunks that fail to decode are skipped.
+pub fn detect(png: &Png) -> Vec<Detection> {
+    png.chunks()
+        .iter()
+        .filter_map(|chunk| match &chunk.chunk_type().bytes() {
+            b"tEXt" => text::decode_text(chunk.data()).ok(),
+            b"zTXt" => text::decode_ztxt(chunk.data()).ok(),
+            b"iTXt" => text::decode_itxt(chunk.data()).ok(),
+            _ => None,
+        })
+        .filter(|(keyword, _)| KEYWORDS.contains(&keyword.as_str()))
+        .map(|(keyword, value)| Detection {
+            editor: identify(&value),
+            keyword,
+            value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify("Adobe ImageReady"), Some("Adobe ImageReady"));
+        assert_eq!(identify("Created with GIMP"), Some("GIMP"));
+        assert_eq!(identify("ImageMagick 7.1.0"), Some("ImageMagick"));
+        assert_eq!(identify("GraphicsMagick 1.3"), Some("GraphicsMagick"));
+        assert_eq!(identify("my own tool"), None);
+    }
+
+    #[test]
+    fn test_detect_software_text_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(Chunk::new_text("Software", "Adobe Photoshop 25.0").unwrap());
+        png.append_chunk(Chunk::new_text("Comment", "drawn by hand").unwrap());
+        png.append_chunk(Chunk::new_text("Title", "GIMP is not the software here").unwrap());
+
+        let detections = detect(&png);
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].editor, Some("Adobe Photoshop"));
+        assert_eq!(
+            detections[0].to_string(),
+            "Software: Adobe Photoshop 25.0 (detected: Adobe Photoshop)"
+        );
+        // Unknown software is shown verbatim.
+        assert_eq!(detections[1].to_string(), "Comment: drawn by hand");
+    }
+}
@@ -0,0 +1,449 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crc::Crc32;
+use crate::png::{self, ParseOptions, ParseWarning, Png};
+use crate::Result;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum RepairError {
+    BadHeader,
+    /// The chunk starting at `offset` could not be bounded; `reason` says why.
+    Unrecoverable {
+        offset: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadHeader => write!(f, "Bad header: not a PNG signature"),
+            Self::Unrecoverable { offset, reason } => {
+                write!(
+                    f,
+                    "Cannot repair chunk at byte offset {}: {}",
+                    offset, reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for RepairError {}
+
+/// A length field that was rewritten.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LengthFix {
+    /// Where the chunk starts in the file.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub declared: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for LengthFix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} chunk at byte offset {}: length {} corrected to {}",
+            self.chunk_type, self.offset, self.declared, self.actual
+        )
+    }
+}
+
+/// Rewrites chunk length fields that disagree with the chunk's data.
+///
+/// A chunk whose declared length does not lead to a matching CRC is re-measured by finding
+/// the first point after its type where the CRC of everything so far matches the next four
+/// bytes, and which is followed by either the end of the file or another plausible chunk
+/// type. The CRC is what makes this reliable: a false match is a one in four billion chance.
+/// Returns the repaired file and the fixes made.
+pub fn fix_lengths(bytes: &[u8]) -> Result<(Vec<u8>, Vec<LengthFix>)> {
+    if !png::is_png_signature(bytes) {
+        return Err(Box::new(RepairError::BadHeader));
+    }
+
+    let mut repaired = bytes[..8].to_vec();
+    let mut fixes = Vec::new();
+    let mut offset = 8;
+    while offset < bytes.len() {
+        let unrecoverable = |reason: &str| RepairError::Unrecoverable {
+            offset,
+            reason: reason.to_string(),
+        };
+        let rest = &bytes[offset..];
+        if rest.len() < 12 {
+            return Err(Box::new(unrecoverable(
+                "fewer than 12 bytes left, too short for any chunk",
+            )));
+        }
+        let type_bytes = [rest[4], rest[5], rest[6], rest[7]];
+        let chunk_type = ChunkType::try_from(type_bytes).map_err(|_| {
+            unrecoverable("the chunk type is not four letters, so this is not a chunk boundary")
+        })?;
+
+        let declared = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        let length = if crc_matches(rest, declared as usize) {
+            declared as usize
+        } else {
+            let actual = measure(rest).ok_or_else(|| {
+                unrecoverable("no CRC matching the chunk's contents was found after its type")
+            })?;
+            fixes.push(LengthFix {
+                offset,
+                chunk_type,
+                declared,
+                actual: actual as u32,
+            });
+            actual
+        };
+
+        repaired.extend_from_slice(&(length as u32).to_be_bytes());
+        repaired.extend_from_slice(&rest[4..12 + length]);
+        offset += 12 + length;
+    }
+    Ok((repaired, fixes))
+}
+
+/// A chunk CRC that was recomputed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CrcFix {
+    /// Where the chunk starts in the file.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for CrcFix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} chunk at byte offset {}: CRC {:#010x} corrected to {:#010x}",
+            self.chunk_type, self.offset, self.stored, self.actual
+        )
+    }
+}
+
+/// Recomputes the CRCs that do not match their chunk's type and data. Chunks whose CRC is
+/// already right are left exactly as they were. Returns the repaired image and the fixes
+/// made; every other chunk was skipped.
+pub fn fix_crcs(bytes: &[u8]) -> Result<(Png, Vec<CrcFix>)> {
+    let options = ParseOptions {
+        allow_bad_crc: true,
+        ..ParseOptions::default()
+    };
+    let png = Png::parse_with_options(bytes, &options)?;
+    let fixes = png
+        .warnings()
+        .iter()
+        .filter_map(|warning| match warning {
+            ParseWarning::BadCrc {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            } => Some(CrcFix {
+                offset: *offset,
+                chunk_type: chunk_type.clone(),
+                stored: *expected,
+                actual: *actual,
+            }),
+            _ => None,
+        })
+        .collect();
+    Ok((png, fixes))
+}
+
+/// What `fix_iend` changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IendFix {
+    /// Chunks that came after the first `IEND` and were moved in front of it.
+    pub moved: Vec<ChunkType>,
+    /// Extra `IEND` chunks that were dropped.
+    pub duplicates_removed: usize,
+    /// Whether there was no `IEND` at all, so one was added.
+    pub added: bool,
+}
+
+impl IendFix {
+    pub fn is_empty(&self) -> bool {
+        *self == IendFix::default()
+    }
+}
+
+/// Makes a single `IEND` the last chunk, by moving any chunks that follow it in front of it,
+/// dropping repeated `IEND` chunks and adding one if it is missing. Chunks otherwise keep
+/// their order.
+pub fn fix_iend(png: Png) -> (Png, IendFix) {
+    let is_iend = |chunk: &Chunk| chunk.chunk_type().bytes() == *b"IEND";
+    let mut fix = IendFix::default();
+    let mut chunks = Vec::new();
+    let mut iend = None;
+    let warnings = png.warnings().to_vec();
+    for chunk in png.into_chunks() {
+        if is_iend(&chunk) {
+            match iend {
+                None => iend = Some(chunk),
+                Some(_) => fix.duplicates_removed += 1,
+            }
+        } else {
+            if iend.is_some() {
+                fix.moved.push(chunk.chunk_type().clone());
+            }
+            chunks.push(chunk);
+        }
+    }
+
+    chunks.push(iend.unwrap_or_else(|| {
+        fix.added = true;
+        let chunk_type = ChunkType::from_str("IEND").expect("IEND is a valid chunk type");
+        Chunk::new(chunk_type, Vec::new())
+    }));
+    (Png::from_chunks(chunks).with_warnings(warnings), fix)
+}
+
+/// Ancillary chunks the spec requires before `PLTE` and `IDAT`.
+const BEFORE_PLTE: [&str; 8] = [
+    "cHRM", "cICP", "cLLI", "gAMA", "iCCP", "mDCV", "sBIT", "sRGB",
+];
+/// Ancillary chunks the spec requires after `PLTE` (if any) and before `IDAT`.
+const BEFORE_IDAT: [&str; 10] = [
+    "acTL", "bKGD", "hIST", "oFFs", "pCAL", "pHYs", "sCAL", "sPLT", "sTER", "tRNS",
+];
+
+/// Reorders the chunks into the canonical order: `IHDR`, the ancillary chunks that must
+/// precede `PLTE`, `PLTE`, those that must precede the image data, the image data, the
+/// remaining ancillary chunks and `IEND`.
+///
+/// Only moves the spec allows are made. The image data keeps its own order, including the
+/// `fcTL` and `fdAT` chunks of an animation, and chunks that may appear anywhere stay on the
+/// same side of `PLTE` and the image data as before. The sort is stable, so chunks in the
+/// same group keep their relative order. Returns the reordered image and how many chunks
+/// were moved, not counting those that only shifted to make room for them.
+pub fn normalize(png: Png) -> (Png, usize) {
+    let warnings = png.warnings().to_vec();
+    let chunks = png.into_chunks();
+    let type_of = |chunk: &Chunk| chunk.chunk_type().to_string();
+    let first = |name: &str| chunks.iter().position(|chunk| type_of(chunk) == name);
+    let (plte, idat) = (first("PLTE"), first("IDAT"));
+
+    let group = |index: usize, chunk: &Chunk| match type_of(chunk).as_str() {
+        "IHDR" => 0,
+        name if BEFORE_PLTE.contains(&name) => 1,
+        "PLTE" => 2,
+        name if BEFORE_IDAT.contains(&name) => 3,
+        "IDAT" | "fcTL" | "fdAT" => 4,
+        "IEND" => 6,
+        _ if idat.is_some_and(|idat| index > idat) => 5,
+        _ if plte.is_some_and(|plte| index < plte) => 1,
+        _ if idat.is_some() => 3,
+        _ => 5,
+    };
+    let mut keyed: Vec<(usize, usize, Chunk)> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| (group(index, &chunk), index, chunk))
+        .collect();
+    keyed.sort_by_key(|(group, index, _)| (*group, *index));
+
+    let moved = moved_count(keyed.iter().map(|(_, index, _)| *index));
+    let chunks = keyed.into_iter().map(|(_, _, chunk)| chunk).collect();
+    (Png::from_chunks(chunks).with_warnings(warnings), moved)
+}
+
+/// The fewest chunks that must be moved to turn the original order into `order`, which
+/// lists the original index of each chunk in its new place: every chunk outside the longest
+/// run that kept its relative order.
+fn moved_count(order: impl Iterator<Item = usize>) -> usize {
+    // tails[k] is the smallest index that ends an increasing run of length k + 1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut total = 0;
+    for index in order {
+        total += 1;
+        let at = tails.partition_point(|&tail| tail < index);
+        if at == tails.len() {
+            tails.push(index);
+        } else {
+            tails[at] = index;
+        }
+    }
+    total - tails.len()
+}
+
+/// Whether `chunk`, read with a data length of `length`, ends with the right CRC.
+fn crc_matches(chunk: &[u8], length: usize) -> bool {
+    let Some(stored) = chunk.get(8 + length..12 + length) else {
+        return false;
+    };
+    let mut crc = Crc32::new();
+    crc.update(&chunk[4..8 + length]);
+    crc.finalize() == u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]])
+}
+
+/// Finds the data length of `chunk` from where its CRC is, ignoring the length field.
+fn measure(chunk: &[u8]) -> Option<usize> {
+    let mut crc = Crc32::new();
+    crc.update(&chunk[4..8]);
+    for length in 0..=chunk.len() - 12 {
+        if length > 0 {
+            crc.update(&chunk[7 + length..8 + length]);
+        }
+        let stored = &chunk[8 + length..12 + length];
+        if crc.finalize() != u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]) {
+            continue;
+        }
+        let next = &chunk[12 + length..];
+        let next_is_chunk =
+            next.len() >= 12 && ChunkType::try_from([next[4], next[5], next[6], next[7]]).is_ok();
+        if next.is_empty() || next_is_chunk {
+            return Some(length);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_fix_iend_moves_trailing_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        // Pushed past IEND, the way a careless tool appending to the file would.
+        let mut chunks = png.into_chunks();
+        chunks.push(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"late".to_vec(),
+        ));
+        png = Png::from_chunks(chunks);
+        assert!(png.validate_structure().is_err());
+
+        let (fixed, fix) = fix_iend(png);
+        assert_eq!(chunk_types(&fixed), ["IHDR", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(fix.moved, [ChunkType::from_str("ruSt").unwrap()]);
+        assert!(fixed.validate_structure().is_ok());
+    }
+
+    #[test]
+    fn test_fix_iend_duplicates_and_missing() {
+        let mut chunks = Png::try_from(&PNG_FILE[..]).unwrap().into_chunks();
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        chunks.insert(1, iend);
+        let (fixed, fix) = fix_iend(Png::from_chunks(chunks));
+        assert_eq!(chunk_types(&fixed), ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(fix.moved.len(), 1);
+        assert_eq!(fix.duplicates_removed, 1);
+
+        let mut chunks = fixed.into_chunks();
+        chunks.pop();
+        let (fixed, fix) = fix_iend(Png::from_chunks(chunks));
+        assert!(fix.added);
+        assert!(fixed.validate_structure().is_ok());
+
+        let (_, fix) = fix_iend(fixed);
+        assert!(fix.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_shuffled_file() {
+        let chunk = |name: &str| Chunk::new(ChunkType::from_str(name).unwrap(), vec![0; 3]);
+        let shuffled = [
+            "IHDR", "tEXt", "PLTE", "gAMA", "IDAT", "pHYs", "IDAT", "tIME", "IEND", "bKGD",
+        ];
+        let png = Png::from_chunks(shuffled.iter().map(|name| chunk(name)).collect());
+        assert!(png.validate_structure().is_err());
+
+        let (normalized, moved) = normalize(png);
+        assert_eq!(
+            chunk_types(&normalized),
+            ["IHDR", "tEXt", "gAMA", "PLTE", "pHYs", "bKGD", "IDAT", "IDAT", "tIME", "IEND"]
+        );
+        // gAMA, pHYs and bKGD moved; the rest only shifted around them.
+        assert_eq!(moved, 3);
+        let reparsed = Png::try_from(normalized.as_bytes().as_slice()).unwrap();
+        assert_eq!(chunk_types(&reparsed), chunk_types(&normalized));
+
+        let (_, moved) = normalize(normalized);
+        assert_eq!(moved, 0);
+    }
+
+    #[test]
+    fn test_normalize_keeps_animation_frames_in_order() {
+        let chunk = |name: &str, id: u8| Chunk::new(ChunkType::from_str(name).unwrap(), vec![id]);
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 0),
+            chunk("fcTL", 1),
+            chunk("IDAT", 2),
+            chunk("acTL", 3),
+            chunk("fcTL", 4),
+            chunk("fdAT", 5),
+            chunk("IEND", 6),
+        ]);
+        let (normalized, _) = normalize(png);
+        let order: Vec<u8> = normalized.chunks().iter().map(|c| c.data()[0]).collect();
+        assert_eq!(order, [0, 3, 1, 2, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_intact_file_is_unchanged() {
+        let (repaired, fixes) = fix_lengths(&PNG_FILE).unwrap();
+        assert!(fixes.is_empty());
+        assert_eq!(repaired, PNG_FILE);
+    }
+
+    #[test]
+    fn test_repairs_corrupted_length() {
+        let mut damaged = PNG_FILE;
+        // The IDAT chunk starts at offset 33 and holds 12 bytes.
+        damaged[33..37].copy_from_slice(&40u32.to_be_bytes());
+        assert!(Png::try_from(&damaged[..]).is_err());
+
+        let (repaired, fixes) = fix_lengths(&damaged).unwrap();
+        assert_eq!(repaired, PNG_FILE);
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].offset, 33);
+        assert_eq!(fixes[0].chunk_type.to_string(), "IDAT");
+        assert_eq!((fixes[0].declared, fixes[0].actual), (40, 12));
+    }
+
+    #[test]
+    fn test_fix_crcs_recomputes_only_bad_ones() {
+        let (png, fixes) = fix_crcs(&PNG_FILE).unwrap();
+        assert!(fixes.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE);
+
+        let mut damaged = PNG_FILE;
+        // The last byte of the IDAT CRC.
+        damaged[56] ^= 0xff;
+        let (png, fixes) = fix_crcs(&damaged).unwrap();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(png.chunks().len() - fixes.len(), 2);
+        assert_eq!(fixes[0].offset, 33);
+        assert_eq!(fixes[0].chunk_type.to_string(), "IDAT");
+        assert_eq!(fixes[0].stored ^ fixes[0].actual, 0xff);
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_reports_unrecoverable_chunk() {
+        let mut damaged = PNG_FILE;
+        damaged[33..37].copy_from_slice(&40u32.to_be_bytes());
+        // Also break the IDAT CRC, so nothing marks where the chunk ends.
+        damaged[53] ^= 0xff;
+
+        let err = fix_lengths(&damaged).unwrap_err();
+        assert!(err.to_string().contains("byte offset 33"));
+    }
+}
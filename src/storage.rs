@@ -0,0 +1,160 @@
+//! Where commands read PNG files from and write them to, so the commands can run against
+//! storage other than the local file system. Every command that works on the files it is
+//! given has a `*_with` form taking a store. `batch`, `scan` and `manifest` do not: they
+//! list and create directories and share the work between threads, so they always use
+//! the local file system.
+
+use std::collections::HashMap;
+use std::fs::{self, FileTimes};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Somewhere PNG files can be read from by path. `FileSystem` is what the command line
+/// uses; embedders can read from other storage, such as an object store or a database,
+/// by implementing this and calling the `*_with` commands.
+pub trait PngSource {
+    /// Reads the whole file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Opens the file at `path` for reading a piece at a time, so a command that only
+    /// needs the start of a file can stop early. By default the whole file is read first.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(io::Cursor::new(self.read(path)?)))
+    }
+}
+
+/// Somewhere PNG files can be written to by path.
+pub trait PngSink {
+    /// Writes `bytes` to `path`, replacing anything already there.
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+
+    /// The access and modification times of the file at `path`, for
+    /// `--preserve-timestamps`. Storage that does not keep times returns `None`.
+    fn times(&self, _path: &Path) -> io::Result<Option<FileTimes>> {
+        Ok(None)
+    }
+
+    /// Sets the times of the file at `path`. Storage that does not keep times ignores it.
+    fn set_times(&self, _path: &Path, _times: FileTimes) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Removes `path` if it is a symbolic link, so the next write creates a regular file in
+    /// its place rather than writing to the link's target, for `--no-follow-symlinks`.
+    /// Storage without links does nothing.
+    fn unlink_symlink(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Storage that can be both read from and written to, as the commands that edit a file in
+/// place need.
+pub trait PngStore: PngSource + PngSink {}
+
+impl<T: PngSource + PngSink> PngStore for T {}
+
+/// The local file system.
+pub struct FileSystem;
+
+impl PngSource for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(io::BufReader::new(fs::File::open(path)?)))
+    }
+}
+
+impl PngSink for FileSystem {
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        fs::write(path, bytes)
+    }
+
+    fn times(&self, path: &Path) -> io::Result<Option<FileTimes>> {
+        let metadata = fs::metadata(path)?;
+        Ok(Some(
+            FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?),
+        ))
+    }
+
+    fn set_times(&self, path: &Path, times: FileTimes) -> io::Result<()> {
+        fs::File::options().write(true).open(path)?.set_times(times)
+    }
+
+    fn unlink_symlink(&self, path: &Path) -> io::Result<()> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => fs::remove_file(path),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Files held in memory, keyed by path, for embedding without a file system and for tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Stores `bytes` at `path`, replacing any file already there.
+    pub fn insert(&self, path: impl Into<PathBuf>, bytes: Vec<u8>) {
+        self.files.lock().unwrap().insert(path.into(), bytes);
+    }
+
+    /// A copy of the file at `path`, if there is one.
+    pub fn get(&self, path: &Path) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path).cloned()
+    }
+}
+
+impl PngSource for MemoryStore {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.get(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no file at '{}' in memory", path.display()),
+            )
+        })
+    }
+}
+
+impl PngSink for MemoryStore {
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        self.insert(path, bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::new();
+        let path = Path::new("images/a.png");
+        assert_eq!(
+            store.read(path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        store.write(path, b"first").unwrap();
+        store.write(path, b"second").unwrap();
+        assert_eq!(store.read(path).unwrap(), b"second");
+
+        let mut opened = Vec::new();
+        store.open(path).unwrap().read_to_end(&mut opened).unwrap();
+        assert_eq!(opened, b"second");
+        assert!(store.times(path).unwrap().is_none());
+    }
+}
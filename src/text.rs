@@ -0,0 +1,283 @@
+use crate::png::ParseOptions;
+use crate::Result;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TextError {
+    BadKeyword(String),
+    MissingSeparator,
+    NotLatin1(char),
+    UnsupportedCompression(u8),
+    CompressionUnavailable,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadKeyword(keyword) => write!(
+                f,
+                "Bad keyword: {:?} (expected 1-79 printable Latin-1 characters)",
+                keyword
+            ),
+            Self::MissingSeparator => write!(f, "Text chunk has no NUL after its keyword"),
+            Self::NotLatin1(c) => write!(f, "Character {:?} cannot be stored as Latin-1", c),
+            Self::UnsupportedCompression(method) => {
+                write!(f, "Unsupported compression method: {}", method)
+            }
+            Self::CompressionUnavailable => {
+                write!(f, "pngme was built without the \"compression\" feature")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// The only compression method defined for `zTXt`: zlib deflate.
+pub const COMPRESSION_DEFLATE: u8 = 0;
+
+/// The most bytes a compressed text or profile chunk may inflate to, the same as the
+/// largest chunk parsing accepts by default.
+pub const MAX_INFLATED_SIZE: usize = ParseOptions::DEFAULT_MAX_CHUNK_SIZE as usize;
+
+/// Checks a text chunk keyword: 1-79 printable Latin-1 characters, without leading,
+/// trailing or doubled spaces.
+pub fn validate_keyword(keyword: &str) -> Result<()> {
+    let printable = keyword
+        .chars()
+        .all(|c| (' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c));
+    let count = keyword.chars().count();
+    if !printable
+        || !(1..=79).contains(&count)
+        || keyword.starts_with(' ')
+        || keyword.ends_with(' ')
+        || keyword.contains("  ")
+    {
+        return Err(Box::new(TextError::BadKeyword(keyword.to_string())));
+    }
+    Ok(())
+}
+
+/// Encodes `text` as Latin-1, failing on characters outside it.
+pub fn to_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).map_err(|_| TextError::NotLatin1(c).into()))
+        .collect()
+}
+
+/// Decodes Latin-1 bytes; every byte maps to the code point of the same value.
+pub fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+/// `text` with control characters written as `\xNN`, so a value read from a chunk can
+/// be shown on a terminal without any escape sequences hidden in it taking effect.
+pub fn escape_control(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            escaped.push_str(&format!("\\x{:02x}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Splits text chunk data at the NUL ending its keyword.
+fn split_keyword(data: &[u8]) -> Result<(String, &[u8])> {
+    let nul = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(TextError::MissingSeparator)?;
+    Ok((from_latin1(&data[..nul]), &data[nul + 1..]))
+}
+
+/// Builds `tEXt` chunk data: the keyword, a NUL and the Latin-1 text.
+pub fn encode_text(keyword: &str, text: &str) -> Result<Vec<u8>> {
+    validate_keyword(keyword)?;
+    let mut data = to_latin1(keyword)?;
+    data.push(0);
+    data.extend(to_latin1(text)?);
+    Ok(data)
+}
+
+/// Parses `tEXt` chunk data into its keyword and text.
+pub fn decode_text(data: &[u8]) -> Result<(String, String)> {
+    let (keyword, text) = split_keyword(data)?;
+    Ok((keyword, from_latin1(text)))
+}
+
+/// Builds `zTXt` chunk data: the keyword, a NUL, the compression method and the
+/// zlib-compressed Latin-1 text.
+pub fn encode_ztxt(keyword: &str, text: &str) -> Result<Vec<u8>> {
+    validate_keyword(keyword)?;
+    let mut data = to_latin1(keyword)?;
+    data.push(0);
+    data.push(COMPRESSION_DEFLATE);
+    data.extend(compress(&to_latin1(text)?)?);
+    Ok(data)
+}
+
+/// Parses `zTXt` chunk data into its keyword and decompressed text.
+pub fn decode_ztxt(data: &[u8]) -> Result<(String, String)> {
+    let (keyword, rest) = split_keyword(data)?;
+    let (method, compressed) = rest.split_first().ok_or(TextError::MissingSeparator)?;
+    if *method != COMPRESSION_DEFLATE {
+        return Err(Box::new(TextError::UnsupportedCompression(*method)));
+    }
+    Ok((
+        keyword,
+        from_latin1(&decompress(compressed, MAX_INFLATED_SIZE)?),
+    ))
+}
+
+/// The profile name at the start of `iCCP` chunk data, read without inflating the profile.
+pub fn iccp_profile_name(data: &[u8]) -> Result<String> {
+    Ok(split_keyword(data)?.0)
+}
+
+/// Parses `iCCP` chunk data into its profile name and the inflated ICC profile. The layout
+/// is the same as `zTXt`, but the profile is binary, so it is returned as bytes.
+pub fn decode_iccp(data: &[u8]) -> Result<(String, Vec<u8>)> {
+    let (name, rest) = split_keyword(data)?;
+    let (method, compressed) = rest.split_first().ok_or(TextError::MissingSeparator)?;
+    if *method != COMPRESSION_DEFLATE {
+        return Err(Box::new(TextError::UnsupportedCompression(*method)));
+    }
+    Ok((name, decompress(compressed, MAX_INFLATED_SIZE)?))
+}
+
+/// Parses `iTXt` chunk data into its keyword and UTF-8 text, inflating the text if the
+/// compression flag is set. The language tag and translated keyword are skipped.
+pub fn decode_itxt(data: &[u8]) -> Result<(String, String)> {
+    let (keyword, rest) = split_keyword(data)?;
+    let [flag, method, rest @ ..] = rest else {
+        return Err(Box::new(TextError::MissingSeparator));
+    };
+    let (_language, rest) = split_keyword(rest)?;
+    let (_translated, text) = split_keyword(rest)?;
+    let text = match (flag, method) {
+        (0, _) => text.to_vec(),
+        (_, &COMPRESSION_DEFLATE) => decompress(text, MAX_INFLATED_SIZE)?,
+        (_, method) => return Err(Box::new(TextError::UnsupportedCompression(*method))),
+    };
+    Ok((keyword, String::from_utf8(text)?))
+}
+
+/// zlib-compresses `data`, or fails if pngme was built without the compression feature.
+#[cfg(feature = "compression")]
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(crate::zlib::compress(data))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn compress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(Box::new(TextError::CompressionUnavailable))
+}
+
+/// Inflates a zlib stream to at most `max_output` bytes, or fails if pngme was built
+/// without the compression feature.
+#[cfg(feature = "compression")]
+pub fn decompress(data: &[u8], max_output: usize) -> Result<Vec<u8>> {
+    crate::zlib::decompress(data, max_output)
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn decompress(_data: &[u8], _max_output: usize) -> Result<Vec<u8>> {
+    Err(Box::new(TextError::CompressionUnavailable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_control() {
+        assert_eq!(escape_control("caf\u{e9} \u{263a}"), "caf\u{e9} \u{263a}");
+        assert_eq!(escape_control("a\x1b[2Jb\n"), "a\\x1b[2Jb\\x0a");
+        assert_eq!(escape_control("\u{9b}"), "\\x9b");
+    }
+
+    #[test]
+    fn test_validate_keyword() {
+        assert!(validate_keyword("Comment").is_ok());
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(&"k".repeat(80)).is_err());
+        assert!(validate_keyword(" Comment").is_err());
+        assert!(validate_keyword("Two  spaces").is_err());
+        assert!(validate_keyword("Tab\there").is_err());
+    }
+
+    #[test]
+    fn test_latin1_round_trip() {
+        let bytes = to_latin1("café").unwrap();
+        assert_eq!(bytes, b"caf\xe9");
+        assert_eq!(from_latin1(&bytes), "café");
+        assert!(to_latin1("🦀").is_err());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let data = encode_text("Title", "déjà vu").unwrap();
+        assert_eq!(data, b"Title\0d\xe9j\xe0 vu");
+        let (keyword, text) = decode_text(&data).unwrap();
+        assert_eq!((keyword.as_str(), text.as_str()), ("Title", "déjà vu"));
+        assert!(decode_text(b"no separator").is_err());
+    }
+
+    #[test]
+    fn test_decode_itxt() {
+        let data = b"Software\0\0\0en\0Logiciel\0Caf\xc3\xa9 Editor";
+        let (keyword, text) = decode_itxt(data).unwrap();
+        assert_eq!(
+            (keyword.as_str(), text.as_str()),
+            ("Software", "Café Editor")
+        );
+        assert!(decode_itxt(b"Software\0\0\0en\0").is_err());
+        assert!(decode_itxt(b"Software\0\x01\x07\0\0text").is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_ztxt_round_trip() {
+        let data = encode_ztxt("Comment", "a secret, compressed").unwrap();
+        assert!(data.starts_with(b"Comment\0\0"));
+
+        let (keyword, text) = decode_ztxt(&data).unwrap();
+        assert_eq!(keyword, "Comment");
+        assert_eq!(text, "a secret, compressed");
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decode_iccp() {
+        let profile: Vec<u8> = (0..=255).cycle().take(600).collect();
+        let mut data = b"sRGB IEC61966-2.1\0\0".to_vec();
+        data.extend(compress(&profile).unwrap());
+
+        assert_eq!(iccp_profile_name(&data).unwrap(), "sRGB IEC61966-2.1");
+        let (name, decoded) = decode_iccp(&data).unwrap();
+        assert_eq!(name, "sRGB IEC61966-2.1");
+        assert_eq!(decoded, profile);
+
+        data[18] = 1;
+        assert!(decode_iccp(&data).is_err());
+        assert!(decode_iccp(b"no separator").is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_ztxt_rejects_unknown_method() {
+        let mut data = encode_ztxt("Comment", "text").unwrap();
+        data[8] = 1;
+        assert!(decode_ztxt(&data).is_err());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn test_ztxt_needs_compression_feature() {
+        assert!(encode_ztxt("Comment", "text").is_err());
+    }
+}
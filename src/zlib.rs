@@ -0,0 +1,592 @@
+//! A small zlib (RFC 1950) / DEFLATE (RFC 1951) implementation, enough for the compressed
+//! PNG text and profile chunks. Compression uses greedy LZ77 matching with the fixed
+//! Huffman codes; decompression handles stored, fixed and dynamic blocks.
+
+use crate::Result;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ZlibError {
+    BadHeader,
+    UnexpectedEnd,
+    BadBlockType,
+    BadStoredLength,
+    BadCode,
+    BadDistance,
+    BadChecksum { expected: u32, actual: u32 },
+    TooLarge { limit: usize },
+}
+
+impl fmt::Display for ZlibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadHeader => write!(f, "Bad zlib header"),
+            Self::UnexpectedEnd => write!(f, "Compressed data ended unexpectedly"),
+            Self::BadBlockType => write!(f, "Bad deflate block type"),
+            Self::BadStoredLength => write!(f, "Bad stored block length"),
+            Self::BadCode => write!(f, "Bad Huffman code in compressed data"),
+            Self::BadDistance => write!(f, "Back-reference distance too far back"),
+            Self::BadChecksum { expected, actual } => write!(
+                f,
+                "Bad Adler-32 checksum: expected {:#010x}, computed {:#010x}",
+                expected, actual
+            ),
+            Self::TooLarge { limit } => write!(
+                f,
+                "Compressed data inflates to more than the limit of {} bytes",
+                limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ZlibError {}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// The Adler-32 checksum that ends every zlib stream.
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Compresses `data` into a zlib stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // CMF: deflate with a 32K window; FLG: default level, check bits so CMF*256+FLG % 31 == 0.
+    writer.out.extend_from_slice(&[0x78, 0x9c]);
+
+    // A single final block using the fixed Huffman codes.
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let hash = |pos: usize| {
+        let value = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+        (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+    };
+    let insert = |pos: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(pos);
+            prev[pos] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, distance) = longest_match(data, pos, &head, &prev, hash);
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for p in pos..pos + length {
+                insert(p, &mut head, &mut prev);
+            }
+            pos += length;
+        } else {
+            write_literal(&mut writer, data[pos] as u16);
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    write_literal(&mut writer, 256);
+
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn longest_match(
+    data: &[u8],
+    pos: usize,
+    head: &[usize],
+    prev: &[usize],
+    hash: impl Fn(usize) -> usize,
+) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max_length = MAX_MATCH.min(data.len() - pos);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(pos)];
+    let mut chain = 0;
+    while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[pos..pos + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = pos - candidate;
+            if length == max_length {
+                break;
+            }
+        }
+        candidate = prev[candidate];
+        chain += 1;
+    }
+    (best_length, best_distance)
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let (code, bits) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.write_code(code, bits);
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|base| *base as usize <= length)
+        .expect("match length is at least 3");
+    write_literal(writer, 257 + index as u16);
+    writer.write_bits(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index],
+    );
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DIST_BASE
+        .iter()
+        .rposition(|base| *base as usize <= distance)
+        .expect("match distance is at least 1");
+    writer.write_code(index as u16, 5);
+    writer.write_bits(
+        (distance - DIST_BASE[index] as usize) as u32,
+        DIST_EXTRA[index],
+    );
+}
+
+/// Decompresses a zlib stream, checking its header and Adler-32 checksum. Fails with
+/// `TooLarge` as soon as the output would grow past `max_output` bytes, so a small stream
+/// cannot inflate into gigabytes.
+pub fn decompress(data: &[u8], max_output: usize) -> Result<Vec<u8>> {
+    if data.len() < 2 {
+        return Err(Box::new(ZlibError::BadHeader));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    let has_dictionary = flg & 0x20 != 0;
+    if cmf & 0x0f != 8
+        || cmf >> 4 > 7
+        || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31)
+        || has_dictionary
+    {
+        return Err(Box::new(ZlibError::BadHeader));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let out = inflate(&mut reader, max_output)?;
+
+    let rest = reader.remaining_bytes();
+    if rest.len() < 4 {
+        return Err(Box::new(ZlibError::UnexpectedEnd));
+    }
+    let expected = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+    let actual = adler32(&out);
+    if expected != actual {
+        return Err(Box::new(ZlibError::BadChecksum { expected, actual }));
+    }
+    Ok(out)
+}
+
+fn inflate(reader: &mut BitReader, max_output: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, &mut out, max_output)?,
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_block(reader, &mut out, max_output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(reader)?;
+                inflate_block(reader, &mut out, max_output, &literals, &distances)?;
+            }
+            _ => return Err(Box::new(ZlibError::BadBlockType)),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Fails with `TooLarge` if `count` more bytes would take `out` past `max_output`.
+fn check_room(out: &[u8], count: usize, max_output: usize) -> Result<()> {
+    if out.len().saturating_add(count) > max_output {
+        return Err(Box::new(ZlibError::TooLarge { limit: max_output }));
+    }
+    Ok(())
+}
+
+fn inflate_stored(reader: &mut BitReader, out: &mut Vec<u8>, max_output: usize) -> Result<()> {
+    reader.align_to_byte();
+    let len = reader.bits(16)? as u16;
+    let nlen = reader.bits(16)? as u16;
+    if len != !nlen {
+        return Err(Box::new(ZlibError::BadStoredLength));
+    }
+    check_room(out, len as usize, max_output)?;
+    for _ in 0..len {
+        out.push(reader.bits(8)? as u8);
+    }
+    Ok(())
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    max_output: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                check_room(out, 1, max_output)?;
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+                let dist_symbol = distances.decode(reader)? as usize;
+                if dist_symbol >= DIST_BASE.len() {
+                    return Err(Box::new(ZlibError::BadCode));
+                }
+                let distance = DIST_BASE[dist_symbol] as usize
+                    + reader.bits(DIST_EXTRA[dist_symbol])? as usize;
+                if distance > out.len() {
+                    return Err(Box::new(ZlibError::BadDistance));
+                }
+                check_room(out, length, max_output)?;
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(Box::new(ZlibError::BadCode)),
+        }
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_table.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(ZlibError::BadCode)?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err(Box::new(ZlibError::BadCode)),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(Box::new(ZlibError::BadCode));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+/// A canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    /// How many codes there are of each bit length.
+    counts: [u16; 16],
+    /// Symbols ordered by code length, then by symbol value.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Box::new(ZlibError::BadCode))
+    }
+}
+
+/// Reads bits least-significant first, as DEFLATE packs them.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = *self.bytes.get(self.pos).ok_or(ZlibError::UnexpectedEnd)?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u64 << count) - 1) as u32;
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buf >>= self.bit_count % 8;
+        self.bit_count -= self.bit_count % 8;
+    }
+
+    /// The bytes after the last fully or partly consumed one.
+    fn remaining_bytes(&self) -> &'a [u8] {
+        let buffered = (self.bit_count / 8) as usize;
+        &self.bytes[self.pos - buffered..]
+    }
+}
+
+/// Writes bits least-significant first, as DEFLATE packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u32,
+    bit_count: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out: Vec::new(),
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8) {
+        self.bit_buf |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn write_code(&mut self, code: u16, bits: u8) {
+        let reversed = (code.reverse_bits() >> (16 - bits)) as u32;
+        self.write_bits(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.out.push(self.bit_buf as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"This is where your secret message will be!".to_vec(),
+            b"abcabcabcabcabcabcabcabcabcabcabcabcabc".repeat(100),
+            (0..=255u8).cycle().take(70_000).collect(),
+        ];
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, usize::MAX).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_compresses_repetitive_data() {
+        let input = b"pngme ".repeat(1000);
+        assert!(compress(&input).len() < input.len() / 10);
+    }
+
+    #[test]
+    fn test_decompress_stored_block() {
+        // zlib.compress(b"hello", 0) from Python.
+        let data = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_decompress_dynamic_block() {
+        // zlib.compress(SENTENCE * 3, 9) from Python, which picks a dynamic Huffman block.
+        let expected = b"PNG chunks carry a length, a type, data and a CRC. Ancillary chunks \
+            may be ignored by decoders; critical ones may not. "
+            .repeat(3);
+        assert_eq!(DYNAMIC_SAMPLE[2] >> 1 & 3, 2);
+        assert_eq!(decompress(&DYNAMIC_SAMPLE, usize::MAX).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_decompress_image_data() {
+        // The IDAT payload of the 1x1 test image.
+        let data = [120, 156, 99, 248, 207, 192, 0, 0, 3, 1, 1, 0];
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), [0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_decompress_rejects_bad_checksum() {
+        let mut data = compress(b"hello");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(decompress(&data, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_decompress_stops_at_max_output() {
+        let input = vec![0u8; 1 << 20];
+        let compressed = compress(&input);
+        assert!(compressed.len() < 16 * 1024);
+        let err = decompress(&compressed, 1000).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ZlibError::TooLarge { limit: 1000 })
+        ));
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+
+        // zlib.compress(b"hello", 0) from Python.
+        let stored = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+        assert!(decompress(&stored, 4).is_err());
+        assert_eq!(decompress(&stored, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_decompress_rejects_truncated() {
+        let data = compress(b"hello hello hello");
+        assert!(decompress(&data[..data.len() - 6], usize::MAX).is_err());
+        assert!(decompress(&[0x78], usize::MAX).is_err());
+    }
+
+    const DYNAMIC_SAMPLE: [u8; 111] = [
+        0x78, 0xda, 0xe5, 0x8d, 0xc1, 0x0d, 0x83, 0x40, 0x0c, 0x04, 0x5b, 0xd9, 0x02, 0x10, 0x0d,
+        0xe4, 0x85, 0x78, 0xe4, 0x87, 0x10, 0x1d, 0x18, 0xdb, 0x82, 0x53, 0x0e, 0x5f, 0x64, 0x9c,
+        0xc7, 0x75, 0x9f, 0x93, 0xa0, 0x0b, 0x7e, 0xa3, 0xd5, 0xac, 0x66, 0x9e, 0xde, 0xe0, 0xfd,
+        0x67, 0x9f, 0x13, 0x4c, 0xee, 0x15, 0x84, 0xac, 0xb6, 0xc5, 0xde, 0x35, 0x8a, 0xfa, 0xd5,
+        0x0e, 0x42, 0x41, 0x20, 0x93, 0x36, 0x8c, 0xcb, 0xd8, 0x63, 0x30, 0x4e, 0x39, 0x53, 0x73,
+        0xef, 0xe3, 0x41, 0x15, 0xab, 0x22, 0x6d, 0x56, 0x5c, 0x05, 0x6b, 0x85, 0x28, 0x17, 0x51,
+        0x3f, 0x5f, 0x60, 0x4f, 0x91, 0x98, 0x32, 0x8a, 0xe9, 0x65, 0x5a, 0x89, 0x1e, 0xf3, 0xa3,
+        0xb2, 0x7f, 0x07, 0xf3, 0x7b, 0x7f,
+    ];
+}
@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.png")
+}
+
+/// A 5x5 grayscale image using Adam7 interlacing.
+fn interlaced_fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/interlaced.png")
+}
+
+fn pngme(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .output()
+        .expect("failed to run pngme")
+}
+
+#[test]
+fn has_exits_zero_when_present() {
+    let output = pngme(&["has", fixture().to_str().unwrap(), "IDAT"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn has_exits_nonzero_when_absent() {
+    let output = pngme(&["has", fixture().to_str().unwrap(), "ruSt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn has_verbose_prints_result() {
+    let output = pngme(&["has", fixture().to_str().unwrap(), "ruSt", "--verbose"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("no ruSt chunk"));
+}
+
+#[test]
+fn info_describes_adam7_passes() {
+    let output = pngme(&["info", interlaced_fixture().to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Interlace: Adam7"));
+    assert!(stdout.contains("seven passes"));
+    assert!(stdout.contains("Pass 1: 1x1"));
+    assert!(stdout.contains("Pass 7: 5x2"));
+}
+
+/// A copy of the tiny fixture under the system temp directory, changed by `edit`.
+fn edited_fixture(name: &str, edit: impl FnOnce(&mut Vec<u8>)) -> PathBuf {
+    let mut bytes = std::fs::read(fixture()).unwrap();
+    edit(&mut bytes);
+    let path = std::env::temp_dir().join(format!("pngme-cli-{}-{}.png", name, std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn exit_codes_tell_failures_apart() {
+    let fixture = fixture();
+    let fixture = fixture.to_str().unwrap();
+    assert_eq!(pngme(&["decode", fixture, "ruSt"]).status.code(), Some(5));
+    assert_eq!(
+        pngme(&["decode", fixture, "--no-such-flag"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(pngme(&["print", "no/such/file.png"]).status.code(), Some(3));
+
+    let not_png = edited_fixture("not-png", |bytes| bytes[0] = b'X');
+    assert_eq!(
+        pngme(&["decode", not_png.to_str().unwrap(), "ruSt"])
+            .status
+            .code(),
+        Some(4)
+    );
+    std::fs::remove_file(not_png).unwrap();
+
+    // Flip a bit in the IEND CRC, the last byte of the file.
+    let bad_crc = edited_fixture("bad-crc", |bytes| *bytes.last_mut().unwrap() ^= 1);
+    assert_eq!(
+        pngme(&["print", bad_crc.to_str().unwrap()]).status.code(),
+        Some(6)
+    );
+    assert_eq!(
+        pngme(&["verify", bad_crc.to_str().unwrap()]).status.code(),
+        Some(6)
+    );
+    std::fs::remove_file(bad_crc).unwrap();
+
+    // Drop IHDR: a structural problem, not just a bad CRC.
+    let no_ihdr = edited_fixture("no-ihdr", |bytes| {
+        bytes.drain(8..33);
+    });
+    assert_eq!(
+        pngme(&["verify", no_ihdr.to_str().unwrap()]).status.code(),
+        Some(4)
+    );
+    std::fs::remove_file(no_ihdr).unwrap();
+}
+
+#[test]
+fn encode_without_output_flag_leaves_file_untouched() {
+    let copy = edited_fixture("no-output", |_| {});
+    let before = std::fs::read(&copy).unwrap();
+
+    let output = pngme(&["encode", copy.to_str().unwrap(), "ruSt", "hi"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--in-place"));
+    assert_eq!(std::fs::read(&copy).unwrap(), before);
+
+    for args in [
+        &["batch", "encode", "ruSt", "hi", copy.to_str().unwrap()][..],
+        &["fix-crcs", copy.to_str().unwrap()][..],
+        &["normalize", copy.to_str().unwrap()][..],
+    ] {
+        assert_eq!(pngme(args).status.code(), Some(2));
+        assert_eq!(std::fs::read(&copy).unwrap(), before);
+    }
+
+    let output = pngme(&["encode", copy.to_str().unwrap(), "ruSt", "hi", "--in-place"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_ne!(std::fs::read(&copy).unwrap(), before);
+    std::fs::remove_file(copy).unwrap();
+}
+
+#[test]
+fn decode_quiet_not_found_exits_zero() {
+    let fixture = fixture();
+    let output = pngme(&[
+        "decode",
+        fixture.to_str().unwrap(),
+        "ruSt",
+        "--quiet-not-found",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn binary_payload_round_trips_exactly() {
+    let payload: Vec<u8> = (0..=255).collect();
+    let payload_path =
+        std::env::temp_dir().join(format!("pngme-cli-payload-{}.bin", std::process::id()));
+    std::fs::write(&payload_path, &payload).unwrap();
+    let image = edited_fixture("binary-payload", |_| {});
+    let image_arg = image.to_str().unwrap();
+
+    let encoded = pngme(&[
+        "encode",
+        image_arg,
+        "ruSt",
+        "--message-file",
+        payload_path.to_str().unwrap(),
+        "--in-place",
+    ]);
+    assert_eq!(encoded.status.code(), Some(0));
+
+    let decoded = pngme(&["decode", image_arg, "ruSt", "--output-format", "raw"]);
+    assert_eq!(decoded.status.code(), Some(0));
+    assert_eq!(decoded.stdout, payload);
+
+    std::fs::remove_file(payload_path).unwrap();
+    std::fs::remove_file(image).unwrap();
+}